@@ -0,0 +1,61 @@
+//! Compares reading the latest revision with reading old, recompressed revisions.
+//!
+//! Needs `DATABASE_URL`; everything runs in a transaction which is never committed.
+use criterion::{criterion_group, criterion_main, Criterion};
+use diesel::prelude::*;
+use ipnetwork::IpNetwork;
+use std::str::FromStr;
+use wikist::models::{Actor, Article, Content, Namespace};
+use wikist::storage::Compression;
+
+const REVISIONS: usize = 50;
+
+fn bench_content_read(c: &mut Criterion) {
+    dotenv::dotenv().ok();
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let conn = PgConnection::establish(&database_url).expect("must connect");
+    conn.begin_test_transaction().expect("must succeed");
+
+    let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+    let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+    let paragraph = "Lorem ipsum dolor sit amet, consectetur adipiscing elit.\n".repeat(20);
+    let mut wikitext = format!("==Benchmark==\n{}", paragraph);
+    let mut article = Article::create(
+        &conn,
+        &Namespace::get_default(&conn).expect("must succeed"),
+        "content read benchmark",
+        &wikitext,
+        "",
+        &actor,
+        false,
+    )
+    .expect("must succeed");
+    for i in 0..REVISIONS {
+        wikitext = format!("{}\n==Section {}==\n{}", wikitext, i, paragraph);
+        article
+            .edit(&conn, &wikitext, "", &actor, false)
+            .expect("must succeed");
+    }
+    let revisions = article.get_all_revisions(&conn).expect("must succeed");
+    let latest = article.get_latest_revision(&conn).expect("must succeed");
+    let old = &revisions[REVISIONS / 2];
+
+    c.bench_function("latest revision, before recompression", |b| {
+        b.iter(|| latest.get_wikitext(&conn).expect("must succeed"))
+    });
+    c.bench_function("old revision, before recompression", |b| {
+        b.iter(|| old.get_wikitext(&conn).expect("must succeed"))
+    });
+
+    while Content::recompress_batch(&conn, Compression::Zstd, 20, 100).expect("must succeed") > 0 {}
+
+    c.bench_function("latest revision, after recompression", |b| {
+        b.iter(|| latest.get_wikitext(&conn).expect("must succeed"))
+    });
+    c.bench_function("old revision, after recompression", |b| {
+        b.iter(|| old.get_wikitext(&conn).expect("must succeed"))
+    });
+}
+
+criterion_group!(benches, bench_content_read);
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+-- This file should undo anything in `up.sql`
+UPDATE revisions
+SET comment = format(
+        '(Rename: %s -> %s) %s',
+        payload->>'oldTitle',
+        payload->>'newTitle',
+        comment
+    )
+WHERE kind = 'rename';
+UPDATE revisions
+SET comment = format('(Delete: %s) %s', payload->>'oldTitle', comment)
+WHERE kind = 'delete';
+UPDATE revisions
+SET comment = format(
+        '(Fork: %s -> %s) %s',
+        payload->>'forkSource',
+        payload->>'newTitle',
+        comment
+    )
+WHERE kind = 'fork';
+UPDATE revisions
+SET comment = format(
+        '(Add redirection: <- %s) %s',
+        payload->>'redirectionSource',
+        comment
+    )
+WHERE kind = 'add_redirection';
+DROP INDEX revisions_kind_idx;
+ALTER TABLE revisions DROP COLUMN payload,
+    DROP COLUMN kind;
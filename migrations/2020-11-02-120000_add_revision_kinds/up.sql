@@ -0,0 +1,59 @@
+ALTER TABLE revisions
+ADD COLUMN kind VARCHAR(20) NOT NULL DEFAULT 'edit',
+    ADD COLUMN payload JSONB NULL;
+-- Existing rows encode the kind as a prefix of the comment, e.g. "(Rename: a -> b) comment"
+UPDATE revisions
+SET kind = 'rename',
+    payload = jsonb_build_object('oldTitle', parsed.m [1], 'newTitle', parsed.m [2]),
+    comment = parsed.m [3]
+FROM (
+        SELECT id,
+            regexp_match(comment, '^\(Rename: (.*?) -> (.*?)\) (.*)$') AS m
+        FROM revisions
+    ) AS parsed
+WHERE revisions.id = parsed.id
+    AND parsed.m IS NOT NULL;
+UPDATE revisions
+SET kind = 'delete',
+    payload = jsonb_build_object('oldTitle', parsed.m [1]),
+    comment = parsed.m [2]
+FROM (
+        SELECT id,
+            regexp_match(comment, '^\(Delete: (.*?)\) (.*)$') AS m
+        FROM revisions
+    ) AS parsed
+WHERE revisions.id = parsed.id
+    AND parsed.m IS NOT NULL;
+UPDATE revisions
+SET kind = 'fork',
+    payload = jsonb_build_object('forkSource', parsed.m [1], 'newTitle', parsed.m [2]),
+    comment = parsed.m [3]
+FROM (
+        SELECT id,
+            regexp_match(comment, '^\(Fork: (.*?) -> (.*?)\) (.*)$') AS m
+        FROM revisions
+    ) AS parsed
+WHERE revisions.id = parsed.id
+    AND parsed.m IS NOT NULL;
+UPDATE revisions
+SET kind = 'add_redirection',
+    payload = jsonb_build_object('redirectionSource', parsed.m [1]),
+    comment = parsed.m [2]
+FROM (
+        SELECT id,
+            regexp_match(comment, '^\(Add redirection: <- (.*?)\) (.*)$') AS m
+        FROM revisions
+    ) AS parsed
+WHERE revisions.id = parsed.id
+    AND parsed.m IS NOT NULL;
+UPDATE revisions
+SET kind = 'create'
+WHERE kind = 'edit'
+    AND id IN (
+        SELECT MIN(id)
+        FROM revisions
+        GROUP BY article_id
+    );
+ALTER TABLE revisions
+ALTER COLUMN kind DROP DEFAULT;
+CREATE INDEX revisions_kind_idx ON revisions (kind);
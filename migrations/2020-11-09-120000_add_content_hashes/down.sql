@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+-- Deduplicated contents are not split again; revisions keep sharing them.
+DROP INDEX contents_sha256_idx;
+ALTER TABLE contents DROP COLUMN byte_length,
+    DROP COLUMN sha256;
@@ -0,0 +1,28 @@
+ALTER TABLE contents
+ADD COLUMN sha256 VARCHAR(64) NULL,
+    ADD COLUMN byte_length INTEGER NULL;
+UPDATE contents
+SET sha256 = encode(sha256(convert_to(wikitext, 'UTF8')), 'hex'),
+    byte_length = octet_length(wikitext);
+ALTER TABLE contents
+ALTER COLUMN sha256
+SET NOT NULL,
+    ALTER COLUMN byte_length
+SET NOT NULL;
+CREATE INDEX contents_sha256_idx ON contents (sha256);
+-- Point revisions with identical contents to a single row and drop the duplicates
+UPDATE revisions
+SET content_id = duplicates.keep_id
+FROM (
+        SELECT id,
+            MIN(id) OVER (PARTITION BY sha256, byte_length) AS keep_id
+        FROM contents
+    ) AS duplicates
+WHERE revisions.content_id = duplicates.id
+    AND duplicates.id <> duplicates.keep_id;
+DELETE FROM contents
+WHERE NOT EXISTS (
+        SELECT 1
+        FROM revisions
+        WHERE revisions.content_id = contents.id
+    );
@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+-- Contents stored in `data` cannot be decoded in SQL; run the recompression job with
+-- CONTENT_COMPRESSION=none first, which stores every content as plain wikitext again.
+DO $$ BEGIN IF EXISTS (
+    SELECT 1
+    FROM contents
+    WHERE wikitext IS NULL
+) THEN RAISE EXCEPTION 'Some contents are still compressed; run the recompression job with CONTENT_COMPRESSION=none first';
+END IF;
+END $$;
+DROP INDEX contents_base_id_idx;
+ALTER TABLE contents DROP CONSTRAINT contents_wikitext_or_data,
+    DROP COLUMN base_id,
+    DROP COLUMN data,
+    DROP COLUMN compression,
+    ALTER COLUMN wikitext
+SET NOT NULL;
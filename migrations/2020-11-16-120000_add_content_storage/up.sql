@@ -0,0 +1,10 @@
+ALTER TABLE contents
+ALTER COLUMN wikitext DROP NOT NULL,
+    ADD COLUMN compression VARCHAR(10) NOT NULL DEFAULT 'none',
+    ADD COLUMN data BYTEA NULL,
+    ADD COLUMN base_id INTEGER NULL REFERENCES contents,
+    ADD CONSTRAINT contents_wikitext_or_data CHECK (
+        wikitext IS NOT NULL
+        OR data IS NOT NULL
+    );
+CREATE INDEX contents_base_id_idx ON contents (base_id);
@@ -0,0 +1,6 @@
+-- Former names of renamed namespaces, so existing links keep working
+CREATE TABLE namespace_aliases (
+    name VARCHAR(30) PRIMARY KEY,
+    namespace_id INTEGER NOT NULL REFERENCES namespaces
+);
+CREATE INDEX namespace_aliases_namespace_id_idx ON namespace_aliases (namespace_id);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX namespace_aliases_lower_name_idx;
+DROP INDEX namespaces_lower_name_idx;
+ALTER TABLE namespace_aliases DROP COLUMN language;
+ALTER TABLE namespaces DROP COLUMN capitalize_first_letter;
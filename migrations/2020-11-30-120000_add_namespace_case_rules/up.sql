@@ -0,0 +1,32 @@
+ALTER TABLE namespaces
+ADD COLUMN capitalize_first_letter BOOLEAN NOT NULL DEFAULT FALSE;
+-- Aliases with a language are localized names
+ALTER TABLE namespace_aliases
+ADD COLUMN language VARCHAR(20) NULL;
+-- Namespaces differing only in case hold articles, so they cannot be merged here; rename one
+-- of them before running this migration.
+DO $$
+DECLARE clashing TEXT;
+BEGIN
+SELECT string_agg(name, ', ') INTO clashing
+FROM namespaces
+WHERE lower(name) IN (
+        SELECT lower(name)
+        FROM namespaces
+        GROUP BY lower(name)
+        HAVING COUNT(*) > 1
+    );
+IF clashing IS NOT NULL THEN RAISE EXCEPTION 'Namespaces differ only in case: %; rename them first',
+clashing;
+END IF;
+END $$;
+-- Names are looked up ignoring case from now on and namespaces take precedence over aliases,
+-- so an alias clashing with a namespace would never resolve. Of aliases differing only in case
+-- one is kept.
+DELETE FROM namespace_aliases a USING namespaces n
+WHERE lower(a.name) = lower(n.name);
+DELETE FROM namespace_aliases a USING namespace_aliases b
+WHERE lower(a.name) = lower(b.name)
+    AND a.name > b.name;
+CREATE UNIQUE INDEX namespaces_lower_name_idx ON namespaces (lower(name));
+CREATE UNIQUE INDEX namespace_aliases_lower_name_idx ON namespace_aliases (lower(name));
@@ -0,0 +1,21 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM namespace_permissions
+WHERE namespace_id IN (
+        SELECT id
+        FROM namespaces
+        WHERE subject_namespace_id IS NOT NULL
+    );
+DELETE FROM namespace_aliases
+WHERE namespace_id IN (
+        SELECT id
+        FROM namespaces
+        WHERE subject_namespace_id IS NOT NULL
+    );
+DELETE FROM namespaces
+WHERE subject_namespace_id IS NOT NULL;
+DROP INDEX namespaces_subject_namespace_id_idx;
+ALTER TABLE namespaces DROP COLUMN subject_namespace_id;
+ALTER TABLE namespace_aliases
+ALTER COLUMN name TYPE VARCHAR(30);
+ALTER TABLE namespaces
+ALTER COLUMN name TYPE VARCHAR(30);
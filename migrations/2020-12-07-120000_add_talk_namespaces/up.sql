@@ -0,0 +1,42 @@
+-- Talk namespaces are named after their subject namespace, and their former names are kept
+-- as aliases when they are renamed
+ALTER TABLE namespaces
+ALTER COLUMN name TYPE VARCHAR(40);
+ALTER TABLE namespace_aliases
+ALTER COLUMN name TYPE VARCHAR(40);
+ALTER TABLE namespaces
+ADD COLUMN subject_namespace_id INTEGER NULL REFERENCES namespaces;
+CREATE UNIQUE INDEX namespaces_subject_namespace_id_idx ON namespaces (subject_namespace_id);
+INSERT INTO namespaces (name, capitalize_first_letter, subject_namespace_id)
+SELECT CASE
+        WHEN name = '_DEFAULT' THEN 'Talk'
+        ELSE name || ' talk'
+    END,
+    capitalize_first_letter,
+    id
+FROM namespaces;
+-- LoggedIn may always create, read and edit talk pages
+INSERT INTO namespace_permissions (
+        namespace_id,
+        role_id,
+        can_create,
+        can_read,
+        can_edit,
+        can_rename,
+        can_delete,
+        can_grant
+    )
+SELECT talk.id,
+    p.role_id,
+    p.can_create
+    OR r.name = 'LoggedIn',
+    p.can_read
+    OR r.name = 'LoggedIn',
+    p.can_edit
+    OR r.name = 'LoggedIn',
+    p.can_rename,
+    p.can_delete,
+    p.can_grant
+FROM namespace_permissions p
+    JOIN roles r ON r.id = p.role_id
+    JOIN namespaces talk ON talk.subject_namespace_id = p.namespace_id;
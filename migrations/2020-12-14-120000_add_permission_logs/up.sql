@@ -0,0 +1,13 @@
+CREATE TABLE permission_logs (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    actor_id INTEGER NOT NULL REFERENCES actors,
+    role_id INTEGER NOT NULL REFERENCES roles,
+    -- Logs outlive deleted namespaces
+    namespace_id INTEGER NULL REFERENCES namespaces ON DELETE SET NULL,
+    article_id INTEGER NULL REFERENCES articles,
+    old_value JSONB NULL,
+    new_value JSONB NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX permission_logs_namespace_id_idx ON permission_logs (namespace_id);
+CREATE INDEX permission_logs_article_id_idx ON permission_logs (article_id);
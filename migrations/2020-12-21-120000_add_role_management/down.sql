@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permission_logs
+WHERE role_id IS NULL;
+ALTER TABLE permission_logs DROP CONSTRAINT permission_logs_role_id_fkey;
+ALTER TABLE permission_logs
+ADD CONSTRAINT permission_logs_role_id_fkey FOREIGN KEY (role_id) REFERENCES roles;
+ALTER TABLE permission_logs
+ALTER COLUMN role_id SET NOT NULL;
+DROP TABLE role_rights;
+ALTER TABLE user_roles DROP COLUMN expires_at;
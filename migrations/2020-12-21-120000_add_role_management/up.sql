@@ -0,0 +1,13 @@
+ALTER TABLE user_roles
+ADD COLUMN expires_at TIMESTAMP NULL;
+CREATE TABLE role_rights (
+    role_id INTEGER NOT NULL REFERENCES roles,
+    name VARCHAR(50) NOT NULL,
+    CONSTRAINT role_rights_pkey PRIMARY KEY (role_id, name)
+);
+-- Keep the audit log of deleted roles
+ALTER TABLE permission_logs
+ALTER COLUMN role_id DROP NOT NULL;
+ALTER TABLE permission_logs DROP CONSTRAINT permission_logs_role_id_fkey;
+ALTER TABLE permission_logs
+ADD CONSTRAINT permission_logs_role_id_fkey FOREIGN KEY (role_id) REFERENCES roles ON DELETE SET NULL;
@@ -0,0 +1,23 @@
+CREATE TABLE template_links (
+    article_id INTEGER NOT NULL REFERENCES articles ON DELETE CASCADE,
+    namespace_id INTEGER NOT NULL REFERENCES namespaces ON DELETE CASCADE,
+    title VARCHAR(300) NOT NULL,
+    CONSTRAINT template_links_pkey PRIMARY KEY (article_id, namespace_id, title)
+);
+CREATE INDEX template_links_namespace_id_title_idx ON template_links (namespace_id, title);
+CREATE TABLE protections (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    -- Protections are keyed by title to protect titles which do not exist yet
+    namespace_id INTEGER NOT NULL REFERENCES namespaces ON DELETE CASCADE,
+    title VARCHAR(300) NOT NULL,
+    action VARCHAR(20) NOT NULL,
+    allowed_role_id INTEGER NOT NULL REFERENCES roles,
+    reason TEXT NOT NULL,
+    actor_id INTEGER NOT NULL REFERENCES actors,
+    cascading BOOLEAN NOT NULL DEFAULT FALSE,
+    source_id INTEGER NULL REFERENCES protections ON DELETE CASCADE,
+    expires_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX protections_namespace_id_title_idx ON protections (namespace_id, title);
+CREATE INDEX protections_source_id_idx ON protections (source_id);
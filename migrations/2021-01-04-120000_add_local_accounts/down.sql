@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE login_attempts;
+DROP TABLE user_tokens;
+ALTER TABLE users DROP COLUMN email_verified_at;
+DELETE FROM authentications
+WHERE provider = 'local';
+ALTER TABLE authentications DROP COLUMN password_hash;
@@ -0,0 +1,27 @@
+ALTER TABLE authentications
+ADD COLUMN password_hash VARCHAR(255) NULL;
+ALTER TABLE users
+ADD COLUMN email_verified_at TIMESTAMP NULL;
+-- Addresses of existing users were verified by their provider
+UPDATE users
+SET email_verified_at = created_at;
+CREATE TABLE user_tokens (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    user_id INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+    purpose VARCHAR(30) NOT NULL,
+    -- Only the SHA-256 hash of a token is stored
+    token_hash VARCHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX user_tokens_user_id_idx ON user_tokens (user_id);
+CREATE TABLE login_attempts (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    username VARCHAR(255) NOT NULL,
+    ip_address CIDR NOT NULL,
+    succeeded BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX login_attempts_username_created_at_idx ON login_attempts (username, created_at);
+CREATE INDEX login_attempts_ip_address_created_at_idx ON login_attempts (ip_address, created_at);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE oauth_states;
+ALTER TABLE authentications DROP CONSTRAINT authentications_provider_provider_user_id_key;
@@ -0,0 +1,9 @@
+CREATE TABLE oauth_states (
+    state VARCHAR(64) PRIMARY KEY,
+    provider VARCHAR(255) NOT NULL,
+    code_verifier VARCHAR(128) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+-- An account of a provider logs in as a single user
+ALTER TABLE authentications
+ADD CONSTRAINT authentications_provider_provider_user_id_key UNIQUE (provider, provider_user_id);
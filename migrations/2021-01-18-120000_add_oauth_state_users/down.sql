@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE oauth_states DROP COLUMN user_id;
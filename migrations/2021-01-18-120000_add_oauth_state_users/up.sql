@@ -0,0 +1,3 @@
+-- Authorizations started by a signed in user link the provider to them
+ALTER TABLE oauth_states
+ADD COLUMN user_id INTEGER NULL REFERENCES users ON DELETE CASCADE;
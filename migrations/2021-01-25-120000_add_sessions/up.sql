@@ -0,0 +1,16 @@
+-- Refresh tokens now name their session, so every refresh token and access token issued before
+-- this migration is rejected and all users have to log in again.
+CREATE TABLE sessions (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    user_id INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+    -- The `jti` of the only refresh token of the session which may still be used. Tokens are
+    -- rotated on every refresh, so presenting an older one means it was leaked.
+    token_id VARCHAR(64) UNIQUE NOT NULL,
+    user_agent TEXT NULL,
+    ip_address CIDR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP NULL
+);
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
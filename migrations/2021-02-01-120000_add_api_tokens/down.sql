@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE revisions DROP COLUMN is_bot;
+DROP TABLE api_tokens;
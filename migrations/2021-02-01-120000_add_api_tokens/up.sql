@@ -0,0 +1,15 @@
+CREATE TABLE api_tokens (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    user_id INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    -- Only the SHA-256 hash of a token is stored
+    token_hash VARCHAR(64) UNIQUE NOT NULL,
+    scopes VARCHAR(20)[] NOT NULL,
+    expires_at TIMESTAMP NULL,
+    last_used_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (user_id, name)
+);
+-- Made with an API token
+ALTER TABLE revisions
+ADD COLUMN is_bot BOOLEAN NOT NULL DEFAULT FALSE;
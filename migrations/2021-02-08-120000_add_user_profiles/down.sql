@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users
+DROP COLUMN preferences,
+DROP COLUMN timezone,
+DROP COLUMN language,
+DROP COLUMN bio,
+DROP COLUMN display_name;
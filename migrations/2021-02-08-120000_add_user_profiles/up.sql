@@ -0,0 +1,8 @@
+ALTER TABLE users
+ADD COLUMN display_name VARCHAR(100) NULL,
+ADD COLUMN bio TEXT NOT NULL DEFAULT '',
+-- A BCP 47 language tag
+ADD COLUMN language VARCHAR(35) NULL,
+-- An IANA time zone name
+ADD COLUMN timezone VARCHAR(64) NULL,
+ADD COLUMN preferences JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,16 @@
+CREATE TABLE blocks (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    user_id INTEGER NULL REFERENCES users ON DELETE CASCADE,
+    -- A single address or a range
+    ip_address CIDR NULL,
+    reason TEXT NOT NULL,
+    actor_id INTEGER NOT NULL REFERENCES actors,
+    -- NULL blocks edits in every namespace
+    namespace_ids INTEGER[] NULL,
+    prevent_account_creation BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT blocks_target_check CHECK ((user_id IS NULL) <> (ip_address IS NULL))
+);
+CREATE INDEX blocks_user_id_idx ON blocks (user_id);
+CREATE INDEX blocks_ip_address_idx ON blocks USING gist (ip_address inet_ops);
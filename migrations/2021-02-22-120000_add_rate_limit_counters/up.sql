@@ -0,0 +1,8 @@
+CREATE TABLE rate_limit_counters (
+    key VARCHAR(200) NOT NULL,
+    window_start TIMESTAMP NOT NULL,
+    hits INTEGER NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    CONSTRAINT rate_limit_counters_pkey PRIMARY KEY (key, window_start)
+);
+CREATE INDEX rate_limit_counters_expires_at_idx ON rate_limit_counters (expires_at);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE revision_tags;
+DROP TABLE abuse_filter_hits;
+DROP TABLE abuse_filters;
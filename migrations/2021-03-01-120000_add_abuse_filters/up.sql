@@ -0,0 +1,33 @@
+CREATE TABLE abuse_filters (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    name VARCHAR(100) NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    pattern TEXT NOT NULL,
+    action VARCHAR(20) NOT NULL,
+    -- Added to matching revisions when action is 'tag'
+    tag VARCHAR(100) NULL,
+    -- When action is 'throttle', matches beyond throttle_count within throttle_period seconds
+    -- are disallowed
+    throttle_count INTEGER NULL,
+    throttle_period INTEGER NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    actor_id INTEGER NOT NULL REFERENCES actors,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE abuse_filter_hits (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    filter_id INTEGER NOT NULL REFERENCES abuse_filters ON DELETE CASCADE,
+    actor_id INTEGER NOT NULL REFERENCES actors,
+    namespace_id INTEGER NOT NULL REFERENCES namespaces ON DELETE CASCADE,
+    title VARCHAR(300) NOT NULL,
+    action VARCHAR(20) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX abuse_filter_hits_filter_id_actor_id_idx ON abuse_filter_hits (filter_id, actor_id, created_at);
+CREATE TABLE revision_tags (
+    revision_id INTEGER NOT NULL REFERENCES revisions ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    PRIMARY KEY (revision_id, name)
+);
+CREATE INDEX revision_tags_name_idx ON revision_tags (name);
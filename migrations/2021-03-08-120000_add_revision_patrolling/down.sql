@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX revisions_unpatrolled_idx;
+ALTER TABLE revisions DROP COLUMN patrolled_at;
+ALTER TABLE revisions DROP COLUMN patrolled_by;
@@ -0,0 +1,6 @@
+-- patrolled_at without patrolled_by marks revisions patrolled automatically
+ALTER TABLE revisions ADD COLUMN patrolled_by INTEGER NULL REFERENCES actors;
+ALTER TABLE revisions ADD COLUMN patrolled_at TIMESTAMP NULL;
+-- Revisions made before patrolling existed are not queued for review
+UPDATE revisions SET patrolled_at = created_at;
+CREATE INDEX revisions_unpatrolled_idx ON revisions (id) WHERE patrolled_at IS NULL;
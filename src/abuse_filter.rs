@@ -0,0 +1,538 @@
+//! Rule language of abuse filters
+//!
+//! A rule is a boolean expression over the variables of a change, e.g.
+//! `namespace == "" & size_delta < -5000 & edit_count < 10` or
+//! `added_lines irlike "buy (cheap|now)" | !(summary contains "revert")`.
+//!
+//! - Values are integers, strings in single or double quotes, `true` and `false`
+//! - `!`, `&` and `|` combine booleans; `==`, `!=`, `<`, `<=`, `>` and `>=` compare values of the
+//!   same type; `+` and `-` add and subtract integers
+//! - `contains` tests for a substring, and `rlike` and `irlike` match a regular expression, case
+//!   sensitively and insensitively. On a list of lines they test whether any line does.
+//! - Parentheses, `!` and `-` nest, and operators chain, at most `MAX_DEPTH` deep. Integer
+//!   overflow is an error.
+//!
+//! See `FilterVariables` for the variables.
+
+use anyhow::{anyhow, Result};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// What a rule knows about a change. Named as in rules.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FilterVariables {
+    /// `create` or `edit`.
+    pub action: String,
+    /// Name of the namespace, empty for the main namespace.
+    pub namespace: String,
+    pub title: String,
+    pub summary: String,
+    pub added_lines: Vec<String>,
+    pub removed_lines: Vec<String>,
+    /// Size of the new wikitext in bytes.
+    pub new_size: i64,
+    pub size_delta: i64,
+    /// Revisions the actor made before.
+    pub edit_count: i64,
+    /// Seconds since the account was created, 0 for anonymous editors.
+    pub account_age: i64,
+    pub is_anonymous: bool,
+}
+
+const VARIABLES: &[&str] = &[
+    "action",
+    "namespace",
+    "title",
+    "summary",
+    "added_lines",
+    "removed_lines",
+    "new_size",
+    "size_delta",
+    "edit_count",
+    "account_age",
+    "is_anonymous",
+];
+
+impl FilterVariables {
+    fn get(&self, name: &str) -> Value {
+        match name {
+            "action" => Value::Str(self.action.clone()),
+            "namespace" => Value::Str(self.namespace.clone()),
+            "title" => Value::Str(self.title.clone()),
+            "summary" => Value::Str(self.summary.clone()),
+            "added_lines" => Value::Lines(self.added_lines.clone()),
+            "removed_lines" => Value::Lines(self.removed_lines.clone()),
+            "new_size" => Value::Int(self.new_size),
+            "size_delta" => Value::Int(self.size_delta),
+            "edit_count" => Value::Int(self.edit_count),
+            "account_age" => Value::Int(self.account_age),
+            "is_anonymous" => Value::Bool(self.is_anonymous),
+            // Names are checked when parsing
+            _ => unreachable!("unknown variable {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Int(i64),
+    Str(String),
+    Bool(bool),
+    Lines(Vec<String>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "integer",
+            Value::Str(_) => "string",
+            Value::Bool(_) => "boolean",
+            Value::Lines(_) => "lines",
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            value => Err(anyhow!("Expected a boolean, got {}", value.type_name())),
+        }
+    }
+
+    fn as_int(&self) -> Result<i64> {
+        match self {
+            Value::Int(value) => Ok(*value),
+            value => Err(anyhow!("Expected an integer, got {}", value.type_name())),
+        }
+    }
+
+    /// Whether the string, or any of the lines, satisfies `test`.
+    fn any_text(&self, test: impl Fn(&str) -> bool) -> Result<bool> {
+        match self {
+            Value::Str(value) => Ok(test(value)),
+            Value::Lines(lines) => Ok(lines.iter().any(|line| test(line))),
+            value => Err(anyhow!("Expected text, got {}", value.type_name())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+const OPERATORS: &[&str] = &["==", "!=", "<=", ">=", "<", ">", "!", "&", "|", "+", "-"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let chars = source.chars().collect::<Vec<char>>();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    Some('\\') => {
+                        let escaped = chars
+                            .get(i + 1)
+                            .ok_or_else(|| anyhow!("Unterminated string"))?;
+                        value.push(*escaped);
+                        i += 2;
+                    }
+                    Some(quote) if *quote == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(other) => {
+                        value.push(*other);
+                        i += 1;
+                    }
+                    None => return Err(anyhow!("Unterminated string")),
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits = chars[start..i].iter().collect::<String>();
+            tokens.push(Token::Int(digits.parse()?));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| {
+                    op.chars()
+                        .enumerate()
+                        .all(|(j, o)| chars.get(i + j) == Some(&o))
+                })
+                .ok_or_else(|| anyhow!("Unexpected character {}", c))?;
+            tokens.push(Token::Op(*op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    And,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Contains,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Variable(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+    Matches(Box<Expr>, Regex),
+}
+
+/// How deep expressions may nest, so that parsing and evaluating cannot overflow the stack.
+pub const MAX_DEPTH: usize = 100;
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat_op(&mut self, op: &'static str) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Goes one level deeper into the expression.
+    fn descend(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(anyhow!("Rule is nested more than {} deep", MAX_DEPTH));
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.and()?;
+        while self.eat_op("|") {
+            self.descend()?;
+            left = Expr::Binary(Box::new(left), BinaryOp::Or, Box::new(self.and()?));
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.not()?;
+        while self.eat_op("&") {
+            self.descend()?;
+            left = Expr::Binary(Box::new(left), BinaryOp::And, Box::new(self.not()?));
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat_op("!") {
+            self.descend()?;
+            let expr = self.not()?;
+            self.depth -= 1;
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.sum()?;
+        let op = match self.peek() {
+            Some(Token::Op("==")) => BinaryOp::Eq,
+            Some(Token::Op("!=")) => BinaryOp::Ne,
+            Some(Token::Op("<")) => BinaryOp::Lt,
+            Some(Token::Op("<=")) => BinaryOp::Le,
+            Some(Token::Op(">")) => BinaryOp::Gt,
+            Some(Token::Op(">=")) => BinaryOp::Ge,
+            Some(Token::Ident(keyword)) if keyword == "contains" => BinaryOp::Contains,
+            Some(Token::Ident(keyword)) if keyword == "rlike" || keyword == "irlike" => {
+                let case_insensitive = keyword == "irlike";
+                self.position += 1;
+                let pattern = match self.next() {
+                    Some(Token::Str(pattern)) => pattern,
+                    _ => return Err(anyhow!("Expected a string pattern")),
+                };
+                let regex = RegexBuilder::new(&pattern)
+                    .case_insensitive(case_insensitive)
+                    .build()?;
+                return Ok(Expr::Matches(Box::new(left), regex));
+            }
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        Ok(Expr::Binary(Box::new(left), op, Box::new(self.sum()?)))
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat_op("+") {
+                BinaryOp::Add
+            } else if self.eat_op("-") {
+                BinaryOp::Sub
+            } else {
+                self.depth = depth;
+                return Ok(left);
+            };
+            self.descend()?;
+            left = Expr::Binary(Box::new(left), op, Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat_op("-") {
+            self.descend()?;
+            let expr = self.unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Neg(Box::new(expr)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Int(value)) => Ok(Expr::Literal(Value::Int(value))),
+            Some(Token::Str(value)) => Ok(Expr::Literal(Value::Str(value))),
+            Some(Token::Ident(name)) if name == "true" => Ok(Expr::Literal(Value::Bool(true))),
+            Some(Token::Ident(name)) if name == "false" => Ok(Expr::Literal(Value::Bool(false))),
+            Some(Token::Ident(name)) if VARIABLES.contains(&name.as_str()) => {
+                Ok(Expr::Variable(name))
+            }
+            Some(Token::Ident(name)) => Err(anyhow!("Unknown variable {}", name)),
+            Some(Token::LParen) => {
+                self.descend()?;
+                let expr = self.or()?;
+                self.depth -= 1;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(anyhow!("Expected )")),
+                }
+            }
+            Some(token) => Err(anyhow!("Unexpected {:?}", token)),
+            None => Err(anyhow!("Unexpected end of rule")),
+        }
+    }
+}
+
+impl Expr {
+    fn evaluate(&self, variables: &FilterVariables) -> Result<Value> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Variable(name) => Ok(variables.get(name)),
+            Expr::Not(expr) => Ok(Value::Bool(!expr.evaluate(variables)?.as_bool()?)),
+            Expr::Neg(expr) => expr
+                .evaluate(variables)?
+                .as_int()?
+                .checked_neg()
+                .map(Value::Int)
+                .ok_or_else(|| anyhow!("Integer overflow")),
+            Expr::Matches(expr, regex) => Ok(Value::Bool(
+                expr.evaluate(variables)?
+                    .any_text(|text| regex.is_match(text))?,
+            )),
+            Expr::Binary(left, BinaryOp::And, right) => Ok(Value::Bool(
+                left.evaluate(variables)?.as_bool()? && right.evaluate(variables)?.as_bool()?,
+            )),
+            Expr::Binary(left, BinaryOp::Or, right) => Ok(Value::Bool(
+                left.evaluate(variables)?.as_bool()? || right.evaluate(variables)?.as_bool()?,
+            )),
+            Expr::Binary(left, op, right) => {
+                let left = left.evaluate(variables)?;
+                let right = right.evaluate(variables)?;
+                match op {
+                    BinaryOp::Eq | BinaryOp::Ne => {
+                        if left.type_name() != right.type_name() {
+                            return Err(anyhow!(
+                                "Cannot compare {} with {}",
+                                left.type_name(),
+                                right.type_name()
+                            ));
+                        }
+                        Ok(Value::Bool((left == right) == (*op == BinaryOp::Eq)))
+                    }
+                    BinaryOp::Lt => Ok(Value::Bool(left.as_int()? < right.as_int()?)),
+                    BinaryOp::Le => Ok(Value::Bool(left.as_int()? <= right.as_int()?)),
+                    BinaryOp::Gt => Ok(Value::Bool(left.as_int()? > right.as_int()?)),
+                    BinaryOp::Ge => Ok(Value::Bool(left.as_int()? >= right.as_int()?)),
+                    BinaryOp::Add => left
+                        .as_int()?
+                        .checked_add(right.as_int()?)
+                        .map(Value::Int)
+                        .ok_or_else(|| anyhow!("Integer overflow")),
+                    BinaryOp::Sub => left
+                        .as_int()?
+                        .checked_sub(right.as_int()?)
+                        .map(Value::Int)
+                        .ok_or_else(|| anyhow!("Integer overflow")),
+                    BinaryOp::Contains => match right {
+                        Value::Str(needle) => Ok(Value::Bool(
+                            left.any_text(|text| text.contains(needle.as_str()))?,
+                        )),
+                        value => Err(anyhow!("Expected a string, got {}", value.type_name())),
+                    },
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+/// A parsed rule.
+#[derive(Debug)]
+pub struct Rule {
+    expr: Expr,
+}
+
+impl Rule {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(anyhow!("Unexpected {:?}", token));
+        }
+        Ok(Self { expr })
+    }
+
+    /// Whether the change described by `variables` matches.
+    pub fn matches(&self, variables: &FilterVariables) -> Result<bool> {
+        self.expr.evaluate(variables)?.as_bool()
+    }
+}
+
+/// For `#[validate(custom = "...")]` on request fields holding a rule.
+pub fn validate_rule(source: &str) -> Result<(), validator::ValidationError> {
+    match Rule::parse(source) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(validator::ValidationError::new("invalid_rule")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> FilterVariables {
+        FilterVariables {
+            action: "edit".to_owned(),
+            namespace: "".to_owned(),
+            title: "Main Page".to_owned(),
+            summary: "".to_owned(),
+            added_lines: vec!["Buy CHEAP pills".to_owned()],
+            removed_lines: vec!["Welcome".to_owned(), "to the wiki".to_owned()],
+            new_size: 15,
+            size_delta: -5000,
+            edit_count: 2,
+            account_age: 0,
+            is_anonymous: true,
+        }
+    }
+
+    fn matches(source: &str) -> bool {
+        Rule::parse(source)
+            .expect("must succeed")
+            .matches(&variables())
+            .expect("must succeed")
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("size_delta < -1000 & edit_count < 10"));
+        assert!(matches("added_lines irlike \"buy (cheap|now)\""));
+        assert!(!matches("added_lines rlike 'buy'"));
+        assert!(matches("removed_lines contains \"wiki\" & is_anonymous"));
+        assert!(matches("!(title == 'Sandbox') | false"));
+        assert!(matches("new_size - size_delta == 5015"));
+        assert!(!matches("namespace != \"\" | account_age > 0"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Rule::parse("unknown_variable == 1").is_err());
+        assert!(Rule::parse("title rlike '('").is_err());
+        assert!(Rule::parse("(size_delta < 0").is_err());
+        assert!(Rule::parse("size_delta < 0 )").is_err());
+        assert!(Rule::parse("'unterminated").is_err());
+        let rule = Rule::parse("title < 3").expect("must succeed");
+        assert!(rule.matches(&variables()).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let nested = format!("{}true{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert!(Rule::parse(&nested).is_ok());
+        let nested = format!("{}true{}", "(".repeat(10000), ")".repeat(10000));
+        assert!(Rule::parse(&nested).is_err());
+        assert!(Rule::parse(&format!("{}true", "!".repeat(10000))).is_err());
+        assert!(Rule::parse(&format!("{}1 == 1", "-".repeat(10000))).is_err());
+        assert!(Rule::parse(&vec!["true"; 10000].join(" & ")).is_err());
+        assert!(Rule::parse(&vec!["true"; MAX_DEPTH].join(" | ")).is_ok());
+
+        for source in &[
+            "new_size + 9223372036854775807 > 0",
+            "size_delta - 9223372036854775807 < 0",
+            "-(-9223372036854775807 - 1) > 0",
+        ] {
+            let rule = Rule::parse(source).expect("must succeed");
+            assert!(rule.matches(&variables()).is_err());
+        }
+    }
+}
@@ -1,68 +0,0 @@
-use anyhow::Result;
-use chrono::prelude::*;
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-pub struct TokenClaims {
-    pub sub: i32,
-    #[serde(with = "jwt_numeric_date")]
-    iat: DateTime<Utc>,
-    #[serde(with = "jwt_numeric_date")]
-    exp: DateTime<Utc>,
-    t: String,
-}
-
-impl TokenClaims {
-    pub fn new(sub: i32, iat: DateTime<Utc>, exp: DateTime<Utc>, t: &str) -> Self {
-        // normalize the timestamps by stripping of microseconds
-        let iat = iat
-            .date()
-            .and_hms_milli(iat.hour(), iat.minute(), iat.second(), 0);
-        let exp = exp
-            .date()
-            .and_hms_milli(exp.hour(), exp.minute(), exp.second(), 0);
-        Self {
-            sub,
-            iat,
-            exp,
-            t: t.to_owned(),
-        }
-    }
-}
-
-mod jwt_numeric_date {
-    //! Custom serialization of DateTime<Utc> to conform with the JWT spec (RFC 7519 section 2, "Numeric Date")
-    use chrono::{DateTime, TimeZone, Utc};
-    use serde::{self, Deserialize, Deserializer, Serializer};
-
-    /// Serializes a DateTime<Utc> to a Unix timestamp (milliseconds since 1970/1/1T00:00:00T)
-    pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let timestamp = date.timestamp();
-        serializer.serialize_i64(timestamp)
-    }
-
-    /// Attempts to deserialize an i64 and use as a Unix timestamp
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        Utc.timestamp_opt(i64::deserialize(deserializer)?, 0)
-            .single() // If there are multiple or no valid DateTimes from timestamp, return None
-            .ok_or_else(|| serde::de::Error::custom("invalid Unix timestamp value"))
-    }
-}
-
-pub fn decode(token: &str) -> Result<jsonwebtoken::TokenData<TokenClaims>> {
-    use jsonwebtoken::{decode, DecodingKey, Validation};
-    use std::env;
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let result = decode::<TokenClaims>(
-        &token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )?;
-    Ok(result)
-}
@@ -0,0 +1,163 @@
+pub mod keys;
+pub mod provider;
+
+use anyhow::Result;
+use chrono::prelude::*;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenClaims {
+    pub sub: i32,
+    #[serde(with = "jwt_numeric_date")]
+    iat: DateTime<Utc>,
+    #[serde(with = "jwt_numeric_date")]
+    exp: DateTime<Utc>,
+    /// `access` or `refresh`.
+    pub t: String,
+    /// The session the token belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<i32>,
+    /// Identifies a refresh token within its session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+}
+
+impl TokenClaims {
+    pub fn new(sub: i32, iat: DateTime<Utc>, exp: DateTime<Utc>, t: &str) -> Self {
+        // normalize the timestamps by stripping of microseconds
+        let iat = iat
+            .date()
+            .and_hms_milli(iat.hour(), iat.minute(), iat.second(), 0);
+        let exp = exp
+            .date()
+            .and_hms_milli(exp.hour(), exp.minute(), exp.second(), 0);
+        Self {
+            sub,
+            iat,
+            exp,
+            t: t.to_owned(),
+            sid: None,
+            jti: None,
+            iss: keys::KEYS.issuer().map(str::to_owned),
+            aud: keys::KEYS.audience().map(str::to_owned),
+        }
+    }
+
+    pub fn with_session(mut self, session_id: i32, token_id: Option<String>) -> Self {
+        self.sid = Some(session_id);
+        self.jti = token_id;
+        self
+    }
+}
+
+mod jwt_numeric_date {
+    //! Custom serialization of DateTime<Utc> to conform with the JWT spec (RFC 7519 section 2, "Numeric Date")
+    use chrono::{DateTime, TimeZone, Utc};
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    /// Serializes a DateTime<Utc> to a Unix timestamp (milliseconds since 1970/1/1T00:00:00T)
+    pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let timestamp = date.timestamp();
+        serializer.serialize_i64(timestamp)
+    }
+
+    /// Attempts to deserialize an i64 and use as a Unix timestamp
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Utc.timestamp_opt(i64::deserialize(deserializer)?, 0)
+            .single() // If there are multiple or no valid DateTimes from timestamp, return None
+            .ok_or_else(|| serde::de::Error::custom("invalid Unix timestamp value"))
+    }
+}
+
+pub fn encode(claims: &TokenClaims) -> Result<String> {
+    keys::KEYS.encode(claims)
+}
+
+/// Issues a short-lived access token for the user `sub` signed in with the session `session_id`.
+pub fn issue_access_token(sub: i32, session_id: i32) -> Result<String> {
+    use chrono::Duration;
+    let claims = TokenClaims::new(sub, Utc::now(), Utc::now() + Duration::hours(1), "access")
+        .with_session(session_id, None);
+    encode(&claims)
+}
+
+pub fn decode(token: &str) -> Result<jsonwebtoken::TokenData<TokenClaims>> {
+    keys::KEYS.decode(token)
+}
+
+/// Hex encoded SHA-256 of an API or user token, as stored instead of the token. Tokens are random
+/// and long, so a fast unsalted hash suffices.
+pub fn hash_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Hashes `password` with Argon2id and a random salt, in the PHC string format.
+pub fn hash_password(password: &str) -> Result<String> {
+    use argon2::{Config, Variant};
+    use rand::Rng;
+    let salt = rand::thread_rng().gen::<[u8; 16]>();
+    let config = Config {
+        variant: Variant::Argon2id,
+        ..Config::default()
+    };
+    Ok(argon2::hash_encoded(password.as_bytes(), &salt, &config)?)
+}
+
+pub fn verify_password(hash: &str, password: &str) -> Result<bool> {
+    Ok(argon2::verify_encoded(hash, password.as_bytes())?)
+}
+
+/// A hash no password matches, verified when there is no real one to verify.
+static DUMMY_PASSWORD_HASH: Lazy<String> =
+    Lazy::new(|| hash_password(&generate_token()).expect("Failed to hash the dummy password"));
+
+/// Takes as long as `verify_password` and never matches, so that response times do not tell
+/// whether a user or their password exists.
+pub fn verify_no_password(password: &str) -> Result<bool> {
+    verify_password(&DUMMY_PASSWORD_HASH, password)?;
+    Ok(false)
+}
+
+/// A random token to be handed out once, such as in an email.
+pub fn generate_token() -> String {
+    use rand::distributions::Alphanumeric;
+    use rand::Rng;
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(43)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password() {
+        let hash = hash_password("correct horse").expect("must succeed");
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password(&hash, "correct horse").expect("must succeed"));
+        assert!(!verify_password(&hash, "battery staple").expect("must succeed"));
+        assert!(!verify_no_password("correct horse").expect("must succeed"));
+    }
+
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
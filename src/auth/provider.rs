@@ -0,0 +1,331 @@
+//! External identity providers, signed in to with the OAuth 2.0 authorization code flow and PKCE.
+//!
+//! Providers are configured from the environment. `AUTH_PROVIDERS` lists their names, and each
+//! provider `<NAME>` reads:
+//!
+//! - `AUTH_<NAME>_CLIENT_ID`, `AUTH_<NAME>_CLIENT_SECRET` and `AUTH_<NAME>_REDIRECT_URI`
+//! - `AUTH_<NAME>_ISSUER` for OpenID Connect discovery, or the endpoints
+//!   `AUTH_<NAME>_AUTHORIZATION_ENDPOINT`, `AUTH_<NAME>_TOKEN_ENDPOINT` and
+//!   `AUTH_<NAME>_USERINFO_ENDPOINT`, which also override discovered ones
+//! - `AUTH_<NAME>_SCOPES`, `openid email profile` by default
+//! - `AUTH_<NAME>_SUBJECT_CLAIM`, `AUTH_<NAME>_EMAIL_CLAIM`, `AUTH_<NAME>_EMAIL_VERIFIED_CLAIM` and
+//!   `AUTH_<NAME>_NAME_CLAIM`, naming the userinfo claims to read, `sub`, `email`,
+//!   `email_verified` and `name` by default
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::Value;
+use std::env;
+use std::sync::Mutex;
+
+/// Who a provider says the user is.
+#[derive(Debug, PartialEq)]
+pub struct Identity {
+    /// Identifies the user within the provider.
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+#[async_trait(?Send)]
+pub trait Provider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Where to send the user to sign in. The provider redirects back with `state` and a code.
+    async fn authorization_url(&self, state: &str, code_challenge: &str) -> Result<Url>;
+
+    /// Exchanges the code the provider redirected back with for the identity of the user.
+    async fn exchange(&self, code: &str, code_verifier: &str) -> Result<Identity>;
+}
+
+/// The PKCE `S256` challenge of `code_verifier`.
+pub fn code_challenge(code_verifier: &str) -> String {
+    use sha2::{Digest, Sha256};
+    base64::encode_config(
+        Sha256::digest(code_verifier.as_bytes()),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+/// Names of the userinfo claims holding the identity.
+#[derive(Clone, Debug)]
+pub struct ClaimMapping {
+    pub subject: String,
+    pub email: String,
+    pub email_verified: String,
+    pub name: String,
+}
+
+impl Default for ClaimMapping {
+    fn default() -> Self {
+        Self {
+            subject: "sub".to_owned(),
+            email: "email".to_owned(),
+            email_verified: "email_verified".to_owned(),
+            name: "name".to_owned(),
+        }
+    }
+}
+
+impl ClaimMapping {
+    fn identity(&self, claims: &Value) -> Result<Identity> {
+        let string = |claim: &str| match claims.get(claim) {
+            Some(Value::String(value)) => Some(value.clone()),
+            // GitHub and Facebook ids are numbers
+            Some(Value::Number(value)) => Some(value.to_string()),
+            _ => None,
+        };
+        Ok(Identity {
+            subject: string(&self.subject)
+                .ok_or_else(|| anyhow!("Claim {} is missing", &self.subject))?,
+            email: string(&self.email),
+            email_verified: claims.get(&self.email_verified) == Some(&Value::Bool(true)),
+            name: string(&self.name),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OAuthConfig {
+    pub name: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    /// Base URL of an OpenID Connect issuer, to discover endpoints from.
+    pub issuer: Option<String>,
+    pub authorization_endpoint: Option<String>,
+    pub token_endpoint: Option<String>,
+    pub userinfo_endpoint: Option<String>,
+    pub scopes: String,
+    pub claims: ClaimMapping,
+}
+
+impl OAuthConfig {
+    pub fn from_env(name: &str) -> Result<Self> {
+        let prefix = format!("AUTH_{}_", name.to_uppercase().replace('-', "_"));
+        let var = |key: &str| env::var(format!("{}{}", prefix, key)).ok();
+        let required = |key: &str| var(key).ok_or_else(|| anyhow!("{}{} must be set", prefix, key));
+        let defaults = ClaimMapping::default();
+        Ok(Self {
+            name: name.to_owned(),
+            client_id: required("CLIENT_ID")?,
+            client_secret: required("CLIENT_SECRET")?,
+            redirect_uri: required("REDIRECT_URI")?,
+            issuer: var("ISSUER"),
+            authorization_endpoint: var("AUTHORIZATION_ENDPOINT"),
+            token_endpoint: var("TOKEN_ENDPOINT"),
+            userinfo_endpoint: var("USERINFO_ENDPOINT"),
+            scopes: var("SCOPES").unwrap_or_else(|| "openid email profile".to_owned()),
+            claims: ClaimMapping {
+                subject: var("SUBJECT_CLAIM").unwrap_or(defaults.subject),
+                email: var("EMAIL_CLAIM").unwrap_or(defaults.email),
+                email_verified: var("EMAIL_VERIFIED_CLAIM").unwrap_or(defaults.email_verified),
+                name: var("NAME_CLAIM").unwrap_or(defaults.name),
+            },
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Endpoints {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// A provider speaking OAuth 2.0, with OpenID Connect discovery when it has an issuer.
+pub struct OAuthProvider {
+    config: OAuthConfig,
+    /// Endpoints discovered from the issuer, fetched once.
+    endpoints: Mutex<Option<Endpoints>>,
+}
+
+impl OAuthProvider {
+    pub fn new(config: OAuthConfig) -> Self {
+        Self {
+            config,
+            endpoints: Mutex::new(None),
+        }
+    }
+
+    async fn endpoints(&self) -> Result<Endpoints> {
+        if let Some(endpoints) = &*self.endpoints.lock().unwrap() {
+            return Ok(endpoints.clone());
+        }
+        let config = &self.config;
+        let discovered = match &config.issuer {
+            Some(issuer) => {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    issuer.trim_end_matches('/')
+                );
+                let document = reqwest::get(&url).await?.error_for_status()?;
+                Some(document.json::<Value>().await?)
+            }
+            None => None,
+        };
+        let endpoint = |configured: &Option<String>, key: &str| {
+            configured
+                .clone()
+                .or_else(|| {
+                    discovered
+                        .as_ref()
+                        .and_then(|document| document.get(key))
+                        .and_then(Value::as_str)
+                        .map(str::to_owned)
+                })
+                .ok_or_else(|| anyhow!("No {} for provider {}", key, &config.name))
+        };
+        let endpoints = Endpoints {
+            authorization_endpoint: endpoint(
+                &config.authorization_endpoint,
+                "authorization_endpoint",
+            )?,
+            token_endpoint: endpoint(&config.token_endpoint, "token_endpoint")?,
+            userinfo_endpoint: endpoint(&config.userinfo_endpoint, "userinfo_endpoint")?,
+        };
+        *self.endpoints.lock().unwrap() = Some(endpoints.clone());
+        Ok(endpoints)
+    }
+}
+
+#[async_trait(?Send)]
+impl Provider for OAuthProvider {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    async fn authorization_url(&self, state: &str, code_challenge: &str) -> Result<Url> {
+        let endpoints = self.endpoints().await?;
+        let url = Url::parse_with_params(
+            &endpoints.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("scope", self.config.scopes.as_str()),
+                ("state", state),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )?;
+        Ok(url)
+    }
+
+    async fn exchange(&self, code: &str, code_verifier: &str) -> Result<Identity> {
+        let endpoints = self.endpoints().await?;
+        let client = reqwest::Client::new();
+        let token = client
+            .post(&endpoints.token_endpoint)
+            .header("Accept", "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("client_id", self.config.client_id.as_str()),
+                ("client_secret", self.config.client_secret.as_str()),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()
+            .await?;
+        let claims = client
+            .get(&endpoints.userinfo_endpoint)
+            .bearer_auth(&token.access_token)
+            .header("Accept", "application/json")
+            // GitHub rejects requests without one
+            .header("User-Agent", "wikist")
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        self.config.claims.identity(&claims)
+    }
+}
+
+/// The configured providers, looked up by name.
+pub struct Providers {
+    providers: Vec<Box<dyn Provider>>,
+}
+
+impl Providers {
+    pub fn new(providers: Vec<Box<dyn Provider>>) -> Self {
+        Self { providers }
+    }
+
+    /// Reads the providers listed in `AUTH_PROVIDERS`.
+    pub fn from_env() -> Result<Self> {
+        let names = env::var("AUTH_PROVIDERS").unwrap_or_default();
+        let providers = names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                Ok(Box::new(OAuthProvider::new(OAuthConfig::from_env(name)?)) as Box<dyn Provider>)
+            })
+            .collect::<Result<Vec<Box<dyn Provider>>>>()?;
+        Ok(Self::new(providers))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Provider> {
+        self.providers
+            .iter()
+            .find(|provider| provider.name() == name)
+            .map(|provider| provider.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.providers
+            .iter()
+            .map(|provider| provider.name())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_code_challenge() {
+        assert_eq!(
+            code_challenge("test-verifier"),
+            "JBbiqONGWPaAmwXk_8bT6UnlPfrn65D32eZlJS-zGG0"
+        );
+    }
+
+    #[test]
+    fn test_claim_mapping() {
+        let mapping = ClaimMapping {
+            subject: "id".to_owned(),
+            email: "email".to_owned(),
+            email_verified: "email_verified".to_owned(),
+            name: "login".to_owned(),
+        };
+        let identity = mapping
+            .identity(&json!({"id": 42, "login": "octocat", "email": null}))
+            .expect("must succeed");
+        assert_eq!(
+            identity,
+            Identity {
+                subject: "42".to_owned(),
+                email: None,
+                email_verified: false,
+                name: Some("octocat".to_owned()),
+            }
+        );
+        assert!(mapping.identity(&json!({"login": "octocat"})).is_err());
+    }
+}
@@ -0,0 +1,41 @@
+//! Compresses the stored wikitext of old revisions.
+//!
+//! Meant to be run periodically, e.g. from cron. Configured with `CONTENT_COMPRESSION`
+//! (`zstd`, `deflate` or `none`), `CONTENT_KEYFRAME_INTERVAL` and `CONTENT_RECOMPRESS_BATCH_SIZE`.
+//! With `none`, every compressed content is stored as plain wikitext again instead.
+extern crate wikist;
+
+use std::env;
+use wikist::models::Content;
+use wikist::storage::Compression;
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let pool = wikist::db::create_connection_pool();
+    let conn = pool.get()?;
+    let compression = Compression::from_env()?;
+    let keyframe_interval = env_or("CONTENT_KEYFRAME_INTERVAL", 20);
+    let batch_size = env_or("CONTENT_RECOMPRESS_BATCH_SIZE", 100);
+    let mut total = 0;
+    loop {
+        let count = if compression == Compression::None {
+            Content::restore_batch(&conn, batch_size)?
+        } else {
+            Content::recompress_batch(&conn, compression, keyframe_interval, batch_size)?
+        };
+        if count == 0 {
+            break;
+        }
+        total += count;
+        log::info!("Recompressed {} contents", total);
+    }
+    println!("Recompressed {} contents", total);
+    Ok(())
+}
@@ -0,0 +1,249 @@
+//! Client address detection behind reverse proxies
+//!
+//! Configured with:
+//! - `TRUSTED_PROXIES`: comma separated addresses or CIDR ranges of the reverse proxies whose
+//!   `Forwarded` and `X-Forwarded-For` headers are believed. Without it the peer address is the
+//!   client address.
+//! - `ANONYMIZE_IPS`: when `true`, addresses of anonymous editors are shown truncated to their /24
+//!   (IPv4) or /48 (IPv6) network in public responses, and contributions are only looked up by
+//!   those networks
+
+use anyhow::{anyhow, Result};
+use ipnetwork::IpNetwork;
+use once_cell::sync::Lazy;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+const ANONYMIZED_IPV4_PREFIX: u8 = 24;
+const ANONYMIZED_IPV6_PREFIX: u8 = 48;
+
+pub struct ClientIpConfig {
+    trusted_proxies: Vec<IpNetwork>,
+    anonymize_ips: bool,
+}
+
+impl ClientIpConfig {
+    pub fn new(trusted_proxies: Vec<IpNetwork>, anonymize_ips: bool) -> Self {
+        Self {
+            trusted_proxies,
+            anonymize_ips,
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let trusted_proxies = env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| {
+                IpNetwork::from_str(proxy)
+                    .map_err(|_| anyhow!("Invalid TRUSTED_PROXIES entry {}", proxy))
+            })
+            .collect::<Result<Vec<IpNetwork>>>()?;
+        let anonymize_ips = match env::var("ANONYMIZE_IPS").ok().as_deref() {
+            None | Some("") | Some("false") => false,
+            Some("true") => true,
+            Some(value) => return Err(anyhow!("Invalid ANONYMIZE_IPS {}", value)),
+        };
+        Ok(Self::new(trusted_proxies, anonymize_ips))
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|network| network.contains(ip))
+    }
+
+    /// The address of the client of a request from `peer`, given the values of its `Forwarded`
+    /// and `X-Forwarded-For` headers.
+    ///
+    /// Hops are followed from the right, the one closest to us, only while they are trusted
+    /// proxies; anything left of the first untrusted hop may be forged by the client. `Forwarded`
+    /// wins over `X-Forwarded-For` when both are present.
+    pub fn client_ip(&self, peer: IpAddr, forwarded: &[&str], x_forwarded_for: &[&str]) -> IpAddr {
+        let mut client = normalize(peer);
+        if !self.is_trusted(client) {
+            return client;
+        }
+        let hops = if forwarded.is_empty() {
+            parse_x_forwarded_for(x_forwarded_for)
+        } else {
+            parse_forwarded(forwarded)
+        };
+        for hop in hops.into_iter().rev() {
+            match hop {
+                Some(ip) => client = normalize(ip),
+                // An obfuscated or unknown hop, so the last proxy is all we know
+                None => return client,
+            }
+            if !self.is_trusted(client) {
+                return client;
+            }
+        }
+        client
+    }
+
+    /// How to show the address of an anonymous editor publicly.
+    pub fn display_ip(&self, ip: IpAddr) -> String {
+        if self.anonymize_ips {
+            anonymize(ip).to_string()
+        } else {
+            ip.to_string()
+        }
+    }
+
+    /// The addresses a public lookup of `network` may show, so that anonymized addresses cannot
+    /// be probed one by one.
+    pub fn lookup_network(&self, network: IpNetwork) -> IpNetwork {
+        if self.anonymize_ips {
+            anonymize(network.ip())
+        } else {
+            network
+        }
+    }
+}
+
+/// Unwraps IPv4 addresses mapped into IPv6, as dual stack sockets report them.
+pub fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) if v6.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => match v6.to_ipv4() {
+            Some(v4) => IpAddr::V4(v4),
+            None => ip,
+        },
+        _ => ip,
+    }
+}
+
+/// The network of `ip` which is shown instead of it.
+pub fn anonymize(ip: IpAddr) -> IpNetwork {
+    let prefix = match ip {
+        IpAddr::V4(_) => ANONYMIZED_IPV4_PREFIX,
+        IpAddr::V6(_) => ANONYMIZED_IPV6_PREFIX,
+    };
+    // The prefixes are valid for their address families
+    let network = IpNetwork::new(ip, prefix).expect("valid prefix");
+    IpNetwork::new(network.network(), prefix).expect("valid prefix")
+}
+
+/// Parses a node such as `192.0.2.1`, `192.0.2.1:4711`, `[2001:db8::1]:4711` or `2001:db8::1`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(ip) = IpAddr::from_str(node) {
+        return Some(ip);
+    }
+    if let Ok(addr) = SocketAddr::from_str(node) {
+        return Some(addr.ip());
+    }
+    let host = node.strip_prefix('[')?.split(']').next()?;
+    IpAddr::from_str(host).ok()
+}
+
+/// The `for` of every element of `Forwarded` header values, left to right.
+fn parse_forwarded(values: &[&str]) -> Vec<Option<IpAddr>> {
+    values
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| {
+                    let mut parts = pair.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case("for") => {
+                            Some(value)
+                        }
+                        _ => None,
+                    }
+                })
+                .next()
+                .and_then(parse_node)
+        })
+        .collect()
+}
+
+/// The addresses of `X-Forwarded-For` header values, left to right.
+fn parse_x_forwarded_for(values: &[&str]) -> Vec<Option<IpAddr>> {
+    values
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(parse_node)
+        .collect()
+}
+
+pub static CLIENT_IP: Lazy<ClientIpConfig> =
+    Lazy::new(|| ClientIpConfig::from_env().expect("Invalid client address configuration"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        IpAddr::from_str(ip).expect("must succeed")
+    }
+
+    fn config() -> ClientIpConfig {
+        ClientIpConfig::new(
+            vec![
+                IpNetwork::from_str("10.0.0.0/8").expect("must succeed"),
+                IpNetwork::from_str("2001:db8:ffff::/48").expect("must succeed"),
+            ],
+            true,
+        )
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let config = config();
+        // Headers from an untrusted peer are ignored
+        let client = config.client_ip(ip("192.0.2.1"), &[], &["198.51.100.1"]);
+        assert_eq!(client, ip("192.0.2.1"));
+        // The rightmost untrusted hop wins over whatever the client put left of it
+        let client = config.client_ip(
+            ip("10.0.0.1"),
+            &[],
+            &["203.0.113.9, 198.51.100.1", "10.0.0.2"],
+        );
+        assert_eq!(client, ip("198.51.100.1"));
+        let client = config.client_ip(
+            ip("::ffff:10.0.0.1"),
+            &["for=192.0.2.60;proto=http, For=\"[2001:db8:cafe::17]:4711\""],
+            &["198.51.100.1"],
+        );
+        assert_eq!(client, ip("2001:db8:cafe::17"));
+        let client = config.client_ip(ip("2001:db8:ffff::1"), &["for=unknown"], &[]);
+        assert_eq!(client, ip("2001:db8:ffff::1"));
+        let client = config.client_ip(ip("10.0.0.1"), &[], &["2001:db8::1", "10.0.0.2:8080"]);
+        assert_eq!(client, ip("2001:db8::1"));
+    }
+
+    #[test]
+    fn test_display_ip() {
+        let config = config();
+        assert_eq!(config.display_ip(ip("192.0.2.42")), "192.0.2.0/24");
+        assert_eq!(
+            config.display_ip(ip("2001:db8:cafe::17")),
+            "2001:db8:cafe::/48"
+        );
+    }
+
+    #[test]
+    fn test_lookup_network() {
+        let network = |network: &str| IpNetwork::from_str(network).expect("must succeed");
+        let config = config();
+        assert_eq!(
+            config.lookup_network(network("192.0.2.42")),
+            network("192.0.2.0/24")
+        );
+        assert_eq!(
+            config.lookup_network(network("192.0.2.0/28")),
+            network("192.0.2.0/24")
+        );
+        let config = ClientIpConfig::new(vec![], false);
+        assert_eq!(
+            config.lookup_network(network("192.0.2.42")),
+            network("192.0.2.42")
+        );
+    }
+}
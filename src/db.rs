@@ -21,3 +21,29 @@ pub fn create_connection() -> PgConnection {
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     PgConnection::establish(&database_url).expect(&format!("Error connecting to {}", database_url))
 }
+
+#[cfg(test)]
+#[derive(Debug)]
+struct TestTransaction;
+
+#[cfg(test)]
+impl r2d2::CustomizeConnection<PgConnection, r2d2::Error> for TestTransaction {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+        conn.begin_test_transaction()
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// A pool of a single connection inside a transaction that is never committed, so route tests
+/// see their own fixtures and leave nothing behind.
+#[cfg(test)]
+pub fn create_test_connection_pool() -> DbPool {
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let manager = ConnectionManager::<PgConnection>::new(database_url);
+    r2d2::Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(TestTransaction))
+        .build(manager)
+        .expect("Failed to create pool.")
+}
@@ -0,0 +1,41 @@
+//! Line based summary of the difference between two wikitexts
+
+/// Lines added and removed between two versions of a wikitext.
+#[derive(Debug, Default, PartialEq)]
+pub struct DiffSummary {
+    pub added_lines: Vec<String>,
+    pub removed_lines: Vec<String>,
+}
+
+impl DiffSummary {
+    pub fn new(old: &str, new: &str) -> Self {
+        let mut summary = Self::default();
+        for line in diff::lines(old, new) {
+            match line {
+                diff::Result::Left(removed) => summary.removed_lines.push(removed.to_owned()),
+                diff::Result::Right(added) => summary.added_lines.push(added.to_owned()),
+                diff::Result::Both(_, _) => {}
+            }
+        }
+        summary
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_lines.is_empty() && self.removed_lines.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_summary() {
+        let summary = DiffSummary::new("a\nb\nc", "a\nc\nd");
+        assert_eq!(summary.added_lines, vec!["d".to_owned()]);
+        assert_eq!(summary.removed_lines, vec!["b".to_owned()]);
+
+        let summary = DiffSummary::new("same", "same");
+        assert!(summary.is_empty());
+    }
+}
@@ -1,21 +1,42 @@
+use crate::client_ip::CLIENT_IP;
+use actix_web::error::ErrorBadRequest;
+use actix_web::http::header;
 use actix_web::{dev, Error, FromRequest, HttpRequest};
-use futures::future::{ok, Ready};
+use futures::future::{ready, Ready};
 use ipnetwork::IpNetwork;
-use std::str::FromStr;
 
 pub struct ConnectionInfo {
+    /// The client address, as a single address network.
     pub ip_address: IpNetwork,
 }
 
+/// Finds the client address, believing forwarding headers only from trusted proxies.
+pub(crate) fn from_request(req: &HttpRequest) -> Result<ConnectionInfo, Error> {
+    let peer = req
+        .peer_addr()
+        .ok_or_else(|| ErrorBadRequest("Unable to determine the client address"))?;
+    let values = |name| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<&str>>()
+    };
+    let ip = CLIENT_IP.client_ip(
+        peer.ip(),
+        &values(header::FORWARDED),
+        &values(header::HeaderName::from_static("x-forwarded-for")),
+    );
+    Ok(ConnectionInfo {
+        ip_address: IpNetwork::from(ip),
+    })
+}
+
 impl FromRequest for ConnectionInfo {
     type Error = Error;
     type Future = Ready<Result<Self, Error>>;
     type Config = ();
 
     fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
-        let conn_info = req.connection_info();
-        let remote = conn_info.realip_remote_addr().unwrap();
-        let ip_address = IpNetwork::from_str(remote.split(':').collect::<Vec<&str>>()[0]).unwrap();
-        ok(ConnectionInfo { ip_address })
+        ready(from_request(req))
     }
 }
@@ -1,7 +1,8 @@
+use actix_web::error::ErrorInternalServerError;
 use actix_web::{dev, web::Data, Error, FromRequest, HttpRequest};
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::PgConnection;
-use futures::future::{ok, Ready};
+use futures::future::{ready, Ready};
 use std::ops::Deref;
 
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
@@ -19,14 +20,20 @@ impl Deref for DbConnection {
     }
 }
 
+/// Takes a connection from the pool in the app data, for extractors which query the database.
+pub(crate) fn get_connection(req: &HttpRequest) -> Result<Conn, Error> {
+    let pool = req
+        .app_data::<Data<DbPool>>()
+        .ok_or_else(|| ErrorInternalServerError("No database pool configured"))?;
+    pool.get().map_err(ErrorInternalServerError)
+}
+
 impl FromRequest for DbConnection {
     type Error = Error;
     type Future = Ready<Result<Self, Error>>;
     type Config = ();
 
     fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
-        let pool = req.app_data::<Data<DbPool>>().unwrap();
-        let conn = pool.get().unwrap();
-        ok(DbConnection { conn })
+        ready(get_connection(req).map(|conn| DbConnection { conn }))
     }
 }
@@ -1,8 +1,10 @@
 mod connection_info;
 mod db_connection;
 mod query;
+mod rate_limit;
 mod user_info;
 pub use connection_info::ConnectionInfo;
 pub use db_connection::DbConnection;
 pub use query::Query;
+pub use rate_limit::{Creations, Edits, LimitedAction, Logins, RateLimit, RateLimited};
 pub use user_info::UserInfo;
@@ -0,0 +1,143 @@
+//! Rate limit extractor
+
+use super::db_connection::get_connection;
+use super::{connection_info, user_info};
+use crate::models::{Role, User};
+use crate::rate_limit::{Limit, RateLimitAction, RateLimitDecision, RateLimiter};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::{header, StatusCode};
+use actix_web::{dev, web::Data, Error, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use futures::future::{ready, Ready};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// An action whose hits are counted by `RateLimit`.
+pub trait LimitedAction {
+    const ACTION: RateLimitAction;
+}
+
+pub struct Edits;
+pub struct Creations;
+pub struct Logins;
+
+impl LimitedAction for Edits {
+    const ACTION: RateLimitAction = RateLimitAction::Edit;
+}
+
+impl LimitedAction for Creations {
+    const ACTION: RateLimitAction = RateLimitAction::Create;
+}
+
+impl LimitedAction for Logins {
+    const ACTION: RateLimitAction = RateLimitAction::Login;
+}
+
+/// Counts how often the actor of the request did `A`, responding with 429 when too often.
+///
+/// Users are counted by id, anonymous visitors by address. The hit is only counted by `hit`, so
+/// that handlers can leave out requests they reject. Requests are never limited when the app has
+/// no `RateLimiter`.
+pub struct RateLimit<A: LimitedAction> {
+    /// The limiter, the key of the actor and their limit, or `None` if they are not limited.
+    counted: Option<(Data<RateLimiter>, String, Limit)>,
+    action: PhantomData<A>,
+}
+
+impl<A: LimitedAction> RateLimit<A> {
+    /// Counts a hit, responding with 429 when it is over the limit.
+    pub fn hit(&self) -> Result<(), Error> {
+        let (limiter, actor_key, limit) = match &self.counted {
+            Some(counted) => counted,
+            None => return Ok(()),
+        };
+        match limiter
+            .check(A::ACTION, actor_key, limit)
+            .map_err(ErrorInternalServerError)?
+        {
+            RateLimitDecision::Allowed => Ok(()),
+            RateLimitDecision::Limited(retry_after) => Err(RateLimited { retry_after }.into()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimited {
+    retry_after: i64,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Too many requests, retry after {} seconds",
+            self.retry_after
+        )
+    }
+}
+
+impl ResponseError for RateLimited {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::TooManyRequests()
+            .header(header::RETRY_AFTER, self.retry_after.to_string())
+            .body(self.to_string())
+    }
+}
+
+/// The key of the actor of the request and their limit of `action`, or `None` if they are not
+/// limited.
+fn find_limit(
+    req: &HttpRequest,
+    limiter: &RateLimiter,
+    action: RateLimitAction,
+) -> Result<Option<(String, Limit)>, Error> {
+    let (actor_key, limit) = match user_info::from_request(req).ok() {
+        Some(user_info) => {
+            let conn = get_connection(req)?;
+            let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+            let roles = user.get_roles(&conn).map_err(ErrorInternalServerError)?;
+            if roles.contains(&Role::root()) {
+                return Ok(None);
+            }
+            let logged_in = Role::logged_in().name;
+            let mut names = roles
+                .iter()
+                .map(|role| role.name.as_str())
+                .collect::<Vec<&str>>();
+            names.push(logged_in.as_str());
+            (
+                format!("user:{}", user.id),
+                limiter.find_limit(action, &names),
+            )
+        }
+        None => {
+            let ip_address = connection_info::from_request(req)?.ip_address;
+            (
+                format!("ip:{}", ip_address.ip()),
+                limiter.find_limit(action, &[Role::anonymous().name.as_str()]),
+            )
+        }
+    };
+    Ok(limit.map(|limit| (actor_key, limit)))
+}
+
+impl<A: LimitedAction> FromRequest for RateLimit<A> {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
+        let counted = match req.app_data::<Data<RateLimiter>>() {
+            Some(limiter) => find_limit(req, limiter, A::ACTION)
+                .map(|limit| limit.map(|(actor_key, limit)| (limiter.clone(), actor_key, limit))),
+            None => Ok(None),
+        };
+        ready(counted.map(|counted| RateLimit {
+            counted,
+            action: PhantomData,
+        }))
+    }
+}
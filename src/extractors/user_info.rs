@@ -1,9 +1,120 @@
-use actix_web::error::ErrorUnauthorized;
+use super::db_connection::get_connection;
+use crate::models::{ApiToken, Scope, Session, API_TOKEN_PREFIX};
+use crate::routes::error::ApiError;
+use actix_web::error::{ErrorInternalServerError, ErrorUnauthorized};
 use actix_web::{dev, Error, FromRequest, HttpRequest};
-use futures::future::{err, ok, Ready};
+use futures::future::{ready, Ready};
 
+#[derive(Clone)]
 pub struct UserInfo {
     pub id: i32,
+    /// The session the access token was issued for, or `None` for API tokens.
+    pub session_id: Option<i32>,
+    /// The scopes of the API token, or `None` for access tokens, which may do whatever the user
+    /// can.
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl UserInfo {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        match &self.scopes {
+            Some(scopes) => scopes.contains(&scope),
+            None => true,
+        }
+    }
+
+    /// Responds with 403 unless the token has `scope`.
+    pub fn require_scope(&self, scope: Scope) -> Result<(), ApiError> {
+        if self.has_scope(scope) {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden(format!(
+                "Token lacks the {} scope",
+                scope.as_str()
+            )))
+        }
+    }
+
+    /// Responds with 403 for API tokens, which may not manage the account they belong to.
+    pub fn require_session(&self) -> Result<(), ApiError> {
+        if self.is_bot() {
+            Err(ApiError::Forbidden(
+                "Not allowed with an API token".to_owned(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Whether the request was made with an API token, whose edits are marked as bot edits.
+    pub fn is_bot(&self) -> bool {
+        self.scopes.is_some()
+    }
+}
+
+fn from_api_token(req: &HttpRequest, token: &str) -> Result<UserInfo, Error> {
+    let conn = get_connection(req)?;
+    match ApiToken::authenticate(&conn, token).map_err(ErrorInternalServerError)? {
+        Some(api_token) => Ok(UserInfo {
+            id: api_token.user_id,
+            session_id: None,
+            scopes: Some(api_token.scopes),
+        }),
+        None => Err(ErrorUnauthorized("invalid token!")),
+    }
+}
+
+/// Access tokens are only honored while their session is active, so that logging out or
+/// revoking a session takes effect at once.
+fn from_access_token(req: &HttpRequest, user_id: i32, session_id: i32) -> Result<UserInfo, Error> {
+    let conn = get_connection(req)?;
+    if !Session::is_active(&conn, user_id, session_id).map_err(ErrorInternalServerError)? {
+        return Err(ErrorUnauthorized("session is revoked!"));
+    }
+    Ok(UserInfo {
+        id: user_id,
+        session_id: Some(session_id),
+        scopes: None,
+    })
+}
+
+fn authenticate(req: &HttpRequest) -> Result<UserInfo, Error> {
+    let auth_header = req.headers().get("Authorization");
+    match auth_header {
+        Some(auth_header) => {
+            let token = match auth_header
+                .to_str()
+                .ok()
+                .and_then(|header| header.strip_prefix("Bearer "))
+            {
+                Some(token) => token.trim(),
+                None => return Err(ErrorUnauthorized("invalid token!")),
+            };
+            if token.starts_with(API_TOKEN_PREFIX) {
+                return from_api_token(req, token);
+            }
+            match crate::auth::decode(token) {
+                Ok(decoded) if decoded.claims.t == "access" => match decoded.claims.sid {
+                    Some(session_id) => from_access_token(req, decoded.claims.sub, session_id),
+                    // Issued before sessions, so it cannot be revoked
+                    None => Err(ErrorUnauthorized("invalid token!")),
+                },
+                _ => Err(ErrorUnauthorized("invalid token!")),
+            }
+        }
+        None => Err(ErrorUnauthorized("blocked!")),
+    }
+}
+
+/// Authenticates the request, for extractors which need the user. The user is kept in the
+/// request, so a token is only looked up once however many extractors need it.
+pub(crate) fn from_request(req: &HttpRequest) -> Result<UserInfo, Error> {
+    if let Some(user_info) = req.extensions().get::<UserInfo>() {
+        return Ok(user_info.clone());
+    }
+    let user_info = authenticate(req)?;
+    req.extensions_mut().insert(user_info.clone());
+    Ok(user_info)
 }
 
 impl FromRequest for UserInfo {
@@ -12,19 +123,6 @@ impl FromRequest for UserInfo {
     type Config = ();
 
     fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
-        let auth_header = req.headers().get("Authorization");
-        match auth_header {
-            Some(auth_header) => {
-                let split: Vec<&str> = auth_header.to_str().unwrap().split("Bearer").collect();
-                let token = split[1].trim();
-                match crate::auth::decode(token) {
-                    Ok(decoded) => ok(UserInfo {
-                        id: decoded.claims.sub,
-                    }),
-                    Err(_e) => err(ErrorUnauthorized("invalid token!")),
-                }
-            }
-            None => err(ErrorUnauthorized("blocked!")),
-        }
+        ready(from_request(req))
     }
 }
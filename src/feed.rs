@@ -0,0 +1,201 @@
+//! Atom and RSS 2.0 feed rendering
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use htmlescape::encode_minimal;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+impl Default for FeedFormat {
+    fn default() -> Self {
+        FeedFormat::Atom
+    }
+}
+
+impl FeedFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+        }
+    }
+}
+
+pub struct FeedEntry {
+    pub id: String,
+    pub title: String,
+    pub link: String,
+    pub updated: NaiveDateTime,
+    pub author: String,
+    /// HTML; escaped when rendered.
+    pub summary: String,
+}
+
+pub struct Feed {
+    pub title: String,
+    pub description: String,
+    pub link: String,
+    pub self_link: String,
+    pub updated: NaiveDateTime,
+    pub entries: Vec<FeedEntry>,
+}
+
+impl Feed {
+    pub fn render(&self, format: FeedFormat) -> String {
+        match format {
+            FeedFormat::Atom => self.render_atom(),
+            FeedFormat::Rss => self.render_rss(),
+        }
+    }
+
+    pub fn render_atom(&self) -> String {
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    concat!(
+                        "<entry>",
+                        "<id>{}</id>",
+                        "<title>{}</title>",
+                        r#"<link rel="alternate" href="{}"/>"#,
+                        "<updated>{}</updated>",
+                        "<author><name>{}</name></author>",
+                        r#"<summary type="html">{}</summary>"#,
+                        "</entry>"
+                    ),
+                    encode_minimal(&entry.id),
+                    encode_minimal(&entry.title),
+                    encode_minimal(&entry.link),
+                    to_rfc3339(&entry.updated),
+                    encode_minimal(&entry.author),
+                    encode_minimal(&entry.summary),
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("");
+        format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="utf-8"?>"#,
+                r#"<feed xmlns="http://www.w3.org/2005/Atom">"#,
+                "<id>{}</id>",
+                "<title>{}</title>",
+                "<subtitle>{}</subtitle>",
+                r#"<link rel="self" href="{}"/>"#,
+                r#"<link rel="alternate" href="{}"/>"#,
+                "<updated>{}</updated>",
+                "{}",
+                "</feed>"
+            ),
+            encode_minimal(&self.self_link),
+            encode_minimal(&self.title),
+            encode_minimal(&self.description),
+            encode_minimal(&self.self_link),
+            encode_minimal(&self.link),
+            to_rfc3339(&self.updated),
+            entries,
+        )
+    }
+
+    pub fn render_rss(&self) -> String {
+        let items = self
+            .entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    concat!(
+                        "<item>",
+                        "<title>{}</title>",
+                        "<link>{}</link>",
+                        r#"<guid isPermaLink="false">{}</guid>"#,
+                        "<pubDate>{}</pubDate>",
+                        "<dc:creator>{}</dc:creator>",
+                        "<description>{}</description>",
+                        "</item>"
+                    ),
+                    encode_minimal(&entry.title),
+                    encode_minimal(&entry.link),
+                    encode_minimal(&entry.id),
+                    to_rfc2822(&entry.updated),
+                    encode_minimal(&entry.author),
+                    encode_minimal(&entry.summary),
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("");
+        format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="utf-8"?>"#,
+                r#"<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/">"#,
+                "<channel>",
+                "<title>{}</title>",
+                "<link>{}</link>",
+                "<description>{}</description>",
+                "<lastBuildDate>{}</lastBuildDate>",
+                "{}",
+                "</channel>",
+                "</rss>"
+            ),
+            encode_minimal(&self.title),
+            encode_minimal(&self.link),
+            encode_minimal(&self.description),
+            to_rfc2822(&self.updated),
+            items,
+        )
+    }
+}
+
+fn to_rfc3339(datetime: &NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(*datetime, Utc).to_rfc3339()
+}
+
+fn to_rfc2822(datetime: &NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(*datetime, Utc).to_rfc2822()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_feed() -> Feed {
+        let updated = NaiveDateTime::from_timestamp(1_600_000_000, 0);
+        Feed {
+            title: "Recent changes".to_owned(),
+            description: "Track the most recent changes".to_owned(),
+            link: "http://localhost/wiki/".to_owned(),
+            self_link: "http://localhost/recent-changes/feed".to_owned(),
+            updated,
+            entries: vec![FeedEntry {
+                id: "http://localhost/wiki/a?revision=1".to_owned(),
+                title: "a & b".to_owned(),
+                link: "http://localhost/wiki/a".to_owned(),
+                updated,
+                author: "tester".to_owned(),
+                summary: "<p>comment</p>".to_owned(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_render_atom() {
+        let rendered = sample_feed().render(FeedFormat::Atom);
+        assert!(rendered.starts_with(r#"<?xml version="1.0" encoding="utf-8"?><feed"#));
+        assert!(rendered.contains("<title>a &amp; b</title>"));
+        assert!(rendered.contains("<updated>2020-09-13T12:26:40+00:00</updated>"));
+        assert!(rendered.contains(r#"<summary type="html">&lt;p&gt;comment&lt;/p&gt;</summary>"#));
+        assert!(rendered.contains("<author><name>tester</name></author>"));
+    }
+
+    #[test]
+    fn test_render_rss() {
+        let rendered = sample_feed().render(FeedFormat::Rss);
+        assert!(rendered.contains(r#"<rss version="2.0""#));
+        assert!(rendered.contains("<pubDate>Sun, 13 Sep 2020 12:26:40 +0000</pubDate>"));
+        assert!(rendered.contains("<dc:creator>tester</dc:creator>"));
+    }
+}
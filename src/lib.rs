@@ -1,14 +1,22 @@
 #[macro_use]
 extern crate diesel;
 
+pub mod abuse_filter;
 pub mod auth;
+pub mod client_ip;
 pub mod db;
+pub mod diff_summary;
 pub mod extractors;
+pub mod feed;
+pub mod mail;
 pub mod models;
 pub mod parser;
+pub mod rate_limit;
 pub mod renderer;
 pub mod routes;
 pub mod schema;
+pub mod storage;
+pub mod title;
 
 use actix_cors::Cors;
 use actix_web::middleware::errhandlers::{ErrorHandlerResponse, ErrorHandlers};
@@ -31,6 +39,15 @@ pub async fn run() -> std::io::Result<()> {
     std::env::set_var("RUST_BACKTRACE", "1");
     env_logger::init();
     let pool = db::create_connection_pool();
+    // Fail on a bad key or proxy configuration now rather than on the first request
+    once_cell::sync::Lazy::force(&auth::keys::KEYS);
+    once_cell::sync::Lazy::force(&client_ip::CLIENT_IP);
+    let rate_limiter = actix_web::web::Data::new(
+        rate_limit::RateLimiter::from_env(&pool).expect("Invalid rate limit configuration"),
+    );
+    let providers = actix_web::web::Data::new(
+        auth::provider::Providers::from_env().expect("Invalid auth provider configuration"),
+    );
     HttpServer::new(move || {
         App::new()
             .app_data(JsonConfig::default().limit(1024 * 1024 * 50))
@@ -43,14 +60,81 @@ pub async fn run() -> std::io::Result<()> {
                     .finish(),
             )
             .data(pool.clone())
+            .app_data(providers.clone())
+            .app_data(rate_limiter.clone())
             .service(routes::index)
             .service(routes::articles::get_article)
             .service(routes::articles::get_revisions)
             .service(routes::articles::edit_article)
             .service(routes::articles::create_article)
             .service(routes::articles::delete_article)
-            .service(routes::auth::auth_facebook)
+            .service(routes::revisions::get_unpatrolled_revisions)
+            .service(routes::revisions::patrol_revision)
+            .service(routes::revisions::add_revision_tag)
+            .service(routes::revisions::delete_revision_tag)
+            .service(routes::feeds::get_recent_changes_feed)
+            .service(routes::feeds::get_article_feed)
+            .service(routes::blocks::get_blocks)
+            .service(routes::blocks::create_block)
+            .service(routes::blocks::delete_block)
+            .service(routes::abuse_filters::get_abuse_filters)
+            .service(routes::abuse_filters::create_abuse_filter)
+            .service(routes::abuse_filters::test_abuse_filter)
+            .service(routes::abuse_filters::get_abuse_filter)
+            .service(routes::abuse_filters::update_abuse_filter)
+            .service(routes::abuse_filters::delete_abuse_filter)
+            .service(routes::abuse_filters::get_abuse_filter_hits)
+            .service(routes::users::get_me)
+            .service(routes::users::update_me)
+            .service(routes::users::get_user)
+            .service(routes::users::update_user)
+            .service(routes::users::get_user_contributions)
+            .service(routes::users::get_ip_contributions)
+            .service(routes::namespaces::get_namespaces)
+            .service(routes::namespaces::create_namespace)
+            .service(routes::namespaces::rename_namespace)
+            .service(routes::namespaces::update_namespace)
+            .service(routes::namespaces::create_namespace_alias)
+            .service(routes::namespaces::delete_namespace_alias)
+            .service(routes::namespaces::delete_namespace)
+            .service(routes::permissions::get_namespace_permissions)
+            .service(routes::permissions::update_namespace_permission)
+            .service(routes::permissions::get_permission_logs)
+            .service(routes::permissions::explain_article_permissions)
+            .service(routes::permissions::get_article_permissions)
+            .service(routes::permissions::set_article_permission)
+            .service(routes::protections::get_protected_titles)
+            .service(routes::protections::protect_article)
+            .service(routes::protections::unprotect_article)
+            .service(routes::roles::get_roles)
+            .service(routes::roles::create_role)
+            .service(routes::roles::update_role_rights)
+            .service(routes::roles::delete_role)
+            .service(routes::roles::get_role_members)
+            .service(routes::roles::assign_user_role)
+            .service(routes::roles::remove_user_role)
+            .service(routes::auth::register)
+            .service(routes::auth::login)
+            .service(routes::auth::verify_email)
+            .service(routes::auth::resend_verification_email)
+            .service(routes::auth::request_password_reset)
+            .service(routes::auth::confirm_password_reset)
+            .service(routes::auth::get_providers)
+            .service(routes::auth::authorize)
+            .service(routes::auth::provider_callback)
+            .service(routes::auth::get_linked_providers)
+            .service(routes::auth::authorize_link)
+            .service(routes::auth::link_provider_callback)
+            .service(routes::auth::unlink_provider)
             .service(routes::auth::refresh)
+            .service(routes::auth::get_jwks)
+            .service(routes::auth::logout)
+            .service(routes::auth::logout_all)
+            .service(routes::auth::get_sessions)
+            .service(routes::auth::delete_session)
+            .service(routes::api_tokens::get_api_tokens)
+            .service(routes::api_tokens::create_api_token)
+            .service(routes::api_tokens::delete_api_token)
     })
     .bind("127.0.0.1:8088")?
     .run()
@@ -0,0 +1,39 @@
+use anyhow::{anyhow, Result};
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Sends a plain text mail.
+///
+/// The mail is piped to `SENDMAIL_PATH -t` with `MAIL_FROM` as sender. Without `SENDMAIL_PATH`
+/// it is only logged, which is enough for development.
+pub fn send(to: &str, subject: &str, body: &str) -> Result<()> {
+    let sendmail = match env::var("SENDMAIL_PATH") {
+        Ok(sendmail) => sendmail,
+        Err(_) => {
+            log::info!("Mail to {}: {}\n{}", to, subject, body);
+            return Ok(());
+        }
+    };
+    let from = env::var("MAIL_FROM").unwrap_or_else(|_| "wikist@localhost".to_owned());
+    let mut child = Command::new(sendmail)
+        .arg("-t")
+        .stdin(Stdio::piped())
+        .spawn()?;
+    // Dropped after writing so that sendmail sees the end of the message
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("Failed to open sendmail stdin"))?;
+    write!(
+        stdin,
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+        from, to, subject, body
+    )?;
+    drop(stdin);
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("sendmail exited with {}", status));
+    }
+    Ok(())
+}
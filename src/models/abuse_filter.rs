@@ -0,0 +1,511 @@
+use crate::abuse_filter::{FilterVariables, Rule};
+use crate::diff_summary::DiffSummary;
+use crate::models::{AbuseFilterHit, Actor, Article, Namespace, Revision, RevisionKind};
+use crate::schema::abuse_filters;
+use anyhow::{anyhow, Result};
+use chrono::prelude::*;
+use chrono::{Duration, NaiveDateTime};
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Parsed rules with the `updated_at` of their filter, by filter id.
+static RULES: Lazy<Mutex<HashMap<i32, (NaiveDateTime, Arc<Rule>)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// What happens to a change matched by an `AbuseFilter`.
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[sql_type = "Text"]
+pub enum FilterAction {
+    /// The change is saved only once the editor acknowledges the warning.
+    Warn,
+    /// The revision is tagged with the tag of the filter.
+    Tag,
+    /// The change is disallowed once the actor matched the filter too often.
+    Throttle,
+    Disallow,
+}
+
+impl FilterAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterAction::Warn => "warn",
+            FilterAction::Tag => "tag",
+            FilterAction::Throttle => "throttle",
+            FilterAction::Disallow => "disallow",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for FilterAction {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for FilterAction {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"warn" => Ok(FilterAction::Warn),
+            b"tag" => Ok(FilterAction::Tag),
+            b"throttle" => Ok(FilterAction::Throttle),
+            b"disallow" => Ok(FilterAction::Disallow),
+            _ => Err("Unrecognized filter action".into()),
+        }
+    }
+}
+
+/// A rule evaluated on every creation and edit of an article. See `crate::abuse_filter` for the
+/// rule language.
+#[derive(Queryable, Identifiable, Debug, Clone)]
+pub struct AbuseFilter {
+    pub id: i32,
+    pub name: String,
+    pub description: String,
+    pub pattern: String,
+    pub action: FilterAction,
+    /// Added to matching revisions when `action` is `Tag`.
+    pub tag: Option<String>,
+    /// With `Throttle`, matches beyond `throttle_count` within `throttle_period` seconds are
+    /// disallowed.
+    pub throttle_count: Option<i32>,
+    pub throttle_period: Option<i32>,
+    pub enabled: bool,
+    pub actor_id: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "abuse_filters"]
+struct NewAbuseFilter<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub pattern: &'a str,
+    pub action: FilterAction,
+    pub tag: Option<&'a str>,
+    pub throttle_count: Option<i32>,
+    pub throttle_period: Option<i32>,
+    pub enabled: bool,
+    pub actor_id: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Changes to an `AbuseFilter`. `None` keeps the current value.
+#[derive(AsChangeset, Default, Debug)]
+#[table_name = "abuse_filters"]
+pub struct AbuseFilterChanges {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub pattern: Option<String>,
+    pub action: Option<FilterAction>,
+    pub tag: Option<Option<String>>,
+    pub throttle_count: Option<Option<i32>>,
+    pub throttle_period: Option<Option<i32>>,
+    pub enabled: Option<bool>,
+}
+
+/// A creation or edit of an article about to be saved, or a past one.
+pub struct FilteredChange<'a> {
+    pub actor: &'a Actor,
+    pub namespace: &'a Namespace,
+    pub title: &'a str,
+    pub kind: RevisionKind,
+    /// Empty for a creation.
+    pub old_wikitext: &'a str,
+    pub new_wikitext: &'a str,
+    pub summary: &'a str,
+    pub at: NaiveDateTime,
+}
+
+impl<'a> FilteredChange<'a> {
+    pub fn variables(&self, conn: &PgConnection) -> Result<FilterVariables> {
+        let diff = DiffSummary::new(self.old_wikitext, self.new_wikitext);
+        let account_age = match self.actor.user_id {
+            Some(_) => (self.at - self.actor.get_user(conn)?.created_at).num_seconds(),
+            None => 0,
+        };
+        Ok(FilterVariables {
+            action: self.kind.as_str().to_owned(),
+            namespace: if self.namespace.is_default() {
+                String::new()
+            } else {
+                self.namespace.name.clone()
+            },
+            title: self.title.to_owned(),
+            summary: self.summary.to_owned(),
+            added_lines: diff.added_lines,
+            removed_lines: diff.removed_lines,
+            new_size: self.new_wikitext.len() as i64,
+            size_delta: self.new_wikitext.len() as i64 - self.old_wikitext.len() as i64,
+            edit_count: Revision::count_by_actor(conn, self.actor, self.at)?,
+            account_age,
+            is_anonymous: self.actor.user_id.is_none(),
+        })
+    }
+
+    /// The variables of the change which made `revision`, as they were when it was made.
+    pub fn variables_of_revision(
+        conn: &PgConnection,
+        revision: &Revision,
+    ) -> Result<FilterVariables> {
+        let article = Article::find_by_id(conn, revision.article_id)?
+            .ok_or_else(|| anyhow!("No article found with id: {}", revision.article_id))?;
+        let old_wikitext = match revision.get_parent(conn)? {
+            Some(parent) => parent.get_wikitext(conn)?,
+            None => String::new(),
+        };
+        let actor = revision.get_actor(conn)?;
+        let namespace = article.get_namespace(conn)?;
+        let new_wikitext = revision.get_wikitext(conn)?;
+        let change = FilteredChange {
+            actor: &actor,
+            namespace: &namespace,
+            title: &article.title,
+            kind: revision.kind,
+            old_wikitext: &old_wikitext,
+            new_wikitext: &new_wikitext,
+            summary: &revision.comment,
+            at: revision.created_at,
+        };
+        change.variables(conn)
+    }
+}
+
+/// What the enabled filters decided about a change.
+#[derive(Debug, Default)]
+pub struct FilterOutcome {
+    /// Matching filters which warn.
+    pub warnings: Vec<AbuseFilter>,
+    /// Tags to add to the revision.
+    pub tags: Vec<String>,
+    /// The first matching filter which disallows the change.
+    pub disallowed_by: Option<AbuseFilter>,
+}
+
+impl AbuseFilter {
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        conn: &PgConnection,
+        name: &str,
+        description: &str,
+        pattern: &str,
+        action: FilterAction,
+        tag: Option<&str>,
+        throttle_count: Option<i32>,
+        throttle_period: Option<i32>,
+        enabled: bool,
+        actor: &Actor,
+    ) -> Result<Self> {
+        let now = Utc::now().naive_utc();
+        let filter = diesel::insert_into(abuse_filters::table)
+            .values(NewAbuseFilter {
+                name,
+                description,
+                pattern,
+                action,
+                tag,
+                throttle_count,
+                throttle_period,
+                enabled,
+                actor_id: actor.id,
+                created_at: now,
+                updated_at: now,
+            })
+            .get_result::<Self>(conn)?;
+        Ok(filter)
+    }
+
+    pub fn find_by_id(conn: &PgConnection, id: i32) -> Result<Option<Self>> {
+        let filter = abuse_filters::table
+            .find(id)
+            .first::<Self>(conn)
+            .optional()?;
+        Ok(filter)
+    }
+
+    /// Every filter, oldest first.
+    pub fn list(conn: &PgConnection) -> Result<Vec<Self>> {
+        let filters = abuse_filters::table
+            .order(abuse_filters::id.asc())
+            .load::<Self>(conn)?;
+        Ok(filters)
+    }
+
+    pub fn update(&mut self, conn: &PgConnection, changes: &AbuseFilterChanges) -> Result<()> {
+        *self = diesel::update(abuse_filters::table.find(self.id))
+            .set((
+                changes,
+                abuse_filters::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result::<Self>(conn)?;
+        Ok(())
+    }
+
+    /// Deletes the filter `id` with its hits. Returns whether it existed.
+    pub fn delete(conn: &PgConnection, id: i32) -> Result<bool> {
+        let count = diesel::delete(abuse_filters::table.find(id)).execute(conn)?;
+        Ok(count > 0)
+    }
+
+    /// The parsed rule, reused until the filter is updated.
+    pub fn get_rule(&self) -> Result<Arc<Rule>> {
+        let mut rules = RULES
+            .lock()
+            .map_err(|_| anyhow!("Abuse filter rules are poisoned"))?;
+        if let Some((updated_at, rule)) = rules.get(&self.id) {
+            if *updated_at == self.updated_at {
+                return Ok(rule.clone());
+            }
+        }
+        let rule = Arc::new(Rule::parse(&self.pattern)?);
+        rules.insert(self.id, (self.updated_at, rule.clone()));
+        Ok(rule)
+    }
+
+    pub fn get_actor(&self, conn: &PgConnection) -> Result<Actor> {
+        use crate::schema::actors;
+        let actor = actors::table.find(self.actor_id).first::<Actor>(conn)?;
+        Ok(actor)
+    }
+
+    /// Evaluates the enabled filters on `change`, recording a hit of every matching one.
+    ///
+    /// Nothing is recorded when the change is only held back by warnings the editor has yet to
+    /// acknowledge, as it is counted once they resubmit it with `acknowledge_warnings`.
+    ///
+    /// Patterns are validated when saved, but a filter which fails to parse or evaluate, e.g.
+    /// comparing a string with an integer, is treated as not matching rather than blocking every
+    /// change.
+    pub fn check(
+        conn: &PgConnection,
+        change: &FilteredChange,
+        acknowledge_warnings: bool,
+    ) -> Result<FilterOutcome> {
+        let filters = abuse_filters::table
+            .filter(abuse_filters::enabled.eq(true))
+            .order(abuse_filters::id.asc())
+            .load::<Self>(conn)?;
+        if let Ok(mut rules) = RULES.lock() {
+            rules.retain(|id, _| filters.iter().any(|filter| filter.id == *id));
+        }
+        let mut outcome = FilterOutcome::default();
+        if filters.is_empty() {
+            return Ok(outcome);
+        }
+        let variables = change.variables(conn)?;
+        let matched_filters = filters
+            .into_iter()
+            .filter(|filter| {
+                filter
+                    .get_rule()
+                    .and_then(|rule| rule.matches(&variables))
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<Self>>();
+        for filter in matched_filters.iter() {
+            match filter.action {
+                FilterAction::Warn => outcome.warnings.push(filter.clone()),
+                FilterAction::Tag => {
+                    if let Some(tag) = &filter.tag {
+                        if !outcome.tags.contains(tag) {
+                            outcome.tags.push(tag.clone());
+                        }
+                    }
+                }
+                FilterAction::Throttle => {
+                    let (count, period) = match (filter.throttle_count, filter.throttle_period) {
+                        (Some(count), Some(period)) => (count, period),
+                        _ => continue,
+                    };
+                    // Not counting this hit, which is recorded below
+                    let hits = AbuseFilterHit::count_recent(
+                        conn,
+                        filter,
+                        change.actor,
+                        Duration::seconds(i64::from(period)),
+                    )?;
+                    if hits >= i64::from(count) && outcome.disallowed_by.is_none() {
+                        outcome.disallowed_by = Some(filter.clone());
+                    }
+                }
+                FilterAction::Disallow => {
+                    if outcome.disallowed_by.is_none() {
+                        outcome.disallowed_by = Some(filter.clone());
+                    }
+                }
+            }
+        }
+        let held_back = outcome.disallowed_by.is_none()
+            && !outcome.warnings.is_empty()
+            && !acknowledge_warnings;
+        if !held_back {
+            for filter in matched_filters.iter() {
+                AbuseFilterHit::record(
+                    conn,
+                    filter,
+                    change.actor,
+                    change.namespace,
+                    change.title,
+                    change.at,
+                )?;
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use ipnetwork::IpNetwork;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_check() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("192.0.2.7").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let namespace = Namespace::get_default(&conn).expect("must succeed");
+            AbuseFilter::create(
+                &conn,
+                "Blanking",
+                "Removes most of an article",
+                "size_delta < -100 & is_anonymous",
+                FilterAction::Tag,
+                Some("blanking"),
+                None,
+                None,
+                true,
+                &actor,
+            )
+            .expect("must succeed");
+            let spam = AbuseFilter::create(
+                &conn,
+                "Spam",
+                "",
+                "added_lines irlike 'cheap pills'",
+                FilterAction::Throttle,
+                None,
+                Some(1),
+                Some(3600),
+                true,
+                &actor,
+            )
+            .expect("must succeed");
+            let old_wikitext = "a".repeat(200);
+            let change = FilteredChange {
+                actor: &actor,
+                namespace: &namespace,
+                title: "Filtered",
+                kind: RevisionKind::Edit,
+                old_wikitext: &old_wikitext,
+                new_wikitext: "Cheap pills",
+                summary: "",
+                at: Utc::now().naive_utc(),
+            };
+            let outcome = AbuseFilter::check(&conn, &change, false).expect("must succeed");
+            assert_eq!(outcome.tags, vec!["blanking".to_owned()]);
+            assert!(outcome.disallowed_by.is_none());
+            let outcome = AbuseFilter::check(&conn, &change, false).expect("must succeed");
+            assert_eq!(outcome.disallowed_by.map(|filter| filter.id), Some(spam.id));
+            let hits = AbuseFilterHit::find_by_filter(&conn, &spam, 0, 10).expect("must succeed");
+            assert_eq!(hits.len(), 2);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_variables_main_namespace() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("192.0.2.9").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let namespace = Namespace::get_default(&conn).expect("must succeed");
+            let change = FilteredChange {
+                actor: &actor,
+                namespace: &namespace,
+                title: "Main",
+                kind: RevisionKind::Create,
+                old_wikitext: "",
+                new_wikitext: "asdf",
+                summary: "",
+                at: Utc::now().naive_utc(),
+            };
+            let variables = change.variables(&conn).expect("must succeed");
+            let rule = Rule::parse("namespace == \"\"").expect("must succeed");
+            assert!(rule.matches(&variables).expect("must succeed"));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_check_warning() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("192.0.2.8").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let namespace = Namespace::get_default(&conn).expect("must succeed");
+            let mut shouting = AbuseFilter::create(
+                &conn,
+                "Shouting",
+                "",
+                "added_lines rlike '^[A-Z ]+$'",
+                FilterAction::Warn,
+                None,
+                None,
+                None,
+                true,
+                &actor,
+            )
+            .expect("must succeed");
+            let change = FilteredChange {
+                actor: &actor,
+                namespace: &namespace,
+                title: "Filtered",
+                kind: RevisionKind::Create,
+                old_wikitext: "",
+                new_wikitext: "HELLO WORLD",
+                summary: "",
+                at: Utc::now().naive_utc(),
+            };
+            for acknowledge_warnings in &[false, true] {
+                let outcome = AbuseFilter::check(&conn, &change, *acknowledge_warnings)
+                    .expect("must succeed");
+                assert_eq!(
+                    outcome
+                        .warnings
+                        .iter()
+                        .map(|filter| filter.id)
+                        .collect::<Vec<i32>>(),
+                    vec![shouting.id]
+                );
+            }
+            // Only the acknowledged resubmission is recorded
+            let hits =
+                AbuseFilterHit::find_by_filter(&conn, &shouting, 0, 10).expect("must succeed");
+            assert_eq!(hits.len(), 1);
+
+            // The cached rule is replaced once the pattern changes
+            let changes = AbuseFilterChanges {
+                pattern: Some("added_lines rlike 'never matches'".to_owned()),
+                ..AbuseFilterChanges::default()
+            };
+            shouting.update(&conn, &changes).expect("must succeed");
+            let outcome = AbuseFilter::check(&conn, &change, false).expect("must succeed");
+            assert!(outcome.warnings.is_empty());
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,91 @@
+use crate::models::{AbuseFilter, Actor, FilterAction, Namespace};
+use crate::schema::abuse_filter_hits;
+use anyhow::Result;
+use chrono::prelude::*;
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+
+/// A change matched by an `AbuseFilter`, whether or not it was saved.
+#[derive(Queryable, Identifiable, Associations, Debug)]
+#[belongs_to(AbuseFilter, foreign_key = "filter_id")]
+pub struct AbuseFilterHit {
+    pub id: i32,
+    pub filter_id: i32,
+    pub actor_id: i32,
+    pub namespace_id: i32,
+    pub title: String,
+    /// The action of the filter at the time.
+    pub action: FilterAction,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "abuse_filter_hits"]
+struct NewAbuseFilterHit<'a> {
+    pub filter_id: i32,
+    pub actor_id: i32,
+    pub namespace_id: i32,
+    pub title: &'a str,
+    pub action: FilterAction,
+    pub created_at: NaiveDateTime,
+}
+
+impl AbuseFilterHit {
+    pub fn record(
+        conn: &PgConnection,
+        filter: &AbuseFilter,
+        actor: &Actor,
+        namespace: &Namespace,
+        title: &str,
+        at: NaiveDateTime,
+    ) -> Result<Self> {
+        let hit = diesel::insert_into(abuse_filter_hits::table)
+            .values(NewAbuseFilterHit {
+                filter_id: filter.id,
+                actor_id: actor.id,
+                namespace_id: namespace.id,
+                title,
+                action: filter.action,
+                created_at: at,
+            })
+            .get_result::<Self>(conn)?;
+        Ok(hit)
+    }
+
+    /// Hits of `filter` by `actor` within `period` before now.
+    pub fn count_recent(
+        conn: &PgConnection,
+        filter: &AbuseFilter,
+        actor: &Actor,
+        period: Duration,
+    ) -> Result<i64> {
+        let since = Utc::now().naive_utc() - period;
+        let count = Self::belonging_to(filter)
+            .filter(abuse_filter_hits::actor_id.eq(actor.id))
+            .filter(abuse_filter_hits::created_at.gt(since))
+            .count()
+            .get_result::<i64>(conn)?;
+        Ok(count)
+    }
+
+    /// Hits of `filter`, newest first.
+    pub fn find_by_filter(
+        conn: &PgConnection,
+        filter: &AbuseFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>> {
+        let hits = Self::belonging_to(filter)
+            .order(abuse_filter_hits::id.desc())
+            .offset(offset)
+            .limit(limit)
+            .load::<Self>(conn)?;
+        Ok(hits)
+    }
+
+    pub fn get_actor(&self, conn: &PgConnection) -> Result<Actor> {
+        use crate::schema::actors;
+        let actor = actors::table.find(self.actor_id).first::<Actor>(conn)?;
+        Ok(actor)
+    }
+}
@@ -2,9 +2,12 @@ use crate::models::User;
 use crate::schema::{actors, users};
 use anyhow::{anyhow, Result};
 use diesel::prelude::*;
+use diesel::sql_types::Cidr;
 use ipnetwork::IpNetwork;
 use serde::Serialize;
 
+diesel_infix_operator!(ContainedByOrEquals, " <<= ");
+
 #[derive(Serialize, Queryable, Associations, Identifiable, Debug)]
 #[belongs_to(User)]
 pub struct Actor {
@@ -21,12 +24,34 @@ struct NewActor {
 }
 
 impl Actor {
-    pub fn find_or_create_from_user_id(conn: &PgConnection, user_id: i32) -> Result<Self> {
-        use diesel::result::{DatabaseErrorKind, Error};
+    pub fn find_by_user_id(conn: &PgConnection, user_id: i32) -> Result<Option<Self>> {
         let actor = actors::table
             .filter(actors::user_id.eq(user_id))
             .first::<Actor>(conn)
             .optional()?;
+        Ok(actor)
+    }
+    pub fn find_by_ip(conn: &PgConnection, ip_address: &IpNetwork) -> Result<Option<Self>> {
+        let actor = actors::table
+            .filter(actors::ip_address.eq(ip_address))
+            .first::<Actor>(conn)
+            .optional()?;
+        Ok(actor)
+    }
+    /// Anonymous actors whose address is within `network`.
+    pub fn find_in_network(conn: &PgConnection, network: &IpNetwork) -> Result<Vec<Self>> {
+        let actors = actors::table
+            .filter(ContainedByOrEquals::new(
+                actors::ip_address,
+                (*network).into_sql::<Cidr>(),
+            ))
+            .order(actors::id.asc())
+            .load::<Actor>(conn)?;
+        Ok(actors)
+    }
+    pub fn find_or_create_from_user_id(conn: &PgConnection, user_id: i32) -> Result<Self> {
+        use diesel::result::{DatabaseErrorKind, Error};
+        let actor = Self::find_by_user_id(conn, user_id)?;
         match actor {
             Some(actor) => return Ok(actor),
             None => {}
@@ -51,10 +76,7 @@ impl Actor {
     }
     pub fn find_or_create_from_ip(conn: &PgConnection, ip_address: &IpNetwork) -> Result<Self> {
         use diesel::result::{DatabaseErrorKind, Error};
-        let actor = actors::table
-            .filter(actors::ip_address.eq(ip_address))
-            .first::<Actor>(conn)
-            .optional()?;
+        let actor = Self::find_by_ip(conn, ip_address)?;
         match actor {
             Some(actor) => return Ok(actor),
             None => {}
@@ -116,4 +138,24 @@ mod tests {
         )
         .expect("must succeed");
     }
+
+    #[test]
+    fn test_find_in_network() {
+        use ipnetwork::IpNetwork;
+        use std::str::FromStr;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let network = |network: &str| IpNetwork::from_str(network).expect("must succeed");
+            let inside = Actor::find_or_create_from_ip(&conn, &network("198.51.100.7"))
+                .expect("must succeed");
+            Actor::find_or_create_from_ip(&conn, &network("198.51.101.7")).expect("must succeed");
+            let actors =
+                Actor::find_in_network(&conn, &network("198.51.100.0/24")).expect("must succeed");
+            assert_eq!(
+                actors.iter().map(|actor| actor.id).collect::<Vec<i32>>(),
+                vec![inside.id]
+            );
+            Ok(())
+        });
+    }
 }
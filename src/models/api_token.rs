@@ -0,0 +1,198 @@
+use crate::auth::hash_token;
+use crate::models::User;
+use crate::schema::api_tokens;
+use anyhow::Result;
+use chrono::prelude::*;
+use chrono::{Duration, NaiveDateTime};
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// Marks API tokens, so that they are told apart from JWTs.
+pub const API_TOKEN_PREFIX: &str = "wikist_";
+/// `last_used_at` is only updated when it is older than this many minutes, to spare a write on
+/// every request of a busy bot.
+const LAST_USED_PRECISION_MINUTES: i64 = 5;
+
+/// What an `ApiToken` may be used for.
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[sql_type = "Text"]
+pub enum Scope {
+    /// Reading articles and their history.
+    Read,
+    /// Creating, editing and renaming articles.
+    Edit,
+    Delete,
+    /// Managing namespaces, roles, permissions and protections.
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Edit => "edit",
+            Scope::Delete => "delete",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for Scope {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for Scope {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"read" => Ok(Scope::Read),
+            b"edit" => Ok(Scope::Edit),
+            b"delete" => Ok(Scope::Delete),
+            b"admin" => Ok(Scope::Admin),
+            _ => Err("Unrecognized scope".into()),
+        }
+    }
+}
+
+/// A long-lived token for bots, acting as its user within its scopes.
+#[derive(Queryable, Identifiable, Associations, Debug)]
+#[belongs_to(User)]
+pub struct ApiToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "api_tokens"]
+struct NewApiToken<'a> {
+    pub user_id: i32,
+    pub name: &'a str,
+    pub token_hash: &'a str,
+    pub scopes: &'a [Scope],
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl ApiToken {
+    /// Creates a token named `name`. Returns it with the token, of which only the hash is kept.
+    pub fn create(
+        conn: &PgConnection,
+        user: &User,
+        name: &str,
+        scopes: &[Scope],
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<(Self, String)> {
+        let token = format!("{}{}", API_TOKEN_PREFIX, crate::auth::generate_token());
+        let api_token = diesel::insert_into(api_tokens::table)
+            .values(NewApiToken {
+                user_id: user.id,
+                name,
+                token_hash: &hash_token(&token),
+                scopes,
+                expires_at,
+                created_at: Utc::now().naive_utc(),
+            })
+            .get_result::<Self>(conn)?;
+        Ok((api_token, token))
+    }
+
+    pub fn find_by_name(conn: &PgConnection, user: &User, name: &str) -> Result<Option<Self>> {
+        let api_token = Self::belonging_to(user)
+            .filter(api_tokens::name.eq(name))
+            .first::<Self>(conn)
+            .optional()?;
+        Ok(api_token)
+    }
+
+    pub fn find_by_user(conn: &PgConnection, user: &User) -> Result<Vec<Self>> {
+        let api_tokens = Self::belonging_to(user)
+            .order_by(api_tokens::created_at.asc())
+            .load::<Self>(conn)?;
+        Ok(api_tokens)
+    }
+
+    /// Returns the unexpired token `token`, recording that it was used.
+    pub fn authenticate(conn: &PgConnection, token: &str) -> Result<Option<Self>> {
+        let now = Utc::now().naive_utc();
+        let api_token = api_tokens::table
+            .filter(api_tokens::token_hash.eq(hash_token(token)))
+            .filter(
+                api_tokens::expires_at
+                    .is_null()
+                    .or(api_tokens::expires_at.gt(now)),
+            )
+            .first::<Self>(conn)
+            .optional()?;
+        let api_token = match api_token {
+            Some(api_token) => api_token,
+            None => return Ok(None),
+        };
+        let used_recently = api_token.last_used_at.map_or(false, |last_used_at| {
+            now - last_used_at < Duration::minutes(LAST_USED_PRECISION_MINUTES)
+        });
+        if used_recently {
+            return Ok(Some(api_token));
+        }
+        let api_token = diesel::update(&api_token)
+            .set(api_tokens::last_used_at.eq(now))
+            .get_result::<Self>(conn)?;
+        Ok(Some(api_token))
+    }
+
+    /// Deletes the token `id` of the user `user_id`. Returns whether it existed.
+    pub fn delete(conn: &PgConnection, user_id: i32, id: i32) -> Result<bool> {
+        let count = diesel::delete(
+            api_tokens::table
+                .find(id)
+                .filter(api_tokens::user_id.eq(user_id)),
+        )
+        .execute(conn)?;
+        Ok(count > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+
+    #[test]
+    fn test_authenticate() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let user = User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            let (api_token, token) =
+                ApiToken::create(&conn, &user, "bot", &[Scope::Read, Scope::Edit], None)
+                    .expect("must succeed");
+            assert!(token.starts_with(API_TOKEN_PREFIX));
+            let authenticated = ApiToken::authenticate(&conn, &token)
+                .expect("must succeed")
+                .expect("must exist");
+            assert_eq!(authenticated.id, api_token.id);
+            assert_eq!(authenticated.scopes, vec![Scope::Read, Scope::Edit]);
+            assert!(authenticated.last_used_at.is_some());
+
+            let expired_at = Utc::now().naive_utc() - Duration::days(1);
+            let (_, token) =
+                ApiToken::create(&conn, &user, "expired", &[Scope::Read], Some(expired_at))
+                    .expect("must succeed");
+            assert!(ApiToken::authenticate(&conn, &token)
+                .expect("must succeed")
+                .is_none());
+            Ok(())
+        });
+    }
+}
@@ -1,4 +1,7 @@
-use crate::models::{Actor, ArticleSearch, Namespace, NewRevision, Redirection, Revision};
+use crate::models::{
+    Actor, ArticleSearch, Namespace, NewRevision, Protection, Redirection, Revision, RevisionKind,
+    RevisionPayload, RevisionTag, TemplateLink,
+};
 use crate::schema::articles;
 use anyhow::{anyhow, Result};
 use chrono::prelude::*;
@@ -33,7 +36,9 @@ impl Article {
         wikitext: &str,
         comment: &str,
         actor: &Actor,
+        is_bot: bool,
     ) -> Result<Self> {
+        let title = &namespace.normalize_title(title);
         if let Some(_) = Self::find(conn, namespace, title)? {
             return Err(anyhow!(
                 "Article {} already exists",
@@ -49,9 +54,18 @@ impl Article {
             let mut article = diesel::insert_into(articles::table)
                 .values(new_article)
                 .get_result::<Article>(conn)?;
-            let revision = Revision::create(conn, &article, wikitext, comment, actor)?;
+            let revision = Revision::create(
+                conn,
+                &article,
+                wikitext,
+                comment,
+                RevisionKind::Create,
+                actor,
+                is_bot,
+            )?;
             article.set_latest_revision(conn, &revision)?;
             ArticleSearch::create(conn, &article)?;
+            TemplateLink::update(conn, &article, wikitext)?;
             Ok(article)
         })
     }
@@ -59,13 +73,19 @@ impl Article {
     pub fn find(conn: &PgConnection, namespace: &Namespace, title: &str) -> Result<Option<Self>> {
         let article = articles::table
             .filter(articles::namespace_id.eq(namespace.id))
-            .filter(articles::title.eq(title))
+            .filter(articles::title.eq(namespace.normalize_title(title)))
             .filter(articles::is_active.eq(true))
             .first::<Article>(conn)
             .optional()?;
         Ok(article)
     }
 
+    /// Finds an `Article` by id, including inactive ones.
+    pub fn find_by_id(conn: &PgConnection, id: i32) -> Result<Option<Self>> {
+        let article = articles::table.find(id).first::<Article>(conn).optional()?;
+        Ok(article)
+    }
+
     pub fn find_by_full_title(conn: &PgConnection, full_title: &str) -> Result<Option<Self>> {
         let (namespace, title) = Namespace::parse_full_title(conn, full_title)?;
         Self::find(conn, &namespace, &title)
@@ -75,6 +95,22 @@ impl Article {
         Ok(Namespace::find_by_id(conn, self.namespace_id)?.unwrap())
     }
 
+    /// Finds the page discussing this article, if its namespace has a talk namespace.
+    pub fn get_talk_page(&self, conn: &PgConnection) -> Result<Option<Self>> {
+        match self.get_namespace(conn)?.get_talk(conn)? {
+            Some(talk) => Self::find(conn, &talk, &self.title),
+            None => Ok(None),
+        }
+    }
+
+    /// Finds the article discussed in this talk page.
+    pub fn get_subject_page(&self, conn: &PgConnection) -> Result<Option<Self>> {
+        match self.get_namespace(conn)?.get_subject(conn)? {
+            Some(subject) => Self::find(conn, &subject, &self.title),
+            None => Ok(None),
+        }
+    }
+
     pub fn get_full_title(&self, conn: &PgConnection) -> Result<String> {
         let namespace = self.get_namespace(conn)?;
         Ok(namespace.join(&self.title))
@@ -86,11 +122,15 @@ impl Article {
         Ok(crate::renderer::render(conn, &parsed))
     }
 
+    /// Create a `Revision` which keeps the current content, recording `kind` and `payload`.
     pub fn add_null_revision(
         &mut self,
         conn: &PgConnection,
+        kind: RevisionKind,
+        payload: &RevisionPayload,
         comment: &str,
         actor: &Actor,
+        is_bot: bool,
     ) -> Result<Revision> {
         use crate::schema::revisions;
         conn.transaction(|| {
@@ -102,6 +142,11 @@ impl Article {
                 content_id: content.id,
                 comment,
                 created_at: now,
+                kind,
+                payload: Some(serde_json::to_value(payload)?),
+                is_bot,
+                patrolled_by: None,
+                patrolled_at: None,
             };
             let revision = diesel::insert_into(revisions::table)
                 .values(new_revision)
@@ -118,34 +163,60 @@ impl Article {
         wikitext: &str,
         comment: &str,
         actor: &Actor,
+        is_bot: bool,
     ) -> Result<Revision> {
         conn.transaction(|| {
-            let revision = Revision::create(conn, self, wikitext, comment, actor)?;
+            let revision = Revision::create(
+                conn,
+                self,
+                wikitext,
+                comment,
+                RevisionKind::Edit,
+                actor,
+                is_bot,
+            )?;
             self.set_latest_revision(conn, &revision)?;
             ArticleSearch::update(conn, self)?;
+            TemplateLink::update(conn, self, wikitext)?;
+            Protection::refresh_cascades(conn, self)?;
             Ok(revision)
         })
     }
 
-    /// Change the title.
+    /// Change the title, moving the article to `namespace` with its protections and the links
+    /// transcluding it.
     ///
     /// Creates a null revision.
     pub fn rename(
         &mut self,
         conn: &PgConnection,
+        namespace: &Namespace,
         title: &str,
         comment: &str,
         actor: &Actor,
+        is_bot: bool,
     ) -> Result<Revision> {
+        let title = &namespace.normalize_title(title);
+        if let Some(_) = Self::find(conn, namespace, title)? {
+            return Err(anyhow!(
+                "Article {} already exists",
+                Namespace::join(namespace, title)
+            ));
+        }
         conn.transaction(|| {
-            let old_title = self.title.clone();
-            self.title = title.to_owned();
+            let old_full_title = self.get_full_title(conn)?;
+            let old_namespace_id = self.namespace_id;
+            let old_title = std::mem::replace(&mut self.title, title.to_owned());
+            self.namespace_id = namespace.id;
             self.save_changes::<Self>(conn)?;
-            self.add_null_revision(
-                conn,
-                &format!("(Rename: {} -> {}) {}", old_title, title, comment),
-                actor,
-            )
+            Protection::follow_rename(conn, old_namespace_id, &old_title, self)?;
+            TemplateLink::follow_rename(conn, old_namespace_id, &old_title, self)?;
+            let payload = RevisionPayload {
+                old_title: Some(old_full_title),
+                new_title: Some(namespace.join(title)),
+                ..Default::default()
+            };
+            self.add_null_revision(conn, RevisionKind::Rename, &payload, comment, actor, is_bot)
         })
     }
 
@@ -157,14 +228,22 @@ impl Article {
         conn: &PgConnection,
         comment: &str,
         actor: &Actor,
+        is_bot: bool,
     ) -> Result<Revision> {
         conn.transaction(|| {
             self.is_active = false;
             self.save_changes::<Self>(conn)?;
+            let payload = RevisionPayload {
+                old_title: Some(self.get_full_title(conn)?),
+                ..Default::default()
+            };
             let revision = self.add_null_revision(
                 conn,
-                &format!("(Delete: {}) {}", self.title, comment),
+                RevisionKind::Delete,
+                &payload,
+                comment,
                 actor,
+                is_bot,
             )?;
             ArticleSearch::delete(conn, self)?;
             Ok(revision)
@@ -202,8 +281,9 @@ impl Article {
         title: &str,
         comment: &str,
         actor: &Actor,
+        is_bot: bool,
     ) -> Result<Self> {
-        use crate::schema::revisions;
+        use crate::schema::{revision_tags, revisions};
         conn.transaction(|| {
             let new_article = NewArticle {
                 namespace_id: namespace.id,
@@ -222,19 +302,50 @@ impl Article {
                     content_id: rev.content_id,
                     comment: &rev.comment,
                     created_at: rev.created_at,
+                    kind: rev.kind,
+                    payload: rev.payload.clone(),
+                    is_bot: rev.is_bot,
+                    patrolled_by: rev.patrolled_by,
+                    patrolled_at: rev.patrolled_at,
                 })
                 .collect::<Vec<NewRevision>>();
             let copied_revisions = diesel::insert_into(revisions::table)
                 .values(new_revisions)
                 .get_results::<Revision>(conn)?;
+            let mut tags = RevisionTag::find_by_revisions(conn, &revisions)?;
+            let new_tags = revisions
+                .iter()
+                .zip(copied_revisions.iter())
+                .flat_map(|(rev, copied)| {
+                    tags.remove(&rev.id)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(move |name| RevisionTag {
+                            revision_id: copied.id,
+                            name,
+                        })
+                })
+                .collect::<Vec<RevisionTag>>();
+            diesel::insert_into(revision_tags::table)
+                .values(&new_tags)
+                .execute(conn)?;
             let latest_rev = &copied_revisions.last().unwrap();
             article.set_latest_revision(conn, latest_rev)?;
+            let payload = RevisionPayload {
+                new_title: Some(namespace.join(title)),
+                fork_source: Some(self.get_full_title(conn)?),
+                ..Default::default()
+            };
             article.add_null_revision(
                 conn,
-                &format!("(Fork: {} -> {}) {}", self.title, title, comment),
+                RevisionKind::Fork,
+                &payload,
+                comment,
                 actor,
+                is_bot,
             )?;
             ArticleSearch::create(conn, &article)?;
+            TemplateLink::update(conn, &article, &latest_rev.get_wikitext(conn)?)?;
             Ok(article)
         })
     }
@@ -246,8 +357,9 @@ impl Article {
         title: &str,
         comment: &str,
         actor: &Actor,
+        is_bot: bool,
     ) -> Result<(Redirection, Revision)> {
-        Redirection::create(conn, self, namespace, title, comment, actor)
+        Redirection::create(conn, self, namespace, title, comment, actor, is_bot)
     }
 
     fn set_latest_revision(&mut self, conn: &PgConnection, revision: &Revision) -> Result<()> {
@@ -274,11 +386,12 @@ mod tests {
             let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
             Article::create(
                 &conn,
-                &Namespace::default(),
+                &Namespace::get_default(&conn).expect("must succeed"),
                 "test",
                 "==test create article==",
                 "Comment!",
                 &actor,
+                false,
             )
             .expect("must succeed");
             articles::table
@@ -303,15 +416,16 @@ mod tests {
             let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
             let mut article = Article::create(
                 &conn,
-                &Namespace::default(),
+                &Namespace::get_default(&conn).expect("must succeed"),
                 "test",
                 "==test==",
                 "Comment!",
                 &actor,
+                false,
             )
             .expect("must succeed");
             article
-                .edit(&conn, "==test-edit==", "Comment!", &actor)
+                .edit(&conn, "==test-edit==", "Comment!", &actor, false)
                 .expect("must succeed");
             let article = articles::table
                 .filter(articles::title.eq("test"))
@@ -328,6 +442,71 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_fork_article() {
+        use ipnetwork::IpNetwork;
+        use std::str::FromStr;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let namespace = Namespace::get_default(&conn).expect("must succeed");
+            let article = Article::create(
+                &conn, &namespace, "test", "==test==", "Comment!", &actor, false,
+            )
+            .expect("must succeed");
+            let mut revision = article.get_latest_revision(&conn).expect("must exist");
+            assert!(revision.patrol(&conn, &actor).expect("must succeed"));
+            RevisionTag::add(&conn, &revision, &["possible-spam".to_owned()])
+                .expect("must succeed");
+            let fork = article
+                .fork(&conn, &namespace, "test fork", "Comment!", &actor, false)
+                .expect("must succeed");
+            // Reloaded, as the database keeps timestamps to the microsecond
+            let revision = article.get_latest_revision(&conn).expect("must exist");
+            let revisions = fork.get_all_revisions(&conn).expect("must succeed");
+            assert_eq!(revisions.len(), 2);
+            assert_eq!(revisions[0].patrolled_by, revision.patrolled_by);
+            assert_eq!(revisions[0].patrolled_at, revision.patrolled_at);
+            assert_eq!(
+                RevisionTag::find_by_revision(&conn, &revisions[0]).expect("must succeed"),
+                vec!["possible-spam".to_owned()]
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_edit_article_reuses_content() {
+        use ipnetwork::IpNetwork;
+        use std::str::FromStr;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let mut article = Article::create(
+                &conn,
+                &Namespace::get_default(&conn).expect("must succeed"),
+                "test",
+                "==test==",
+                "Comment!",
+                &actor,
+                false,
+            )
+            .expect("must succeed");
+            let first = article.get_latest_revision(&conn).expect("must exist");
+            let second = article
+                .edit(&conn, "==test==", "No change", &actor, false)
+                .expect("must succeed");
+            assert_eq!(first.content_id, second.content_id);
+            assert_eq!(second.is_no_op(Some(first.content_id)), true);
+            assert_eq!(second.get_size(&conn).expect("must succeed"), 8);
+            assert_eq!(second.get_size_delta(&conn).expect("must succeed"), 0);
+            Ok(())
+        });
+    }
+
     #[test]
     fn test_rename_article() {
         use ipnetwork::IpNetwork;
@@ -338,16 +517,35 @@ mod tests {
             let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
             let mut article = Article::create(
                 &conn,
-                &Namespace::default(),
+                &Namespace::get_default(&conn).expect("must succeed"),
                 "test",
                 "==test==",
                 "Comment!",
                 &actor,
+                false,
             )
             .expect("must succeed");
-            article
-                .rename(&conn, "test2", "Comment!", &actor)
+            let revision = article
+                .rename(
+                    &conn,
+                    &Namespace::get_default(&conn).expect("must succeed"),
+                    "test2",
+                    "Comment!",
+                    &actor,
+                    false,
+                )
                 .expect("must succeed");
+            assert_eq!(revision.kind, RevisionKind::Rename);
+            assert_eq!(revision.comment, "Comment!");
+            let payload = revision.get_payload().expect("must succeed");
+            assert_eq!(
+                payload,
+                Some(RevisionPayload {
+                    old_title: Some("test".to_owned()),
+                    new_title: Some("test2".to_owned()),
+                    ..Default::default()
+                })
+            );
             let article = articles::table
                 .filter(articles::title.eq("test2"))
                 .first::<Article>(&conn)
@@ -373,15 +571,16 @@ mod tests {
             let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
             let mut article = Article::create(
                 &conn,
-                &Namespace::default(),
+                &Namespace::get_default(&conn).expect("must succeed"),
                 "test",
                 "==test==",
                 "Comment!",
                 &actor,
+                false,
             )
             .expect("must succeed");
             article
-                .delete(&conn, "Comment!", &actor)
+                .delete(&conn, "Comment!", &actor, false)
                 .expect("must succeed");
             assert_eq!(article.is_active, false);
             let article = Article::find_by_full_title(&conn, "test").expect("must succeed");
@@ -405,9 +604,10 @@ mod tests {
                 .values(namespaces::name.eq("Test"))
                 .get_result::<Namespace>(&conn)
                 .expect("must succeed");
-            let article =
-                Article::create(&conn, &namespace, "test", "==test==", "Comment!", &actor)
-                    .expect("must succeed");
+            let article = Article::create(
+                &conn, &namespace, "test", "==test==", "Comment!", &actor, false,
+            )
+            .expect("must succeed");
             let full_title = article.get_full_title(&conn).expect("must succeed");
             assert_eq!(full_title, "Test:test");
             Ok(())
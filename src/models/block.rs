@@ -0,0 +1,222 @@
+use crate::models::{Actor, Namespace, User};
+use crate::schema::blocks;
+use anyhow::Result;
+use chrono::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::Cidr;
+use ipnetwork::IpNetwork;
+
+diesel_infix_operator!(ContainsOrEquals, " >>= ");
+
+/// Who a `Block` is against.
+#[derive(Debug, Clone, Copy)]
+pub enum BlockTarget<'a> {
+    User(&'a User),
+    /// A single address or a range.
+    Address(&'a IpNetwork),
+}
+
+/// Stops a user, or everyone editing from an address range, from editing until `expires_at`.
+#[derive(Queryable, Identifiable, Debug)]
+pub struct Block {
+    pub id: i32,
+    pub user_id: Option<i32>,
+    pub ip_address: Option<IpNetwork>,
+    pub reason: String,
+    pub actor_id: i32,
+    /// The namespaces edits are blocked in, or `None` for every namespace.
+    pub namespace_ids: Option<Vec<i32>>,
+    /// Also stops accounts from being created from the blocked addresses.
+    pub prevent_account_creation: bool,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "blocks"]
+struct NewBlock<'a> {
+    pub user_id: Option<i32>,
+    pub ip_address: Option<&'a IpNetwork>,
+    pub reason: &'a str,
+    pub actor_id: i32,
+    pub namespace_ids: Option<Vec<i32>>,
+    pub prevent_account_creation: bool,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl Block {
+    pub fn create(
+        conn: &PgConnection,
+        target: BlockTarget,
+        reason: &str,
+        actor: &Actor,
+        namespaces: Option<&[Namespace]>,
+        prevent_account_creation: bool,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<Self> {
+        let (user_id, ip_address) = match target {
+            BlockTarget::User(user) => (Some(user.id), None),
+            BlockTarget::Address(ip_address) => (None, Some(ip_address)),
+        };
+        let block = diesel::insert_into(blocks::table)
+            .values(NewBlock {
+                user_id,
+                ip_address,
+                reason,
+                actor_id: actor.id,
+                namespace_ids: namespaces
+                    .map(|namespaces| namespaces.iter().map(|namespace| namespace.id).collect()),
+                prevent_account_creation,
+                expires_at,
+                created_at: Utc::now().naive_utc(),
+            })
+            .get_result::<Self>(conn)?;
+        Ok(block)
+    }
+
+    /// Lifts the block `id`. Returns whether it existed.
+    pub fn unblock(conn: &PgConnection, id: i32) -> Result<bool> {
+        let count = diesel::delete(blocks::table.find(id)).execute(conn)?;
+        Ok(count > 0)
+    }
+
+    /// Blocks which have not expired.
+    fn active() -> blocks::BoxedQuery<'static, Pg> {
+        let now = Utc::now().naive_utc();
+        blocks::table
+            .filter(blocks::expires_at.is_null().or(blocks::expires_at.gt(now)))
+            .into_boxed()
+    }
+
+    /// Active blocks, newest first.
+    pub fn list_active(conn: &PgConnection, offset: i64, limit: i64) -> Result<Vec<Self>> {
+        let blocks = Self::active()
+            .order(blocks::id.desc())
+            .offset(offset)
+            .limit(limit)
+            .load::<Self>(conn)?;
+        Ok(blocks)
+    }
+
+    /// Active blocks against `user`, or against ranges containing `ip_address`, oldest first.
+    pub fn find_active(
+        conn: &PgConnection,
+        user: Option<&User>,
+        ip_address: &IpNetwork,
+    ) -> Result<Vec<Self>> {
+        let in_range = ContainsOrEquals::new(blocks::ip_address, (*ip_address).into_sql::<Cidr>());
+        let query = match user {
+            Some(user) => Self::active().filter(blocks::user_id.eq(user.id).or(in_range)),
+            None => Self::active().filter(in_range),
+        };
+        let blocks = query.order(blocks::id.asc()).load::<Self>(conn)?;
+        Ok(blocks)
+    }
+
+    /// The first active block stopping `user`, or an anonymous visitor if `None`, from editing
+    /// in `namespace` from `ip_address`.
+    pub fn find_blocking_edit(
+        conn: &PgConnection,
+        user: Option<&User>,
+        ip_address: &IpNetwork,
+        namespace: &Namespace,
+    ) -> Result<Option<Self>> {
+        let blocks = Self::find_active(conn, user, ip_address)?;
+        Ok(blocks.into_iter().find(|block| block.applies_to(namespace)))
+    }
+
+    /// The first active block stopping accounts from being created from `ip_address`.
+    pub fn find_blocking_account_creation(
+        conn: &PgConnection,
+        ip_address: &IpNetwork,
+    ) -> Result<Option<Self>> {
+        let blocks = Self::find_active(conn, None, ip_address)?;
+        Ok(blocks
+            .into_iter()
+            .find(|block| block.prevent_account_creation))
+    }
+
+    pub fn applies_to(&self, namespace: &Namespace) -> bool {
+        match &self.namespace_ids {
+            Some(namespace_ids) => namespace_ids.contains(&namespace.id),
+            None => true,
+        }
+    }
+
+    pub fn get_user(&self, conn: &PgConnection) -> Result<Option<User>> {
+        match self.user_id {
+            Some(user_id) => Ok(Some(User::find_by_id(conn, user_id)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_actor(&self, conn: &PgConnection) -> Result<Actor> {
+        use crate::schema::actors;
+        let actor = actors::table.find(self.actor_id).first::<Actor>(conn)?;
+        Ok(actor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_find_blocking_edit() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let range = IpNetwork::from_str("192.0.2.0/24").expect("must succeed");
+            let inside = IpNetwork::from_str("192.0.2.42").expect("must succeed");
+            let outside = IpNetwork::from_str("198.51.100.1").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &outside).expect("must succeed");
+            let namespace = Namespace::get_default(&conn).expect("must succeed");
+            let block = Block::create(
+                &conn,
+                BlockTarget::Address(&range),
+                "Vandalism",
+                &actor,
+                None,
+                true,
+                None,
+            )
+            .expect("must succeed");
+            let found = Block::find_blocking_edit(&conn, None, &inside, &namespace)
+                .expect("must succeed")
+                .expect("must exist");
+            assert_eq!(found.id, block.id);
+            assert!(Block::find_blocking_account_creation(&conn, &inside)
+                .expect("must succeed")
+                .is_some());
+            assert!(Block::find_blocking_edit(&conn, None, &outside, &namespace)
+                .expect("must succeed")
+                .is_none());
+
+            let user = User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            Block::create(
+                &conn,
+                BlockTarget::User(&user),
+                "Spam",
+                &actor,
+                Some(&[]),
+                false,
+                None,
+            )
+            .expect("must succeed");
+            assert!(
+                Block::find_blocking_edit(&conn, Some(&user), &outside, &namespace)
+                    .expect("must succeed")
+                    .is_none()
+            );
+            assert!(Block::unblock(&conn, block.id).expect("must succeed"));
+            assert!(Block::find_blocking_edit(&conn, None, &inside, &namespace)
+                .expect("must succeed")
+                .is_none());
+            Ok(())
+        });
+    }
+}
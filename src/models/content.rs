@@ -0,0 +1,286 @@
+use crate::schema::{articles, contents, revisions};
+use crate::storage::{self, Compression};
+use anyhow::{anyhow, Result};
+use diesel::prelude::*;
+use serde::Serialize;
+
+/// Wikitext of one or more `Revision`s.
+///
+/// New contents are stored as plain text. Contents which are no longer the latest revision of any
+/// article can be recompressed with `recompress_batch`, after which the wikitext lives in `data`,
+/// optionally as a delta against the wikitext of the `Content` `base_id`.
+#[derive(Serialize, Queryable, Identifiable, Debug)]
+pub struct Content {
+    pub id: i32,
+    pub wikitext: Option<String>,
+    pub sha256: String,
+    pub byte_length: i32,
+    pub compression: String,
+    pub data: Option<Vec<u8>>,
+    pub base_id: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[table_name = "contents"]
+struct NewContent<'a> {
+    pub wikitext: &'a str,
+    pub sha256: &'a str,
+    pub byte_length: i32,
+}
+
+impl Content {
+    /// Hex encoded SHA-256 of `wikitext`.
+    pub fn hash(wikitext: &str) -> String {
+        use sha2::{Digest, Sha256};
+        format!("{:x}", Sha256::digest(wikitext.as_bytes()))
+    }
+
+    pub fn find_by_id(conn: &PgConnection, id: i32) -> Result<Self> {
+        Ok(contents::table.find(id).first::<Self>(conn)?)
+    }
+
+    /// Returns the `Content` with the same wikitext if there is one, otherwise inserts it.
+    pub fn find_or_create(conn: &PgConnection, wikitext: &str) -> Result<Self> {
+        let sha256 = Self::hash(wikitext);
+        let byte_length = wikitext.len() as i32;
+        let content = contents::table
+            .filter(contents::sha256.eq(&sha256))
+            .filter(contents::byte_length.eq(byte_length))
+            .first::<Self>(conn)
+            .optional()?;
+        if let Some(content) = content {
+            // It is about to be the latest revision of an article again
+            if !content.is_plain() {
+                return content.restore(conn);
+            }
+            return Ok(content);
+        }
+        let content = diesel::insert_into(contents::table)
+            .values(NewContent {
+                wikitext,
+                sha256: &sha256,
+                byte_length,
+            })
+            .get_result::<Self>(conn)?;
+        Ok(content)
+    }
+
+    pub fn get_wikitext(&self, conn: &PgConnection) -> Result<String> {
+        if let Some(wikitext) = &self.wikitext {
+            return Ok(wikitext.clone());
+        }
+        let data = self
+            .data
+            .as_ref()
+            .ok_or_else(|| anyhow!("Content {} has neither wikitext nor data", self.id))?;
+        let decompressed = Compression::from_name(&self.compression)?.decompress(data)?;
+        match self.base_id {
+            Some(base_id) => {
+                let base = Self::find_by_id(conn, base_id)?.get_wikitext(conn)?;
+                storage::apply_delta(&base, &decompressed)
+            }
+            None => Ok(String::from_utf8(decompressed)?),
+        }
+    }
+
+    fn is_plain(&self) -> bool {
+        self.wikitext.is_some()
+    }
+
+    /// Compresses up to `batch_size` plain contents which are not the latest revision of any
+    /// article, so reading the latest revisions never has to decompress anything.
+    ///
+    /// Every `keyframe_interval`th revision of an article is stored whole; the others are stored
+    /// as deltas against the preceding keyframe. Returns the number of rewritten contents.
+    pub fn recompress_batch(
+        conn: &PgConnection,
+        compression: Compression,
+        keyframe_interval: usize,
+        batch_size: i64,
+    ) -> Result<usize> {
+        let latest_content_ids = articles::table
+            .inner_join(revisions::table.on(revisions::id.eq(articles::latest_revision_id)))
+            .select(revisions::content_id);
+        let candidates = contents::table
+            .filter(contents::wikitext.is_not_null())
+            .filter(diesel::dsl::not(contents::id.eq_any(latest_content_ids)))
+            .order(contents::id.asc())
+            .limit(batch_size)
+            .load::<Self>(conn)?;
+        for content in candidates.iter() {
+            conn.transaction::<_, anyhow::Error, _>(|| {
+                content.recompress(conn, compression, keyframe_interval)
+            })?;
+        }
+        Ok(candidates.len())
+    }
+
+    fn recompress(
+        &self,
+        conn: &PgConnection,
+        compression: Compression,
+        keyframe_interval: usize,
+    ) -> Result<()> {
+        let wikitext = self.get_wikitext(conn)?;
+        let base = match self.find_keyframe(conn, keyframe_interval)? {
+            // A delta must never be based on a delta, and nothing may be based on a delta.
+            Some(base) if base.base_id.is_none() && !self.is_base(conn)? => Some(base),
+            _ => None,
+        };
+        let data = match &base {
+            Some(base) => storage::encode_delta(&base.get_wikitext(conn)?, &wikitext)?,
+            None => wikitext.as_bytes().to_vec(),
+        };
+        let data = compression.compress(&data)?;
+        diesel::update(contents::table.find(self.id))
+            .set((
+                contents::wikitext.eq(None::<String>),
+                contents::compression.eq(compression.as_str()),
+                contents::data.eq(Some(data)),
+                contents::base_id.eq(base.as_ref().map(|base| base.id)),
+            ))
+            .execute(conn)?;
+        let stored = Self::find_by_id(conn, self.id)?.get_wikitext(conn)?;
+        if Self::hash(&stored) != self.sha256 {
+            return Err(anyhow!("Recompressed content {} does not match", self.id));
+        }
+        Ok(())
+    }
+
+    /// Stores up to `batch_size` compressed contents as plain wikitext again, e.g. before
+    /// reverting the migration which added compression. Returns the number of rewritten contents.
+    pub fn restore_batch(conn: &PgConnection, batch_size: i64) -> Result<usize> {
+        let candidates = contents::table
+            .filter(contents::wikitext.is_null())
+            .order(contents::id.asc())
+            .limit(batch_size)
+            .load::<Self>(conn)?;
+        for content in candidates.iter() {
+            conn.transaction::<_, anyhow::Error, _>(|| content.restore(conn).map(|_| ()))?;
+        }
+        Ok(candidates.len())
+    }
+
+    /// Stores the wikitext plainly. Contents stored as deltas against this one keep working, as
+    /// a plain base is read directly.
+    fn restore(&self, conn: &PgConnection) -> Result<Self> {
+        let wikitext = self.get_wikitext(conn)?;
+        let content = diesel::update(contents::table.find(self.id))
+            .set((
+                contents::wikitext.eq(Some(wikitext)),
+                contents::compression.eq(Compression::None.as_str()),
+                contents::data.eq(None::<Vec<u8>>),
+                contents::base_id.eq(None::<i32>),
+            ))
+            .get_result::<Self>(conn)?;
+        Ok(content)
+    }
+
+    /// Keyframe of the article which first used this content, if this is not a keyframe itself.
+    fn find_keyframe(&self, conn: &PgConnection, keyframe_interval: usize) -> Result<Option<Self>> {
+        if keyframe_interval < 2 {
+            return Ok(None);
+        }
+        let article_id = revisions::table
+            .filter(revisions::content_id.eq(self.id))
+            .order(revisions::id.asc())
+            .select(revisions::article_id)
+            .first::<i32>(conn)
+            .optional()?;
+        let article_id = match article_id {
+            Some(article_id) => article_id,
+            None => return Ok(None),
+        };
+        let content_ids = revisions::table
+            .filter(revisions::article_id.eq(article_id))
+            .order(revisions::id.asc())
+            .select(revisions::content_id)
+            .load::<i32>(conn)?;
+        let position = match content_ids.iter().position(|id| *id == self.id) {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        let keyframe_id = content_ids[position - position % keyframe_interval];
+        if keyframe_id == self.id {
+            return Ok(None);
+        }
+        let keyframe = Self::find_by_id(conn, keyframe_id)?;
+        if keyframe.is_plain() || keyframe.base_id.is_none() {
+            Ok(Some(keyframe))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Whether another content is stored as a delta against this one.
+    fn is_base(&self, conn: &PgConnection) -> Result<bool> {
+        let dependant = contents::table
+            .filter(contents::base_id.eq(self.id))
+            .select(contents::id)
+            .first::<i32>(conn)
+            .optional()?;
+        Ok(dependant.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use crate::models::{Actor, Article, Namespace};
+
+    #[test]
+    fn test_recompress_batch() {
+        use ipnetwork::IpNetwork;
+        use std::str::FromStr;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let mut article = Article::create(
+                &conn,
+                &Namespace::get_default(&conn).expect("must succeed"),
+                "test",
+                "==recompress==\nline 0",
+                "Comment!",
+                &actor,
+                false,
+            )
+            .expect("must succeed");
+            let mut texts = vec!["==recompress==\nline 0".to_owned()];
+            for i in 1..5 {
+                let text = format!("{}\nline {}", texts.last().unwrap(), i);
+                article
+                    .edit(&conn, &text, "Comment!", &actor, false)
+                    .expect("must succeed");
+                texts.push(text);
+            }
+            while Content::recompress_batch(&conn, Compression::Zstd, 3, 100).expect("must succeed")
+                > 0
+            {}
+            let revisions = article.get_all_revisions(&conn).expect("must succeed");
+            for (revision, text) in revisions.iter().zip(texts.iter()) {
+                assert_eq!(&revision.get_wikitext(&conn).expect("must succeed"), text);
+            }
+            let latest = article
+                .get_latest_revision(&conn)
+                .expect("must exist")
+                .get_content(&conn)
+                .expect("must succeed");
+            assert!(latest.is_plain());
+            let delta = revisions[1].get_content(&conn).expect("must succeed");
+            assert_eq!(delta.base_id, Some(revisions[0].content_id));
+
+            // Restoring the keyframe first must not break the deltas against it
+            let keyframe = revisions[0].get_content(&conn).expect("must succeed");
+            keyframe.restore(&conn).expect("must succeed");
+            while Content::restore_batch(&conn, 100).expect("must succeed") > 0 {}
+            for (revision, text) in revisions.iter().zip(texts.iter()) {
+                let content = revision.get_content(&conn).expect("must succeed");
+                assert_eq!(content.wikitext.as_ref(), Some(text));
+                assert_eq!(content.data, None);
+            }
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,108 @@
+use crate::schema::login_attempts;
+use anyhow::Result;
+use chrono::prelude::*;
+use chrono::{Duration, NaiveDateTime};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use ipnetwork::IpNetwork;
+
+/// Failed logins counted towards throttling are those within this many minutes.
+const WINDOW_MINUTES: i64 = 15;
+/// Failed logins allowed for one username from one IP address within the window. Failures from
+/// other addresses do not count, so nobody can lock others out of their account.
+const MAX_FAILURES_PER_USERNAME: i64 = 5;
+/// Failed logins allowed from one IP address within the window, across usernames.
+const MAX_FAILURES_PER_IP: i64 = 20;
+
+#[derive(Queryable, Identifiable, Debug)]
+pub struct LoginAttempt {
+    pub id: i32,
+    pub username: String,
+    pub ip_address: IpNetwork,
+    pub succeeded: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "login_attempts"]
+struct NewLoginAttempt<'a> {
+    pub username: &'a str,
+    pub ip_address: &'a IpNetwork,
+    pub succeeded: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl LoginAttempt {
+    pub fn record(
+        conn: &PgConnection,
+        username: &str,
+        ip_address: &IpNetwork,
+        succeeded: bool,
+    ) -> Result<()> {
+        diesel::insert_into(login_attempts::table)
+            .values(NewLoginAttempt {
+                username: &username.to_lowercase(),
+                ip_address,
+                succeeded,
+                created_at: Utc::now().naive_utc(),
+            })
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Whether logins for `username` from `ip_address`, or any logins from `ip_address`, failed
+    /// too often recently.
+    pub fn is_throttled(
+        conn: &PgConnection,
+        username: &str,
+        ip_address: &IpNetwork,
+    ) -> Result<bool> {
+        let since = Utc::now().naive_utc() - Duration::minutes(WINDOW_MINUTES);
+        let failures = login_attempts::table
+            .filter(login_attempts::succeeded.eq(false))
+            .filter(login_attempts::created_at.gt(since))
+            .filter(login_attempts::ip_address.eq(ip_address));
+        let username_failures = failures
+            .clone()
+            .filter(login_attempts::username.eq(username.to_lowercase()))
+            .select(count_star())
+            .first::<i64>(conn)?;
+        if username_failures >= MAX_FAILURES_PER_USERNAME {
+            return Ok(true);
+        }
+        let ip_failures = failures.select(count_star()).first::<i64>(conn)?;
+        Ok(ip_failures >= MAX_FAILURES_PER_IP)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_throttling() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("192.0.2.1").expect("must succeed");
+            let other_ip_address = IpNetwork::from_str("192.0.2.2").expect("must succeed");
+            for _ in 0..MAX_FAILURES_PER_USERNAME {
+                assert!(!LoginAttempt::is_throttled(&conn, "Throttled", &ip_address)
+                    .expect("must succeed"));
+                LoginAttempt::record(&conn, "Throttled", &ip_address, false).expect("must succeed");
+            }
+            assert!(
+                LoginAttempt::is_throttled(&conn, "throttled", &ip_address).expect("must succeed")
+            );
+            assert!(
+                !LoginAttempt::is_throttled(&conn, "someone", &ip_address).expect("must succeed")
+            );
+            assert!(
+                !LoginAttempt::is_throttled(&conn, "throttled", &other_ip_address)
+                    .expect("must succeed")
+            );
+            Ok(())
+        });
+    }
+}
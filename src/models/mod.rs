@@ -1,18 +1,54 @@
+mod abuse_filter;
+mod abuse_filter_hit;
 mod actor;
+mod api_token;
 mod article;
 mod article_search;
+mod block;
+mod content;
+mod login_attempt;
 mod namespace;
+mod oauth_state;
 mod permission;
+mod permission_log;
+mod protection;
+mod rate_limit_counter;
 mod redirection;
 mod revision;
+mod revision_tag;
 mod role;
+mod session;
+mod template_link;
 mod user;
+mod user_token;
+pub use abuse_filter::{
+    AbuseFilter, AbuseFilterChanges, FilterAction, FilterOutcome, FilteredChange,
+};
+pub use abuse_filter_hit::AbuseFilterHit;
 pub use actor::Actor;
+pub use api_token::{ApiToken, Scope, API_TOKEN_PREFIX};
 pub use article::Article;
 pub use article_search::ArticleSearch;
-pub use namespace::Namespace;
-pub use permission::{ArticlePermission, NamespacePermission};
+pub use block::{Block, BlockTarget};
+pub use content::Content;
+pub use login_attempt::LoginAttempt;
+pub use namespace::{Namespace, NamespaceAlias, NamespaceError};
+pub use oauth_state::OauthState;
+pub use permission::{
+    Action, ArticlePermission, ArticlePermissionValues, NamespacePermission,
+    NamespacePermissionChanges, PermissionDecision, PermissionSource, Permissions,
+    ReadableArticles,
+};
+pub use permission_log::PermissionLog;
+pub use protection::{Protection, ProtectionAction};
+pub use rate_limit_counter::RateLimitCounter;
 pub use redirection::Redirection;
-pub use revision::{NewRevision, Revision};
-pub use role::{Role, UserRole};
-pub use user::{User, UserFindResult};
+pub use revision::{NewRevision, Revision, RevisionKind, RevisionPayload, RevisionSize};
+pub use revision_tag::{RevisionTag, AUTOMATIC_TAGS};
+pub use role::{Right, Role, RoleRight, UserRole};
+pub use session::{Rotation, Session};
+pub use template_link::TemplateLink;
+pub use user::{
+    Authentication, ProfileChanges, User, UserFindResult, UserPreferences, LOCAL_PROVIDER,
+};
+pub use user_token::{TokenPurpose, UserToken};
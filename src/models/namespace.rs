@@ -1,12 +1,39 @@
-use crate::schema::namespaces;
+use crate::models::{NamespacePermission, Role};
+use crate::schema::{
+    articles, namespace_aliases, namespace_permissions, namespaces, redirections, roles,
+};
+use crate::title::Title;
 use anyhow::Result;
 use diesel::prelude::*;
+use diesel::sql_types::Text;
 use serde::Serialize;
+use std::fmt;
+
+sql_function!(fn lower(x: Text) -> Text);
+
+/// Name of the namespace of titles without a namespace prefix.
+pub const DEFAULT_NAMESPACE_NAME: &str = "_DEFAULT";
 
 #[derive(Serialize, Queryable, Identifiable, Debug, Eq)]
 pub struct Namespace {
     pub id: i32,
     pub name: String,
+    /// Whether the first letter of titles in this namespace is uppercased.
+    pub capitalize_first_letter: bool,
+    /// The namespace discussed in this one, if this is a talk namespace.
+    pub subject_namespace_id: Option<i32>,
+}
+
+/// Another name resolving to a `Namespace`, e.g. its name before a rename.
+///
+/// An alias with a `language` is a localized name of the namespace.
+#[derive(Serialize, Queryable, Insertable, Associations, Debug)]
+#[table_name = "namespace_aliases"]
+#[belongs_to(Namespace)]
+pub struct NamespaceAlias {
+    pub name: String,
+    pub namespace_id: i32,
+    pub language: Option<String>,
 }
 
 impl PartialEq for Namespace {
@@ -15,53 +42,506 @@ impl PartialEq for Namespace {
     }
 }
 
-impl Default for Namespace {
-    fn default() -> Self {
-        Self {
-            id: 1,
-            name: "_DEFAULT".to_owned(),
+/// Why a namespace cannot be created, renamed or deleted.
+#[derive(Debug, PartialEq, Eq)]
+pub enum NamespaceError {
+    /// A namespace or alias already has the name.
+    Exists(String),
+    NotEmpty(String),
+    /// The default namespace cannot be renamed or deleted.
+    Default(&'static str),
+    /// A talk namespace is only renamed or deleted with its subject namespace.
+    Talk(&'static str),
+}
+
+impl fmt::Display for NamespaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Exists(name) => write!(f, "Namespace {} already exists", name),
+            Self::NotEmpty(name) => write!(f, "Namespace {} is not empty", name),
+            Self::Default(action) => write!(f, "The default namespace cannot be {}", action),
+            Self::Talk(action) => {
+                write!(
+                    f,
+                    "A talk namespace is {} with its subject namespace",
+                    action
+                )
+            }
         }
     }
 }
 
+impl std::error::Error for NamespaceError {}
+
 impl Namespace {
     pub fn find_by_id(conn: &PgConnection, id: i32) -> Result<Option<Self>, diesel::result::Error> {
-        if id == 1 {
-            return Ok(Some(Self::default()));
-        }
         let namespace = namespaces::table.find(id).first::<Self>(conn).optional()?;
         Ok(namespace)
     }
+    /// Loads the default namespace with its current settings.
+    pub fn get_default(conn: &PgConnection) -> Result<Self> {
+        let namespace = namespaces::table
+            .filter(namespaces::name.eq(DEFAULT_NAMESPACE_NAME))
+            .first::<Self>(conn)?;
+        Ok(namespace)
+    }
+    /// Finds a `Namespace` by its name or one of its aliases, ignoring case.
     pub fn find_by_name(
         conn: &PgConnection,
         name: &str,
     ) -> Result<Option<Self>, diesel::result::Error> {
         let namespace = namespaces::table
-            .filter(namespaces::name.eq(name))
+            .filter(lower(namespaces::name).eq(lower(name)))
+            .first::<Self>(conn)
+            .optional()?;
+        if namespace.is_some() {
+            return Ok(namespace);
+        }
+        let namespace = namespace_aliases::table
+            .inner_join(namespaces::table)
+            .select(namespaces::all_columns)
+            .filter(lower(namespace_aliases::name).eq(lower(name)))
             .first::<Self>(conn)
             .optional()?;
         Ok(namespace)
     }
-    pub fn parse_full_title(conn: &PgConnection, full_title: &str) -> Result<(Namespace, String)> {
-        let full_title = full_title.trim();
+    pub fn list(conn: &PgConnection) -> Result<Vec<Self>> {
+        let namespaces = namespaces::table
+            .order(namespaces::id.asc())
+            .load::<Self>(conn)?;
+        Ok(namespaces)
+    }
+    /// The name of the talk namespace paired with a namespace named `name`.
+    pub fn talk_name_of(name: &str) -> String {
+        format!("{} talk", name)
+    }
+    /// Creates a `Namespace` together with its talk namespace.
+    ///
+    /// Permissions are copied from the default namespace. Roles without a permission there get
+    /// none, except `Root` which gets all.
+    pub fn create(conn: &PgConnection, name: &str) -> Result<Self> {
+        let talk_name = Self::talk_name_of(name);
+        for name in &[name, talk_name.as_str()] {
+            if Self::find_by_name(conn, name)?.is_some() {
+                return Err(NamespaceError::Exists((*name).to_owned()).into());
+            }
+        }
+        let default = Self::get_default(conn)?;
+        conn.transaction(|| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(namespaces::name.eq(name))
+                .get_result::<Self>(conn)?;
+            let default_permissions =
+                NamespacePermission::belonging_to(&default).load::<NamespacePermission>(conn)?;
+            namespace.create_permissions(conn, &default_permissions)?;
+            let talk = diesel::insert_into(namespaces::table)
+                .values((
+                    namespaces::name.eq(&talk_name),
+                    namespaces::subject_namespace_id.eq(namespace.id),
+                ))
+                .get_result::<Self>(conn)?;
+            talk.create_permissions(conn, &default_permissions)?;
+            Ok(namespace)
+        })
+    }
+    /// Creates a `NamespacePermission` for every `Role`, copying `template`.
+    ///
+    /// `LoggedIn` may always create, read and edit in talk namespaces.
+    fn create_permissions(
+        &self,
+        conn: &PgConnection,
+        template: &[NamespacePermission],
+    ) -> Result<()> {
+        let role_ids = roles::table.select(roles::id).load::<i32>(conn)?;
+        let permissions = role_ids
+            .iter()
+            .map(|role_id| {
+                let default = template.iter().find(|p| p.role_id == *role_id);
+                let is_root = *role_id == Role::root().id;
+                let is_talk = self.is_talk() && *role_id == Role::logged_in().id;
+                NamespacePermission {
+                    namespace_id: self.id,
+                    role_id: *role_id,
+                    can_create: is_talk || default.map_or(is_root, |p| p.can_create),
+                    can_read: is_talk || default.map_or(is_root, |p| p.can_read),
+                    can_edit: is_talk || default.map_or(is_root, |p| p.can_edit),
+                    can_rename: default.map_or(is_root, |p| p.can_rename),
+                    can_delete: default.map_or(is_root, |p| p.can_delete),
+                    can_grant: default.map_or(is_root, |p| p.can_grant),
+                }
+            })
+            .collect::<Vec<NamespacePermission>>();
+        diesel::insert_into(namespace_permissions::table)
+            .values(&permissions)
+            .execute(conn)?;
+        Ok(())
+    }
+    /// Whether this is the namespace of titles without a namespace prefix.
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_NAMESPACE_NAME
+    }
+    pub fn is_talk(&self) -> bool {
+        self.subject_namespace_id.is_some()
+    }
+    /// Finds the talk namespace paired with this one.
+    pub fn get_talk(&self, conn: &PgConnection) -> Result<Option<Self>> {
+        let talk = namespaces::table
+            .filter(namespaces::subject_namespace_id.eq(self.id))
+            .first::<Self>(conn)
+            .optional()?;
+        Ok(talk)
+    }
+    /// Finds the namespace discussed in this one, if this is a talk namespace.
+    pub fn get_subject(&self, conn: &PgConnection) -> Result<Option<Self>> {
+        match self.subject_namespace_id {
+            Some(id) => Ok(Self::find_by_id(conn, id)?),
+            None => Ok(None),
+        }
+    }
+    /// Changes the name, keeping the old one as an alias. The talk namespace is renamed along.
+    pub fn rename(&mut self, conn: &PgConnection, name: &str) -> Result<()> {
+        if self.is_default() {
+            return Err(NamespaceError::Default("renamed").into());
+        }
+        if self.is_talk() {
+            return Err(NamespaceError::Talk("renamed").into());
+        }
+        let mut talk = self.get_talk(conn)?;
+        let talk_name = Self::talk_name_of(name);
+        for (name, namespace) in &[(name, Some(&*self)), (talk_name.as_str(), talk.as_ref())] {
+            if let Some(existing) = Self::find_by_name(conn, name)? {
+                if Some(existing.id) != namespace.map(|n| n.id) {
+                    return Err(NamespaceError::Exists((*name).to_owned()).into());
+                }
+            }
+        }
+        conn.transaction(|| {
+            self.set_name(conn, name)?;
+            if let Some(talk) = &mut talk {
+                talk.set_name(conn, &talk_name)?;
+            }
+            Ok(())
+        })
+    }
+    fn set_name(&mut self, conn: &PgConnection, name: &str) -> Result<()> {
+        diesel::delete(
+            namespace_aliases::table.filter(lower(namespace_aliases::name).eq(lower(name))),
+        )
+        .execute(conn)?;
+        if self.name.to_lowercase() != name.to_lowercase() {
+            diesel::insert_into(namespace_aliases::table)
+                .values(NamespaceAlias {
+                    name: self.name.clone(),
+                    namespace_id: self.id,
+                    language: None,
+                })
+                .execute(conn)?;
+        }
+        diesel::update(namespaces::table.find(self.id))
+            .set(namespaces::name.eq(name))
+            .execute(conn)?;
+        self.name = name.to_owned();
+        Ok(())
+    }
+    /// Sets whether the first letter of titles is uppercased, in the talk namespace as well.
+    ///
+    /// Existing titles are not changed; they are looked up with the new rule from now on.
+    pub fn set_capitalize_first_letter(&mut self, conn: &PgConnection, value: bool) -> Result<()> {
+        diesel::update(
+            namespaces::table.filter(
+                namespaces::id
+                    .eq(self.id)
+                    .or(namespaces::subject_namespace_id.eq(self.id)),
+            ),
+        )
+        .set(namespaces::capitalize_first_letter.eq(value))
+        .execute(conn)?;
+        self.capitalize_first_letter = value;
+        Ok(())
+    }
+    /// Whether any article or redirection, including deleted articles, is in this namespace.
+    pub fn is_empty(&self, conn: &PgConnection) -> Result<bool> {
+        let article = articles::table
+            .filter(articles::namespace_id.eq(self.id))
+            .select(articles::id)
+            .first::<i32>(conn)
+            .optional()?;
+        let redirection = redirections::table
+            .filter(redirections::namespace_id.eq(self.id))
+            .select(redirections::id)
+            .first::<i32>(conn)
+            .optional()?;
+        Ok(article.is_none() && redirection.is_none())
+    }
+    /// Deletes an empty namespace and its talk namespace with their permissions and aliases.
+    pub fn delete(&self, conn: &PgConnection) -> Result<()> {
+        if self.is_default() {
+            return Err(NamespaceError::Default("deleted").into());
+        }
+        if self.is_talk() {
+            return Err(NamespaceError::Talk("deleted").into());
+        }
+        let talk = self.get_talk(conn)?;
+        for namespace in std::iter::once(self).chain(talk.iter()) {
+            if !namespace.is_empty(conn)? {
+                return Err(NamespaceError::NotEmpty(namespace.name.clone()).into());
+            }
+        }
+        conn.transaction(|| {
+            for namespace in talk.iter().chain(std::iter::once(self)) {
+                diesel::delete(NamespacePermission::belonging_to(namespace)).execute(conn)?;
+                diesel::delete(NamespaceAlias::belonging_to(namespace)).execute(conn)?;
+                diesel::delete(namespaces::table.find(namespace.id)).execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+    pub fn get_aliases(&self, conn: &PgConnection) -> Result<Vec<NamespaceAlias>> {
+        let aliases = NamespaceAlias::belonging_to(self)
+            .order(namespace_aliases::name.asc())
+            .load::<NamespaceAlias>(conn)?;
+        Ok(aliases)
+    }
+    /// Adds an alias, which is a localized name if `language` is given.
+    pub fn add_alias(
+        &self,
+        conn: &PgConnection,
+        name: &str,
+        language: Option<&str>,
+    ) -> Result<NamespaceAlias> {
+        if Self::find_by_name(conn, name)?.is_some() {
+            return Err(NamespaceError::Exists(name.to_owned()).into());
+        }
+        let alias = diesel::insert_into(namespace_aliases::table)
+            .values(NamespaceAlias {
+                name: name.to_owned(),
+                namespace_id: self.id,
+                language: language.map(|l| l.to_owned()),
+            })
+            .get_result::<NamespaceAlias>(conn)?;
+        Ok(alias)
+    }
+    /// Removes an alias of this namespace. Returns whether it existed.
+    pub fn remove_alias(&self, conn: &PgConnection, name: &str) -> Result<bool> {
+        let count = diesel::delete(
+            NamespaceAlias::belonging_to(self)
+                .filter(lower(namespace_aliases::name).eq(lower(name))),
+        )
+        .execute(conn)?;
+        Ok(count > 0)
+    }
+    /// Applies the case rule of this namespace to a title.
+    pub fn normalize_title(&self, title: &str) -> String {
+        if !self.capitalize_first_letter {
+            return title.to_owned();
+        }
+        let mut chars = title.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    }
+    /// Splits a full title into its namespace and the title normalized by the namespace's case
+    /// rule. Fails with a `TitleError` if the full title, or the title after the namespace as in
+    /// `Template:` or `Template::Foo`, is not a valid `Title`.
+    pub fn parse_full_title(conn: &PgConnection, full_title: &str) -> Result<(Namespace, Title)> {
+        let full_title = Title::new(full_title)?;
+        let full_title = full_title.as_str();
         let split: Vec<&str> = full_title.splitn(2, ':').map(|s| s.trim()).collect();
-        match split.as_slice() {
-            [_first] => Ok((Self::default(), full_title.to_owned())),
+        let (namespace, title) = match split.as_slice() {
+            [_first] => (Self::get_default(conn)?, full_title),
             [first, second] => {
                 if let Some(namespace) = Self::find_by_name(conn, first)? {
-                    Ok((namespace, (*second).to_owned()))
+                    (namespace, *second)
                 } else {
-                    Ok((Self::default(), full_title.to_owned()))
+                    (Self::get_default(conn)?, full_title)
                 }
             }
             _ => panic!(),
-        }
+        };
+        let title = Title::new(&namespace.normalize_title(Title::new(title)?.as_str()))?;
+        Ok((namespace, title))
     }
     pub fn join(&self, title: &str) -> String {
-        if self == &Self::default() {
+        if self.is_default() {
             title.to_owned()
         } else {
             format!("{}:{}", self.name, title)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+
+    #[test]
+    fn test_create_namespace() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let namespace = Namespace::create(&conn, "Test").expect("must succeed");
+            let permissions = NamespacePermission::belonging_to(&namespace)
+                .load::<NamespacePermission>(&conn)
+                .expect("must succeed");
+            let role_count = roles::table
+                .count()
+                .get_result::<i64>(&conn)
+                .expect("must succeed");
+            assert_eq!(permissions.len() as i64, role_count);
+            let anonymous = permissions
+                .iter()
+                .find(|p| p.role_id == Role::anonymous().id)
+                .expect("must exist");
+            assert_eq!(anonymous.can_rename, false);
+            assert!(Namespace::create(&conn, "Test").is_err());
+            let talk = namespace
+                .get_talk(&conn)
+                .expect("must succeed")
+                .expect("must exist");
+            assert_eq!(talk.name, "Test talk");
+            assert_eq!(
+                talk.get_subject(&conn).expect("must succeed"),
+                Some(namespace)
+            );
+            assert!(Role::logged_in()
+                .can_create(&conn, &talk)
+                .expect("must succeed"));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_rename_namespace() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let mut namespace = Namespace::create(&conn, "Test").expect("must succeed");
+            namespace.rename(&conn, "Renamed").expect("must succeed");
+            let (found, title) =
+                Namespace::parse_full_title(&conn, "Test:title").expect("must succeed");
+            assert_eq!(found, namespace);
+            assert_eq!(title.as_str(), "title");
+            assert_eq!(found.join(&title), "Renamed:title");
+            assert_eq!(
+                Namespace::find_by_name(&conn, "Test talk")
+                    .expect("must succeed")
+                    .map(|talk| talk.name),
+                Some("Renamed talk".to_owned())
+            );
+            namespace.rename(&conn, "Test").expect("must succeed");
+            assert_eq!(
+                Namespace::find_by_name(&conn, "Renamed").expect("must succeed"),
+                Some(namespace)
+            );
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_namespace_case_rules() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let mut namespace = Namespace::create(&conn, "Test").expect("must succeed");
+            namespace
+                .add_alias(&conn, "Prueba", Some("es"))
+                .expect("must succeed");
+            assert!(namespace.add_alias(&conn, "test", None).is_err());
+            assert_eq!(
+                Namespace::find_by_name(&conn, "TEST").expect("must succeed"),
+                Some(
+                    Namespace::find_by_id(&conn, namespace.id)
+                        .expect("must succeed")
+                        .unwrap()
+                )
+            );
+            let (found, title) =
+                Namespace::parse_full_title(&conn, "prueba:title").expect("must succeed");
+            assert_eq!(found, namespace);
+            assert_eq!(title.as_str(), "title");
+            namespace
+                .set_capitalize_first_letter(&conn, true)
+                .expect("must succeed");
+            let (_, title) =
+                Namespace::parse_full_title(&conn, "test:éclair").expect("must succeed");
+            assert_eq!(title.as_str(), "Éclair");
+            assert!(namespace
+                .remove_alias(&conn, "PRUEBA")
+                .expect("must succeed"));
+            assert_eq!(
+                Namespace::find_by_name(&conn, "Prueba").expect("must succeed"),
+                None
+            );
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_parse_invalid_full_title() {
+        use crate::title::TitleError;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            Namespace::create(&conn, "Test").expect("must succeed");
+            for (full_title, error) in &[
+                ("Test:", TitleError::Empty),
+                ("Test::title", TitleError::LeadingColon),
+            ] {
+                assert_eq!(
+                    Namespace::parse_full_title(&conn, full_title)
+                        .expect_err("must fail")
+                        .downcast_ref::<TitleError>(),
+                    Some(error)
+                );
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_delete_namespace() {
+        use crate::models::{Actor, Article};
+        use ipnetwork::IpNetwork;
+        use std::str::FromStr;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let namespace = Namespace::create(&conn, "Test").expect("must succeed");
+            Article::create(
+                &conn, &namespace, "test", "==test==", "Comment!", &actor, false,
+            )
+            .expect("must succeed");
+            assert_eq!(
+                namespace
+                    .delete(&conn)
+                    .expect_err("must fail")
+                    .downcast_ref::<NamespaceError>(),
+                Some(&NamespaceError::NotEmpty("Test".to_owned()))
+            );
+            let empty = Namespace::create(&conn, "Empty").expect("must succeed");
+            let empty_talk = empty
+                .get_talk(&conn)
+                .expect("must succeed")
+                .expect("must exist");
+            assert!(empty_talk.delete(&conn).is_err());
+            empty.delete(&conn).expect("must succeed");
+            assert_eq!(
+                Namespace::find_by_name(&conn, "Empty").expect("must succeed"),
+                None
+            );
+            assert_eq!(
+                Namespace::find_by_name(&conn, "Empty talk").expect("must succeed"),
+                None
+            );
+            let default = Namespace::get_default(&conn).expect("must succeed");
+            assert!(default.is_default());
+            assert_eq!(
+                default
+                    .delete(&conn)
+                    .expect_err("must fail")
+                    .downcast_ref::<NamespaceError>(),
+                Some(&NamespaceError::Default("deleted"))
+            );
+            Ok(())
+        });
+    }
+}
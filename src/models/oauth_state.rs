@@ -0,0 +1,59 @@
+use crate::schema::oauth_states;
+use anyhow::Result;
+use chrono::prelude::*;
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+
+/// Minutes a user has to sign in at the provider.
+const LIFETIME_MINUTES: i64 = 10;
+
+/// An authorization started with a provider, kept until the provider redirects back.
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "oauth_states"]
+pub struct OauthState {
+    /// Sent to the provider and back to tie the redirect to this authorization.
+    pub state: String,
+    pub provider: String,
+    /// The PKCE secret whose challenge was sent to the provider.
+    pub code_verifier: String,
+    pub created_at: NaiveDateTime,
+    /// The user to link the provider to, or `None` to sign in.
+    pub user_id: Option<i32>,
+}
+
+impl OauthState {
+    pub fn create(conn: &PgConnection, provider: &str, user_id: Option<i32>) -> Result<Self> {
+        let now = Utc::now().naive_utc();
+        // Abandoned authorizations are cleaned up as new ones start
+        diesel::delete(
+            oauth_states::table
+                .filter(oauth_states::created_at.lt(now - Duration::minutes(LIFETIME_MINUTES))),
+        )
+        .execute(conn)?;
+        let state = diesel::insert_into(oauth_states::table)
+            .values(OauthState {
+                state: crate::auth::generate_token(),
+                provider: provider.to_owned(),
+                code_verifier: crate::auth::generate_token(),
+                created_at: now,
+                user_id,
+            })
+            .get_result::<Self>(conn)?;
+        Ok(state)
+    }
+
+    /// Removes and returns the unexpired authorization `state` of `provider`, which can only be
+    /// completed once.
+    pub fn take(conn: &PgConnection, provider: &str, state: &str) -> Result<Option<Self>> {
+        let since = Utc::now().naive_utc() - Duration::minutes(LIFETIME_MINUTES);
+        let state = diesel::delete(
+            oauth_states::table
+                .filter(oauth_states::state.eq(state))
+                .filter(oauth_states::provider.eq(provider))
+                .filter(oauth_states::created_at.gt(since)),
+        )
+        .get_result::<Self>(conn)
+        .optional()?;
+        Ok(state)
+    }
+}
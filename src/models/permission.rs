@@ -1,6 +1,9 @@
-use crate::models::{Article, Namespace, Role};
-use crate::schema::{article_permissions, namespace_permissions};
-use serde::Serialize;
+use crate::models::{Actor, Article, Namespace, PermissionLog, Role, User};
+use crate::schema::{article_permissions, articles, namespace_permissions, roles, user_roles};
+use anyhow::Result;
+use chrono::prelude::*;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Associations, Identifiable, Insertable, Queryable, Debug)]
 #[primary_key(article_id, role_id)]
@@ -29,3 +32,607 @@ pub struct NamespacePermission {
     pub can_delete: bool,
     pub can_grant: bool,
 }
+
+/// Changes to a `NamespacePermission`. `None` keeps the current value.
+#[derive(Serialize, Deserialize, AsChangeset, Default, Debug)]
+#[table_name = "namespace_permissions"]
+#[serde(rename_all = "camelCase")]
+pub struct NamespacePermissionChanges {
+    pub can_create: Option<bool>,
+    pub can_read: Option<bool>,
+    pub can_edit: Option<bool>,
+    pub can_rename: Option<bool>,
+    pub can_delete: Option<bool>,
+    pub can_grant: Option<bool>,
+}
+
+/// Values of an `ArticlePermission`. `None` falls back to the `NamespacePermission`.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticlePermissionValues {
+    pub can_read: Option<bool>,
+    pub can_edit: Option<bool>,
+    pub can_rename: Option<bool>,
+    pub can_delete: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Action {
+    Create,
+    Read,
+    Edit,
+    Rename,
+    Delete,
+    Grant,
+}
+
+impl Action {
+    pub fn all() -> [Self; 6] {
+        [
+            Self::Create,
+            Self::Read,
+            Self::Edit,
+            Self::Rename,
+            Self::Delete,
+            Self::Grant,
+        ]
+    }
+}
+
+/// Where the decision for a `Role` comes from.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PermissionSource {
+    Article,
+    Namespace,
+    /// No permission is set for the role, so it is denied.
+    None,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionDecision {
+    pub role: Role,
+    pub action: Action,
+    pub source: PermissionSource,
+    pub allowed: bool,
+}
+
+/// Effective permissions of a user or a role on a namespace or an article.
+///
+/// For each role, a value of an `ArticlePermission` overrides the `NamespacePermission`, and a
+/// role without a `NamespacePermission` is denied. Across roles, allow wins: an action is
+/// permitted if any role permits it.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Permissions {
+    pub can_create: bool,
+    pub can_read: bool,
+    pub can_edit: bool,
+    pub can_rename: bool,
+    pub can_delete: bool,
+    pub can_grant: bool,
+}
+
+/// Whose permissions are resolved.
+enum Grantee<'a> {
+    User(&'a User),
+    Anonymous,
+    Role(&'a Role),
+}
+
+impl<'a> Grantee<'a> {
+    fn role_ids(&self, conn: &PgConnection) -> Result<Vec<i32>> {
+        match self {
+            Grantee::User(user) => {
+                let now = Utc::now().naive_utc();
+                let mut role_ids = user_roles::table
+                    .filter(user_roles::user_id.eq(user.id))
+                    .filter(
+                        user_roles::expires_at
+                            .is_null()
+                            .or(user_roles::expires_at.gt(now)),
+                    )
+                    .select(user_roles::role_id)
+                    .load::<i32>(conn)?;
+                role_ids.push(Role::logged_in().id);
+                Ok(role_ids)
+            }
+            Grantee::Anonymous => Ok(vec![Role::anonymous().id]),
+            Grantee::Role(role) => Ok(vec![role.id]),
+        }
+    }
+}
+
+/// The articles a user or an anonymous visitor may read, as lists a query can filter on.
+///
+/// An article is readable when it is in `allowed_article_ids`, or when it is in one of
+/// `namespace_ids` and not in `denied_article_ids`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReadableArticles {
+    pub namespace_ids: Vec<i32>,
+    pub allowed_article_ids: Vec<i32>,
+    pub denied_article_ids: Vec<i32>,
+}
+
+impl ReadableArticles {
+    /// Resolves what `user`, or an anonymous visitor if `None`, may read, with the same rules as
+    /// `Permissions`.
+    pub fn of_user(conn: &PgConnection, user: Option<&User>) -> Result<Self> {
+        let grantee = match user {
+            Some(user) => Grantee::User(user),
+            None => Grantee::Anonymous,
+        };
+        let role_ids = grantee.role_ids(conn)?;
+        let namespace_rows = namespace_permissions::table
+            .filter(namespace_permissions::role_id.eq_any(&role_ids))
+            .filter(namespace_permissions::can_read.eq(true))
+            .select((
+                namespace_permissions::role_id,
+                namespace_permissions::namespace_id,
+            ))
+            .load::<(i32, i32)>(conn)?;
+        let article_rows = article_permissions::table
+            .inner_join(articles::table)
+            .filter(article_permissions::role_id.eq_any(&role_ids))
+            .filter(article_permissions::can_read.is_not_null())
+            .select((
+                article_permissions::role_id,
+                article_permissions::article_id,
+                articles::namespace_id,
+                article_permissions::can_read,
+            ))
+            .load::<(i32, i32, i32, Option<bool>)>(conn)?;
+        let mut readable = Self::default();
+        for (_, namespace_id) in namespace_rows.iter() {
+            if !readable.namespace_ids.contains(namespace_id) {
+                readable.namespace_ids.push(*namespace_id);
+            }
+        }
+        for (_, article_id, namespace_id, _) in article_rows.iter() {
+            if readable.allowed_article_ids.contains(article_id)
+                || readable.denied_article_ids.contains(article_id)
+            {
+                continue;
+            }
+            let overrides = article_rows
+                .iter()
+                .filter(|(_, id, _, _)| id == article_id)
+                .collect::<Vec<_>>();
+            if overrides
+                .iter()
+                .any(|(_, _, _, can_read)| *can_read == Some(true))
+            {
+                readable.allowed_article_ids.push(*article_id);
+                continue;
+            }
+            // Denied by the roles overriding it, but still readable through any other role
+            // reading its namespace
+            let readable_through_namespace = namespace_rows.iter().any(|(role_id, id)| {
+                id == namespace_id
+                    && !overrides
+                        .iter()
+                        .any(|(override_role_id, _, _, _)| override_role_id == role_id)
+            });
+            if !readable_through_namespace {
+                readable.denied_article_ids.push(*article_id);
+            }
+        }
+        Ok(readable)
+    }
+}
+
+impl Permissions {
+    /// Resolves the permissions of `user`, or of an anonymous visitor if `None`.
+    ///
+    /// A user implicitly has `LoggedIn` besides their unexpired roles, and an anonymous visitor
+    /// has `Anonymous` only.
+    pub fn of_user(
+        conn: &PgConnection,
+        user: Option<&User>,
+        namespace_id: i32,
+        article_id: Option<i32>,
+    ) -> Result<Self> {
+        let grantee = match user {
+            Some(user) => Grantee::User(user),
+            None => Grantee::Anonymous,
+        };
+        Self::resolve(conn, grantee, namespace_id, article_id)
+    }
+
+    /// Resolves the permissions of `role` alone.
+    pub fn of_role(
+        conn: &PgConnection,
+        role: &Role,
+        namespace_id: i32,
+        article_id: Option<i32>,
+    ) -> Result<Self> {
+        Self::resolve(conn, Grantee::Role(role), namespace_id, article_id)
+    }
+
+    pub fn get(&self, action: Action) -> bool {
+        match action {
+            Action::Create => self.can_create,
+            Action::Read => self.can_read,
+            Action::Edit => self.can_edit,
+            Action::Rename => self.can_rename,
+            Action::Delete => self.can_delete,
+            Action::Grant => self.can_grant,
+        }
+    }
+
+    /// Loads the permission rows of every role of `grantee` in a single query and combines them.
+    fn resolve(
+        conn: &PgConnection,
+        grantee: Grantee,
+        namespace_id: i32,
+        article_id: Option<i32>,
+    ) -> Result<Self> {
+        let query = roles::table
+            .left_join(
+                namespace_permissions::table.on(namespace_permissions::role_id
+                    .eq(roles::id)
+                    .and(namespace_permissions::namespace_id.eq(namespace_id))),
+            )
+            .left_join(
+                article_permissions::table.on(article_permissions::role_id
+                    .eq(roles::id)
+                    .and(article_permissions::article_id.nullable().eq(article_id))),
+            )
+            .select((
+                namespace_permissions::all_columns.nullable(),
+                article_permissions::all_columns.nullable(),
+            ))
+            .into_boxed();
+        let query = match grantee {
+            Grantee::User(user) => {
+                let now = Utc::now().naive_utc();
+                let role_ids = user_roles::table
+                    .filter(user_roles::user_id.eq(user.id))
+                    .filter(
+                        user_roles::expires_at
+                            .is_null()
+                            .or(user_roles::expires_at.gt(now)),
+                    )
+                    .select(user_roles::role_id);
+                query.filter(
+                    roles::id
+                        .eq_any(role_ids)
+                        .or(roles::id.eq(Role::logged_in().id)),
+                )
+            }
+            Grantee::Anonymous => query.filter(roles::id.eq(Role::anonymous().id)),
+            Grantee::Role(role) => query.filter(roles::id.eq(role.id)),
+        };
+        let rows = query.load::<(Option<NamespacePermission>, Option<ArticlePermission>)>(conn)?;
+        let allows = |action: Action| {
+            rows.iter()
+                .any(|(namespace_permission, article_permission)| {
+                    match article_permission.as_ref().and_then(|p| p.get(action)) {
+                        Some(allowed) => allowed,
+                        None => namespace_permission
+                            .as_ref()
+                            .map_or(false, |p| p.get(action)),
+                    }
+                })
+        };
+        Ok(Self {
+            can_create: allows(Action::Create),
+            can_read: allows(Action::Read),
+            can_edit: allows(Action::Edit),
+            can_rename: allows(Action::Rename),
+            can_delete: allows(Action::Delete),
+            can_grant: allows(Action::Grant),
+        })
+    }
+}
+
+impl ArticlePermission {
+    pub fn find(conn: &PgConnection, article: &Article, role: &Role) -> Result<Option<Self>> {
+        let permission = article_permissions::table
+            .find((article.id, role.id))
+            .first::<Self>(conn)
+            .optional()?;
+        Ok(permission)
+    }
+
+    pub fn get(&self, action: Action) -> Option<bool> {
+        match action {
+            Action::Read => self.can_read,
+            Action::Edit => self.can_edit,
+            Action::Rename => self.can_rename,
+            Action::Delete => self.can_delete,
+            Action::Create | Action::Grant => None,
+        }
+    }
+
+    /// Replaces the permission of `role` on `article`, logging the change.
+    ///
+    /// The row is removed when every value is `None`.
+    pub fn set(
+        conn: &PgConnection,
+        article: &Article,
+        role: &Role,
+        values: &ArticlePermissionValues,
+        actor: &Actor,
+    ) -> Result<Option<Self>> {
+        conn.transaction(|| {
+            let old = Self::find(conn, article, role)?;
+            let is_empty = values.can_read.is_none()
+                && values.can_edit.is_none()
+                && values.can_rename.is_none()
+                && values.can_delete.is_none();
+            let new = if is_empty {
+                diesel::delete(article_permissions::table.find((article.id, role.id)))
+                    .execute(conn)?;
+                None
+            } else {
+                let permission = Self {
+                    article_id: article.id,
+                    role_id: role.id,
+                    can_read: values.can_read,
+                    can_edit: values.can_edit,
+                    can_rename: values.can_rename,
+                    can_delete: values.can_delete,
+                };
+                let permission = diesel::insert_into(article_permissions::table)
+                    .values(&permission)
+                    .on_conflict((
+                        article_permissions::article_id,
+                        article_permissions::role_id,
+                    ))
+                    .do_update()
+                    .set((
+                        article_permissions::can_read.eq(values.can_read),
+                        article_permissions::can_edit.eq(values.can_edit),
+                        article_permissions::can_rename.eq(values.can_rename),
+                        article_permissions::can_delete.eq(values.can_delete),
+                    ))
+                    .get_result::<Self>(conn)?;
+                Some(permission)
+            };
+            PermissionLog::create(
+                conn,
+                actor,
+                role,
+                Some(article.namespace_id),
+                Some(article.id),
+                old.as_ref().map(serde_json::to_value).transpose()?,
+                new.as_ref().map(serde_json::to_value).transpose()?,
+            )?;
+            Ok(new)
+        })
+    }
+}
+
+impl NamespacePermission {
+    pub fn find(conn: &PgConnection, namespace: &Namespace, role: &Role) -> Result<Option<Self>> {
+        let permission = namespace_permissions::table
+            .find((namespace.id, role.id))
+            .first::<Self>(conn)
+            .optional()?;
+        Ok(permission)
+    }
+
+    pub fn get(&self, action: Action) -> bool {
+        match action {
+            Action::Create => self.can_create,
+            Action::Read => self.can_read,
+            Action::Edit => self.can_edit,
+            Action::Rename => self.can_rename,
+            Action::Delete => self.can_delete,
+            Action::Grant => self.can_grant,
+        }
+    }
+
+    /// Applies `changes` to the permission of `role` on `namespace`, logging the change.
+    ///
+    /// A role without a permission yet starts from denying everything.
+    pub fn update(
+        conn: &PgConnection,
+        namespace: &Namespace,
+        role: &Role,
+        changes: &NamespacePermissionChanges,
+        actor: &Actor,
+    ) -> Result<Self> {
+        conn.transaction(|| {
+            let old = Self::find(conn, namespace, role)?;
+            if old.is_none() {
+                diesel::insert_into(namespace_permissions::table)
+                    .values(&Self {
+                        namespace_id: namespace.id,
+                        role_id: role.id,
+                        can_create: false,
+                        can_read: false,
+                        can_edit: false,
+                        can_rename: false,
+                        can_delete: false,
+                        can_grant: false,
+                    })
+                    .execute(conn)?;
+            }
+            let target = namespace_permissions::table.find((namespace.id, role.id));
+            let new = if changes.can_create.is_none()
+                && changes.can_read.is_none()
+                && changes.can_edit.is_none()
+                && changes.can_rename.is_none()
+                && changes.can_delete.is_none()
+                && changes.can_grant.is_none()
+            {
+                // Diesel refuses an empty changeset
+                target.first::<Self>(conn)?
+            } else {
+                diesel::update(target)
+                    .set(changes)
+                    .get_result::<Self>(conn)?
+            };
+            PermissionLog::create(
+                conn,
+                actor,
+                role,
+                Some(namespace.id),
+                None,
+                old.as_ref().map(serde_json::to_value).transpose()?,
+                Some(serde_json::to_value(&new)?),
+            )?;
+            Ok(new)
+        })
+    }
+}
+
+impl PermissionDecision {
+    /// Explains how the permissions of `role` on `article` are decided, without acting on them.
+    ///
+    /// An `ArticlePermission` value overrides the `NamespacePermission`.
+    pub fn explain(conn: &PgConnection, role: &Role, article: &Article) -> Result<Vec<Self>> {
+        let article_permission = ArticlePermission::find(conn, article, role)?;
+        let namespace_permission = namespace_permissions::table
+            .find((article.namespace_id, role.id))
+            .first::<NamespacePermission>(conn)
+            .optional()?;
+        let decisions = Action::all()
+            .iter()
+            .map(|action| {
+                let (source, allowed) = match (
+                    article_permission.as_ref().and_then(|p| p.get(*action)),
+                    &namespace_permission,
+                ) {
+                    (Some(allowed), _) => (PermissionSource::Article, allowed),
+                    (None, Some(permission)) => {
+                        (PermissionSource::Namespace, permission.get(*action))
+                    }
+                    (None, None) => (PermissionSource::None, false),
+                };
+                Self {
+                    role: role.clone(),
+                    action: *action,
+                    source,
+                    allowed,
+                }
+            })
+            .collect();
+        Ok(decisions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use crate::schema::permission_logs;
+    use ipnetwork::IpNetwork;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_set_article_permission() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let article = Article::create(
+                &conn,
+                &Namespace::get_default(&conn).expect("must succeed"),
+                "test",
+                "==test==",
+                "Comment!",
+                &actor,
+                false,
+            )
+            .expect("must succeed");
+            let role = Role::anonymous();
+            let values = ArticlePermissionValues {
+                can_edit: Some(false),
+                ..Default::default()
+            };
+            ArticlePermission::set(&conn, &article, &role, &values, &actor).expect("must succeed");
+            assert_eq!(role.can_edit(&conn, &article).expect("must succeed"), false);
+            let decisions =
+                PermissionDecision::explain(&conn, &role, &article).expect("must succeed");
+            let edit = decisions
+                .iter()
+                .find(|d| d.action == Action::Edit)
+                .expect("must exist");
+            assert_eq!(edit.source, PermissionSource::Article);
+            assert_eq!(edit.allowed, false);
+            let read = decisions
+                .iter()
+                .find(|d| d.action == Action::Read)
+                .expect("must exist");
+            assert_eq!(read.source, PermissionSource::Namespace);
+            let removed = ArticlePermission::set(
+                &conn,
+                &article,
+                &role,
+                &ArticlePermissionValues::default(),
+                &actor,
+            )
+            .expect("must succeed");
+            assert!(removed.is_none());
+            assert_eq!(role.can_edit(&conn, &article).expect("must succeed"), true);
+            let logs = permission_logs::table
+                .filter(permission_logs::article_id.eq(article.id))
+                .count()
+                .get_result::<i64>(&conn)
+                .expect("must succeed");
+            assert_eq!(logs, 2);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_readable_articles() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let article = Article::create(
+                &conn,
+                &Namespace::get_default(&conn).expect("must succeed"),
+                "test",
+                "==test==",
+                "Comment!",
+                &actor,
+                false,
+            )
+            .expect("must succeed");
+            let values = ArticlePermissionValues {
+                can_read: Some(false),
+                ..Default::default()
+            };
+            ArticlePermission::set(&conn, &article, &Role::anonymous(), &values, &actor)
+                .expect("must succeed");
+            let readable = ReadableArticles::of_user(&conn, None).expect("must succeed");
+            assert!(readable.namespace_ids.contains(&article.namespace_id));
+            assert!(readable.denied_article_ids.contains(&article.id));
+            // LoggedIn still reads the namespace
+            let user = User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            let readable = ReadableArticles::of_user(&conn, Some(&user)).expect("must succeed");
+            assert!(!readable.denied_article_ids.contains(&article.id));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_permissions_without_namespace_permission() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let user = User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            let role = Role::create(&conn, "Test Role").expect("must succeed");
+            user.add_role(&conn, &role).expect("must succeed");
+            let namespace = Namespace::get_default(&conn).expect("must succeed");
+            let permissions =
+                Permissions::of_role(&conn, &role, namespace.id, None).expect("must succeed");
+            assert_eq!(permissions, Permissions::default());
+            // LoggedIn is implied for users
+            let permissions =
+                Permissions::of_user(&conn, Some(&user), namespace.id, None).expect("must succeed");
+            assert_eq!(permissions.can_create, true);
+            assert_eq!(permissions.can_grant, false);
+            let permissions =
+                Permissions::of_user(&conn, None, namespace.id, None).expect("must succeed");
+            assert_eq!(permissions.can_rename, false);
+            Ok(())
+        });
+    }
+}
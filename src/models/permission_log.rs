@@ -0,0 +1,85 @@
+use crate::models::{Actor, Namespace, Role};
+use crate::schema::{articles, permission_logs};
+use anyhow::Result;
+use chrono::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+
+/// A change to a `NamespacePermission` or an `ArticlePermission`.
+///
+/// `old_value` and `new_value` hold the whole row, `None` meaning it did not exist.
+#[derive(Serialize, Queryable, Identifiable, Debug)]
+pub struct PermissionLog {
+    pub id: i32,
+    pub actor_id: i32,
+    /// `None` if the role has been deleted since.
+    pub role_id: Option<i32>,
+    pub namespace_id: Option<i32>,
+    pub article_id: Option<i32>,
+    pub old_value: Option<Value>,
+    pub new_value: Option<Value>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "permission_logs"]
+struct NewPermissionLog {
+    pub actor_id: i32,
+    pub role_id: Option<i32>,
+    pub namespace_id: Option<i32>,
+    pub article_id: Option<i32>,
+    pub old_value: Option<Value>,
+    pub new_value: Option<Value>,
+    pub created_at: NaiveDateTime,
+}
+
+impl PermissionLog {
+    pub fn create(
+        conn: &PgConnection,
+        actor: &Actor,
+        role: &Role,
+        namespace_id: Option<i32>,
+        article_id: Option<i32>,
+        old_value: Option<Value>,
+        new_value: Option<Value>,
+    ) -> Result<Self> {
+        let new_log = NewPermissionLog {
+            actor_id: actor.id,
+            role_id: Some(role.id),
+            namespace_id,
+            article_id,
+            old_value,
+            new_value,
+            created_at: Utc::now().naive_utc(),
+        };
+        let log = diesel::insert_into(permission_logs::table)
+            .values(new_log)
+            .get_result::<Self>(conn)?;
+        Ok(log)
+    }
+
+    /// Lists the changes in `namespace`, including those to its articles, newest first.
+    pub fn find_by_namespace(
+        conn: &PgConnection,
+        namespace: &Namespace,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>> {
+        let article_ids = articles::table
+            .filter(articles::namespace_id.eq(namespace.id))
+            .select(articles::id);
+        let logs = permission_logs::table
+            .filter(
+                permission_logs::namespace_id
+                    .eq(namespace.id)
+                    .or(permission_logs::article_id.eq_any(article_ids.nullable())),
+            )
+            .order(permission_logs::id.desc())
+            .offset(offset)
+            .limit(limit)
+            .load::<Self>(conn)?;
+        Ok(logs)
+    }
+}
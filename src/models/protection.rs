@@ -0,0 +1,419 @@
+use crate::models::{Actor, Article, Namespace, Role, TemplateLink, User};
+use crate::schema::protections;
+use anyhow::{anyhow, Result};
+use chrono::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// What a `Protection` restricts.
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[sql_type = "Text"]
+pub enum ProtectionAction {
+    /// Creating an article with a title which does not exist yet.
+    Create,
+    Edit,
+    Rename,
+}
+
+impl ProtectionAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProtectionAction::Create => "create",
+            ProtectionAction::Edit => "edit",
+            ProtectionAction::Rename => "rename",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for ProtectionAction {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for ProtectionAction {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"create" => Ok(ProtectionAction::Create),
+            b"edit" => Ok(ProtectionAction::Edit),
+            b"rename" => Ok(ProtectionAction::Rename),
+            _ => Err("Unrecognized protection action".into()),
+        }
+    }
+}
+
+/// Restricts `action` on a title to members of `allowed_role_id` until `expires_at`.
+///
+/// Protections are keyed by title so that titles which do not exist yet can be protected. A
+/// cascading protection also protects the templates its article transcludes, through
+/// protections whose `source_id` points back to it.
+#[derive(Serialize, Queryable, Identifiable, Debug)]
+pub struct Protection {
+    pub id: i32,
+    pub namespace_id: i32,
+    pub title: String,
+    pub action: ProtectionAction,
+    pub allowed_role_id: i32,
+    pub reason: String,
+    pub actor_id: i32,
+    pub cascading: bool,
+    pub source_id: Option<i32>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "protections"]
+struct NewProtection<'a> {
+    pub namespace_id: i32,
+    pub title: &'a str,
+    pub action: ProtectionAction,
+    pub allowed_role_id: i32,
+    pub reason: &'a str,
+    pub actor_id: i32,
+    pub cascading: bool,
+    pub source_id: Option<i32>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl Protection {
+    /// Protects a title, replacing its current protection against `action`.
+    ///
+    /// `Create` only applies to titles without an article, the other actions only to existing
+    /// articles.
+    #[allow(clippy::too_many_arguments)]
+    pub fn protect(
+        conn: &PgConnection,
+        namespace: &Namespace,
+        title: &str,
+        action: ProtectionAction,
+        allowed_role: &Role,
+        reason: &str,
+        actor: &Actor,
+        cascading: bool,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<Self> {
+        let title = &namespace.normalize_title(title);
+        let article = Article::find(conn, namespace, title)?;
+        match (action, &article) {
+            (ProtectionAction::Create, Some(_)) => {
+                return Err(anyhow!("Article {} already exists", namespace.join(title)));
+            }
+            (ProtectionAction::Edit, None) | (ProtectionAction::Rename, None) => {
+                return Err(anyhow!("No article found: {}", namespace.join(title)));
+            }
+            _ => {}
+        }
+        conn.transaction(|| {
+            Self::unprotect(conn, namespace, title, action)?;
+            let protection = diesel::insert_into(protections::table)
+                .values(NewProtection {
+                    namespace_id: namespace.id,
+                    title,
+                    action,
+                    allowed_role_id: allowed_role.id,
+                    reason,
+                    actor_id: actor.id,
+                    cascading,
+                    source_id: None,
+                    expires_at,
+                    created_at: Utc::now().naive_utc(),
+                })
+                .get_result::<Self>(conn)?;
+            if let (true, Some(article)) = (cascading, &article) {
+                protection.cascade(conn, article)?;
+            }
+            Ok(protection)
+        })
+    }
+
+    /// Removes the protection of a title against `action`, with the protections cascading from
+    /// it. Returns whether it was protected.
+    pub fn unprotect(
+        conn: &PgConnection,
+        namespace: &Namespace,
+        title: &str,
+        action: ProtectionAction,
+    ) -> Result<bool> {
+        let count = diesel::delete(
+            protections::table
+                .filter(protections::namespace_id.eq(namespace.id))
+                .filter(protections::title.eq(namespace.normalize_title(title)))
+                .filter(protections::action.eq(action))
+                .filter(protections::source_id.is_null()),
+        )
+        .execute(conn)?;
+        Ok(count > 0)
+    }
+
+    /// Protects the templates transcluded by `article` like this protection.
+    fn cascade(&self, conn: &PgConnection, article: &Article) -> Result<()> {
+        diesel::delete(protections::table.filter(protections::source_id.eq(self.id)))
+            .execute(conn)?;
+        let links = TemplateLink::find_by_article(conn, article)?;
+        let now = Utc::now().naive_utc();
+        let cascaded = links
+            .iter()
+            .map(|link| NewProtection {
+                namespace_id: link.namespace_id,
+                title: &link.title,
+                action: self.action,
+                allowed_role_id: self.allowed_role_id,
+                reason: &self.reason,
+                actor_id: self.actor_id,
+                cascading: false,
+                source_id: Some(self.id),
+                expires_at: self.expires_at,
+                created_at: now,
+            })
+            .collect::<Vec<NewProtection>>();
+        diesel::insert_into(protections::table)
+            .values(&cascaded)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Updates the cascaded protections of `article` after the templates it transcludes changed.
+    pub fn refresh_cascades(conn: &PgConnection, article: &Article) -> Result<()> {
+        let cascading = Self::active()
+            .filter(protections::namespace_id.eq(article.namespace_id))
+            .filter(protections::title.eq(article.title.clone()))
+            .filter(protections::cascading.eq(true))
+            .filter(protections::source_id.is_null())
+            .load::<Self>(conn)?;
+        for protection in cascading.iter() {
+            protection.cascade(conn, article)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the protections of the title `article` was renamed from to its new title. `Create`
+    /// protections stay, as the old title no longer has an article.
+    pub fn follow_rename(
+        conn: &PgConnection,
+        old_namespace_id: i32,
+        old_title: &str,
+        article: &Article,
+    ) -> Result<()> {
+        diesel::update(
+            protections::table
+                .filter(protections::namespace_id.eq(old_namespace_id))
+                .filter(protections::title.eq(old_title))
+                .filter(protections::action.ne(ProtectionAction::Create)),
+        )
+        .set((
+            protections::namespace_id.eq(article.namespace_id),
+            protections::title.eq(&article.title),
+        ))
+        .execute(conn)?;
+        Ok(())
+    }
+
+    /// Protections which have not expired.
+    fn active() -> protections::BoxedQuery<'static, Pg> {
+        let now = Utc::now().naive_utc();
+        protections::table
+            .filter(
+                protections::expires_at
+                    .is_null()
+                    .or(protections::expires_at.gt(now)),
+            )
+            .into_boxed()
+    }
+
+    /// Active protections of a title, including cascaded ones.
+    pub fn find_active(
+        conn: &PgConnection,
+        namespace: &Namespace,
+        title: &str,
+    ) -> Result<Vec<Self>> {
+        let protections = Self::active()
+            .filter(protections::namespace_id.eq(namespace.id))
+            .filter(protections::title.eq(namespace.normalize_title(title)))
+            .order(protections::id.asc())
+            .load::<Self>(conn)?;
+        Ok(protections)
+    }
+
+    /// Active protections, newest first.
+    pub fn list_active(
+        conn: &PgConnection,
+        action: Option<ProtectionAction>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>> {
+        let mut query = Self::active();
+        if let Some(action) = action {
+            query = query.filter(protections::action.eq(action));
+        }
+        let protections = query
+            .order(protections::id.desc())
+            .offset(offset)
+            .limit(limit)
+            .load::<Self>(conn)?;
+        Ok(protections)
+    }
+
+    /// Whether `user`, or an anonymous visitor if `None`, may do `action` on a title despite its
+    /// protections. Every protection must be satisfied; `Root` satisfies any.
+    pub fn allows(
+        conn: &PgConnection,
+        user: Option<&User>,
+        namespace: &Namespace,
+        title: &str,
+        action: ProtectionAction,
+    ) -> Result<bool> {
+        let protections = Self::find_active(conn, namespace, title)?;
+        for protection in protections.iter().filter(|p| p.action == action) {
+            let user = match user {
+                Some(user) => user,
+                None => return Ok(false),
+            };
+            if protection.allowed_role_id == Role::logged_in().id {
+                continue;
+            }
+            let allowed_role = match Role::find_by_id(conn, protection.allowed_role_id)? {
+                Some(role) => role,
+                None => Role::root(),
+            };
+            if !user.has_any_role(conn, &[Role::root(), allowed_role])? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn get_full_title(&self, conn: &PgConnection) -> Result<String> {
+        let namespace = Namespace::find_by_id(conn, self.namespace_id)?
+            .ok_or_else(|| anyhow!("No namespace found with id: {}", self.namespace_id))?;
+        Ok(namespace.join(&self.title))
+    }
+
+    /// The cascading protection this protection comes from.
+    pub fn get_source(&self, conn: &PgConnection) -> Result<Option<Self>> {
+        match self.source_id {
+            Some(source_id) => Ok(Some(
+                protections::table.find(source_id).first::<Self>(conn)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_actor(&self, conn: &PgConnection) -> Result<Actor> {
+        use crate::schema::actors;
+        let actor = actors::table.find(self.actor_id).first::<Actor>(conn)?;
+        Ok(actor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use ipnetwork::IpNetwork;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_cascading_protection() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let namespace = Namespace::get_default(&conn).expect("must succeed");
+            let user = User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            user.add_role(&conn, &Role::logged_in())
+                .expect("must succeed");
+            Article::create(
+                &conn, &namespace, "test", "{{box}}", "Comment!", &actor, false,
+            )
+            .expect("must succeed");
+            Protection::protect(
+                &conn,
+                &namespace,
+                "test",
+                ProtectionAction::Edit,
+                &Role::root(),
+                "Vandalism",
+                &actor,
+                true,
+                None,
+            )
+            .expect("must succeed");
+            for title in &["test", "box"] {
+                let allowed = Protection::allows(
+                    &conn,
+                    Some(&user),
+                    &namespace,
+                    title,
+                    ProtectionAction::Edit,
+                )
+                .expect("must succeed");
+                assert_eq!(allowed, false);
+            }
+            let allowed = Protection::allows(
+                &conn,
+                Some(&user),
+                &namespace,
+                "test",
+                ProtectionAction::Rename,
+            )
+            .expect("must succeed");
+            assert_eq!(allowed, true);
+            assert!(
+                Protection::unprotect(&conn, &namespace, "test", ProtectionAction::Edit)
+                    .expect("must succeed")
+            );
+            let allowed = Protection::allows(
+                &conn,
+                Some(&user),
+                &namespace,
+                "box",
+                ProtectionAction::Edit,
+            )
+            .expect("must succeed");
+            assert_eq!(allowed, true);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_expired_protection() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let namespace = Namespace::get_default(&conn).expect("must succeed");
+            let expired = Utc::now().naive_utc() - chrono::Duration::days(1);
+            Protection::protect(
+                &conn,
+                &namespace,
+                "nonexistent",
+                ProtectionAction::Create,
+                &Role::root(),
+                "Spam",
+                &actor,
+                false,
+                Some(expired),
+            )
+            .expect("must succeed");
+            let allowed = Protection::allows(
+                &conn,
+                None,
+                &namespace,
+                "nonexistent",
+                ProtectionAction::Create,
+            )
+            .expect("must succeed");
+            assert_eq!(allowed, true);
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,93 @@
+use crate::schema::rate_limit_counters;
+use anyhow::Result;
+use chrono::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+/// Hits of a rate limit key within one window, shared by every instance.
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "rate_limit_counters"]
+pub struct RateLimitCounter {
+    pub key: String,
+    pub window_start: NaiveDateTime,
+    pub hits: i32,
+    pub expires_at: NaiveDateTime,
+}
+
+impl RateLimitCounter {
+    /// Counts a hit of `key` in the window starting at `window_start`, dropping its counters of
+    /// windows which ended. Returns the hits in the window so far.
+    pub fn hit(
+        conn: &PgConnection,
+        key: &str,
+        window_start: NaiveDateTime,
+        expires_at: NaiveDateTime,
+    ) -> Result<i32> {
+        diesel::delete(
+            rate_limit_counters::table
+                .filter(rate_limit_counters::key.eq(key))
+                .filter(rate_limit_counters::expires_at.le(Utc::now().naive_utc())),
+        )
+        .execute(conn)?;
+        let hits = diesel::insert_into(rate_limit_counters::table)
+            .values(Self {
+                key: key.to_owned(),
+                window_start,
+                hits: 1,
+                expires_at,
+            })
+            .on_conflict((rate_limit_counters::key, rate_limit_counters::window_start))
+            .do_update()
+            .set(rate_limit_counters::hits.eq(rate_limit_counters::hits + 1))
+            .returning(rate_limit_counters::hits)
+            .get_result::<i32>(conn)?;
+        Ok(hits)
+    }
+
+    /// Drops the counters of every key whose window ended. Returns how many were dropped.
+    pub fn prune(conn: &PgConnection) -> Result<usize> {
+        let pruned = diesel::delete(
+            rate_limit_counters::table
+                .filter(rate_limit_counters::expires_at.le(Utc::now().naive_utc())),
+        )
+        .execute(conn)?;
+        Ok(pruned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use chrono::Duration;
+
+    #[test]
+    fn test_hit() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let window_start = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+            let expires_at = window_start + Duration::hours(1);
+            for expected in 1..=3 {
+                let hits =
+                    RateLimitCounter::hit(&conn, "edit:ip:192.0.2.1", window_start, expires_at)
+                        .expect("must succeed");
+                assert_eq!(hits, expected);
+            }
+            let hits = RateLimitCounter::hit(&conn, "edit:ip:192.0.2.2", window_start, expires_at)
+                .expect("must succeed");
+            assert_eq!(hits, 1);
+
+            let expired_start = window_start - Duration::hours(2);
+            RateLimitCounter::hit(
+                &conn,
+                "edit:ip:192.0.2.3",
+                expired_start,
+                expired_start + Duration::hours(1),
+            )
+            .expect("must succeed");
+            let pruned = RateLimitCounter::prune(&conn).expect("must succeed");
+            assert_eq!(pruned, 1);
+            Ok(())
+        });
+    }
+}
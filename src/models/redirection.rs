@@ -1,4 +1,4 @@
-use crate::models::{Actor, Article, Namespace, Revision};
+use crate::models::{Actor, Article, Namespace, Revision, RevisionKind, RevisionPayload};
 use crate::schema::redirections;
 use anyhow::{anyhow, Result};
 use chrono::NaiveDateTime;
@@ -27,7 +27,7 @@ impl Redirection {
     pub fn find(conn: &PgConnection, namespace: &Namespace, title: &str) -> Result<Option<Self>> {
         let redirection = redirections::table
             .filter(redirections::namespace_id.eq(namespace.id))
-            .filter(redirections::title.eq(title))
+            .filter(redirections::title.eq(namespace.normalize_title(title)))
             .first::<Self>(conn)
             .optional()?;
         Ok(redirection)
@@ -39,7 +39,9 @@ impl Redirection {
         title: &str,
         comment: &str,
         actor: &Actor,
+        is_bot: bool,
     ) -> Result<(Self, Revision)> {
+        let title = &namespace.normalize_title(title);
         if let Some(_) = Self::find(conn, namespace, title)? {
             return Err(anyhow!(
                 "Redirection {} already exists",
@@ -61,14 +63,17 @@ impl Redirection {
             let redirection = diesel::insert_into(redirections::table)
                 .values(new_redirection)
                 .get_result::<Self>(conn)?;
+            let payload = RevisionPayload {
+                redirection_source: Some(Namespace::join(namespace, title)),
+                ..Default::default()
+            };
             let revision = target.add_null_revision(
                 conn,
-                &format!(
-                    "(Add redirection: <- {}) {}",
-                    Namespace::join(namespace, title),
-                    comment
-                ),
+                RevisionKind::AddRedirection,
+                &payload,
+                comment,
                 actor,
+                is_bot,
             )?;
             Ok((redirection, revision))
         })
@@ -90,26 +95,34 @@ mod tests {
             let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
             let mut article = Article::create(
                 &conn,
-                &Namespace::default(),
+                &Namespace::get_default(&conn).expect("must succeed"),
                 "test",
                 "==test==",
                 "Comment!",
                 &actor,
+                false,
             )
             .expect("must succeed");
 
             let (_redirection, rev) = Redirection::create(
                 &conn,
                 &mut article,
-                &Namespace::default(),
+                &Namespace::get_default(&conn).expect("must succeed"),
                 "redirection_test",
                 "redirection comment",
                 &actor,
+                false,
             )
             .expect("must succeed");
+            assert_eq!(rev.comment, "redirection comment");
+            assert_eq!(rev.kind, RevisionKind::AddRedirection);
+            let payload = rev
+                .get_payload()
+                .expect("must succeed")
+                .expect("must exist");
             assert_eq!(
-                rev.comment,
-                "(Add redirection: <- redirection_test) redirection comment"
+                payload.redirection_source,
+                Some("redirection_test".to_owned())
             );
             Ok(())
         });
@@ -1,10 +1,82 @@
-use crate::models::{Actor, Article};
-use crate::schema::{actors, contents, revisions};
+use crate::models::{Actor, Article, Content, Namespace, ReadableArticles, RevisionTag};
+use crate::schema::{actors, articles, contents, revision_tags, revisions};
 use anyhow::Result;
 use chrono::prelude::*;
 use chrono::NaiveDateTime;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
 use diesel::prelude::*;
-use serde::Serialize;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{Array, Integer, Nullable, Text};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Write;
+
+/// What a `Revision` did to its `Article`.
+///
+/// Serialized the same way it is stored, e.g. `add_redirection`.
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Text"]
+pub enum RevisionKind {
+    Create,
+    Edit,
+    Rename,
+    Delete,
+    Fork,
+    AddRedirection,
+}
+
+impl RevisionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevisionKind::Create => "create",
+            RevisionKind::Edit => "edit",
+            RevisionKind::Rename => "rename",
+            RevisionKind::Delete => "delete",
+            RevisionKind::Fork => "fork",
+            RevisionKind::AddRedirection => "add_redirection",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for RevisionKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for RevisionKind {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"create" => Ok(RevisionKind::Create),
+            b"edit" => Ok(RevisionKind::Edit),
+            b"rename" => Ok(RevisionKind::Rename),
+            b"delete" => Ok(RevisionKind::Delete),
+            b"fork" => Ok(RevisionKind::Fork),
+            b"add_redirection" => Ok(RevisionKind::AddRedirection),
+            _ => Err("Unrecognized revision kind".into()),
+        }
+    }
+}
+
+/// Structured details of a rename, delete, fork or redirection `Revision`.
+///
+/// Stored as JSON in `revisions.payload`; fields which do not apply to the kind are omitted.
+/// Titles are full titles, including the namespace.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionPayload {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fork_source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirection_source: Option<String>,
+}
 
 #[derive(Serialize, Queryable, Identifiable, Associations, Debug)]
 #[belongs_to(Article)]
@@ -16,6 +88,29 @@ pub struct Revision {
     pub content_id: i32,
     pub comment: String,
     pub created_at: NaiveDateTime,
+    pub kind: RevisionKind,
+    pub payload: Option<Value>,
+    /// Made with an API token.
+    pub is_bot: bool,
+    /// `None` when patrolled automatically.
+    pub patrolled_by: Option<i32>,
+    pub patrolled_at: Option<NaiveDateTime>,
+}
+
+/// Size of a `Revision` compared with its parent, loaded for many revisions at once by
+/// `Revision::get_sizes`.
+#[derive(QueryableByName, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RevisionSize {
+    #[sql_type = "Integer"]
+    pub revision_id: i32,
+    /// In bytes.
+    #[sql_type = "Integer"]
+    pub size: i32,
+    /// Difference in bytes from the parent `Revision`.
+    #[sql_type = "Integer"]
+    pub size_delta: i32,
+    #[sql_type = "Nullable<Integer>"]
+    pub parent_content_id: Option<i32>,
 }
 
 #[derive(Insertable)]
@@ -26,18 +121,11 @@ pub struct NewRevision<'a> {
     pub content_id: i32,
     pub comment: &'a str,
     pub created_at: NaiveDateTime,
-}
-
-#[derive(Serialize, Queryable, Identifiable, Debug)]
-pub struct Content {
-    pub id: i32,
-    pub wikitext: String,
-}
-
-#[derive(Insertable)]
-#[table_name = "contents"]
-struct NewContent<'a> {
-    pub wikitext: &'a str,
+    pub kind: RevisionKind,
+    pub payload: Option<Value>,
+    pub is_bot: bool,
+    pub patrolled_by: Option<i32>,
+    pub patrolled_at: Option<NaiveDateTime>,
 }
 
 impl Revision {
@@ -46,24 +134,191 @@ impl Revision {
         article: &Article,
         wikitext: &str,
         comment: &str,
+        kind: RevisionKind,
         actor: &Actor,
+        is_bot: bool,
     ) -> Result<Self> {
         let now = Utc::now().naive_utc();
-        let content = diesel::insert_into(contents::table)
-            .values(NewContent { wikitext })
-            .get_result::<Content>(conn)?;
+        let content = Content::find_or_create(conn, wikitext)?;
         let new_revision = NewRevision {
             article_id: article.id,
             actor_id: actor.id,
             content_id: content.id,
             comment,
             created_at: now,
+            kind,
+            payload: None,
+            is_bot,
+            patrolled_by: None,
+            patrolled_at: None,
         };
         let revision = diesel::insert_into(revisions::table)
             .values(new_revision)
             .get_result(conn)?;
         Ok(revision)
     }
+    pub fn find_by_id(conn: &PgConnection, id: i32) -> Result<Option<Self>> {
+        let revision = revisions::table.find(id).first::<Self>(conn).optional()?;
+        Ok(revision)
+    }
+    /// Returns the most recent `Revision`s of all `Article`s, newest first, optionally only those
+    /// tagged `tag`.
+    pub fn get_recent(
+        conn: &PgConnection,
+        limit: i64,
+        hide_bots: bool,
+        tag: Option<&str>,
+    ) -> Result<Vec<Self>> {
+        let mut query = revisions::table
+            .order(revisions::id.desc())
+            .limit(limit)
+            .into_boxed();
+        if hide_bots {
+            query = query.filter(revisions::is_bot.eq(false));
+        }
+        if let Some(tag) = tag {
+            query = query.filter(
+                revisions::id.eq_any(
+                    revision_tags::table
+                        .filter(revision_tags::name.eq(tag))
+                        .select(revision_tags::revision_id),
+                ),
+            );
+        }
+        Ok(query.load::<Self>(conn)?)
+    }
+    /// Returns the `Revision`s of `article`, oldest first, optionally only those tagged `tag`.
+    pub fn find_by_article(
+        conn: &PgConnection,
+        article: &Article,
+        tag: Option<&str>,
+    ) -> Result<Vec<Self>> {
+        let mut query = Self::belonging_to(article)
+            .order(revisions::id.asc())
+            .into_boxed();
+        if let Some(tag) = tag {
+            query = query.filter(
+                revisions::id.eq_any(
+                    revision_tags::table
+                        .filter(revision_tags::name.eq(tag))
+                        .select(revision_tags::revision_id),
+                ),
+            );
+        }
+        Ok(query.load::<Self>(conn)?)
+    }
+    /// Returns `Revision`s of `readable` articles nobody patrolled yet, oldest first, optionally
+    /// only those in `namespace` or tagged `tag`.
+    pub fn find_unpatrolled(
+        conn: &PgConnection,
+        namespace: Option<&Namespace>,
+        tag: Option<&str>,
+        readable: &ReadableArticles,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>> {
+        let mut query = revisions::table
+            .inner_join(articles::table)
+            .select(revisions::all_columns)
+            .filter(revisions::patrolled_at.is_null())
+            .filter(
+                articles::id
+                    .eq_any(readable.allowed_article_ids.clone())
+                    .or(articles::namespace_id
+                        .eq_any(readable.namespace_ids.clone())
+                        .and(articles::id.ne_all(readable.denied_article_ids.clone()))),
+            )
+            .order(revisions::id.asc())
+            .offset(offset)
+            .limit(limit)
+            .into_boxed();
+        if let Some(namespace) = namespace {
+            query = query.filter(articles::namespace_id.eq(namespace.id));
+        }
+        if let Some(tag) = tag {
+            query = query.filter(
+                revisions::id.eq_any(
+                    revision_tags::table
+                        .filter(revision_tags::name.eq(tag))
+                        .select(revision_tags::revision_id),
+                ),
+            );
+        }
+        Ok(query.load::<Self>(conn)?)
+    }
+    /// Marks the revision as reviewed by `actor`. Returns `false` if somebody patrolled it first.
+    pub fn patrol(&mut self, conn: &PgConnection, actor: &Actor) -> Result<bool> {
+        let now = Utc::now().naive_utc();
+        let count = diesel::update(
+            revisions::table
+                .find(self.id)
+                .filter(revisions::patrolled_at.is_null()),
+        )
+        .set((
+            revisions::patrolled_by.eq(actor.id),
+            revisions::patrolled_at.eq(now),
+        ))
+        .execute(conn)?;
+        if count > 0 {
+            self.patrolled_by = Some(actor.id);
+            self.patrolled_at = Some(now);
+        }
+        Ok(count > 0)
+    }
+    /// Marks the revision as patrolled without a reviewer, as its author needs no review.
+    pub fn autopatrol(&mut self, conn: &PgConnection) -> Result<()> {
+        let now = Utc::now().naive_utc();
+        diesel::update(revisions::table.find(self.id))
+            .set(revisions::patrolled_at.eq(now))
+            .execute(conn)?;
+        self.patrolled_at = Some(now);
+        Ok(())
+    }
+    pub fn is_patrolled(&self) -> bool {
+        self.patrolled_at.is_some()
+    }
+    /// Returns `Revision`s made by any of `actors` of `readable` articles, newest first.
+    pub fn find_by_actors(
+        conn: &PgConnection,
+        actors: &[Actor],
+        namespace: Option<&Namespace>,
+        readable: &ReadableArticles,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>> {
+        let mut query = revisions::table
+            .inner_join(articles::table)
+            .select(revisions::all_columns)
+            .filter(
+                revisions::actor_id
+                    .eq_any(actors.iter().map(|actor| actor.id).collect::<Vec<i32>>()),
+            )
+            .filter(
+                articles::id
+                    .eq_any(readable.allowed_article_ids.clone())
+                    .or(articles::namespace_id
+                        .eq_any(readable.namespace_ids.clone())
+                        .and(articles::id.ne_all(readable.denied_article_ids.clone()))),
+            )
+            .order(revisions::id.desc())
+            .offset(offset)
+            .limit(limit)
+            .into_boxed();
+        if let Some(namespace) = namespace {
+            query = query.filter(articles::namespace_id.eq(namespace.id));
+        }
+        Ok(query.load::<Self>(conn)?)
+    }
+    /// Returns the `Revision` of the same `Article` right before this one.
+    pub fn get_parent(&self, conn: &PgConnection) -> Result<Option<Self>> {
+        let parent = revisions::table
+            .filter(revisions::article_id.eq(self.article_id))
+            .filter(revisions::id.lt(self.id))
+            .order(revisions::id.desc())
+            .first::<Self>(conn)
+            .optional()?;
+        Ok(parent)
+    }
     pub fn get_content(&self, conn: &PgConnection) -> Result<Content> {
         let content = contents::table
             .find(self.content_id)
@@ -71,10 +326,105 @@ impl Revision {
         Ok(content)
     }
     pub fn get_wikitext(&self, conn: &PgConnection) -> Result<String> {
-        Ok(self.get_content(conn)?.wikitext)
+        self.get_content(conn)?.get_wikitext(conn)
+    }
+    /// Size of the wikitext in bytes.
+    pub fn get_size(&self, conn: &PgConnection) -> Result<i32> {
+        let byte_length = contents::table
+            .find(self.content_id)
+            .select(contents::byte_length)
+            .first::<i32>(conn)?;
+        Ok(byte_length)
+    }
+    /// Size difference in bytes from the parent `Revision`.
+    pub fn get_size_delta(&self, conn: &PgConnection) -> Result<i32> {
+        let parent_size = match self.get_parent(conn)? {
+            Some(parent) => parent.get_size(conn)?,
+            None => 0,
+        };
+        Ok(self.get_size(conn)? - parent_size)
+    }
+    /// Sizes of `revisions` keyed by revision id, in a single query.
+    pub fn get_sizes(
+        conn: &PgConnection,
+        revisions: &[Revision],
+    ) -> Result<HashMap<i32, RevisionSize>> {
+        let ids = revisions.iter().map(|rev| rev.id).collect::<Vec<i32>>();
+        let sizes = diesel::sql_query(
+            "SELECT r.id AS revision_id, c.byte_length AS size, \
+             c.byte_length - COALESCE(pc.byte_length, 0) AS size_delta, \
+             p.content_id AS parent_content_id \
+             FROM revisions r \
+             JOIN contents c ON c.id = r.content_id \
+             LEFT JOIN LATERAL ( \
+                 SELECT content_id FROM revisions \
+                 WHERE article_id = r.article_id AND id < r.id \
+                 ORDER BY id DESC LIMIT 1 \
+             ) p ON true \
+             LEFT JOIN contents pc ON pc.id = p.content_id \
+             WHERE r.id = ANY($1)",
+        )
+        .bind::<Array<Integer>, _>(ids)
+        .load::<RevisionSize>(conn)?;
+        Ok(sizes
+            .into_iter()
+            .map(|size| (size.revision_id, size))
+            .collect())
+    }
+    pub fn get_payload(&self) -> Result<Option<RevisionPayload>> {
+        match &self.payload {
+            Some(payload) => Ok(Some(serde_json::from_value(payload.clone())?)),
+            None => Ok(None),
+        }
+    }
+    /// Whether this is an edit restoring the content of one of the `depth` revisions before its
+    /// parent.
+    pub fn is_revert(&self, conn: &PgConnection, depth: i64) -> Result<bool> {
+        let parent = match (self.kind, self.get_parent(conn)?) {
+            (RevisionKind::Edit, Some(parent)) => parent,
+            _ => return Ok(false),
+        };
+        if parent.content_id == self.content_id {
+            return Ok(false);
+        }
+        let earlier = revisions::table
+            .filter(revisions::article_id.eq(self.article_id))
+            .filter(revisions::id.lt(parent.id))
+            .order(revisions::id.desc())
+            .limit(depth)
+            .select(revisions::content_id)
+            .load::<i32>(conn)?;
+        Ok(earlier.contains(&self.content_id))
+    }
+    /// Whether this is an edit which did not change the content of its parent.
+    pub fn is_no_op(&self, parent_content_id: Option<i32>) -> bool {
+        self.kind == RevisionKind::Edit && parent_content_id == Some(self.content_id)
     }
     pub fn get_actor(&self, conn: &PgConnection) -> Result<Actor> {
         let actor = actors::table.find(self.actor_id).first::<Actor>(conn)?;
         Ok(actor)
     }
+    pub fn add_tags(&self, conn: &PgConnection, names: &[String]) -> Result<()> {
+        RevisionTag::add(conn, self, names)
+    }
+    pub fn get_tags(&self, conn: &PgConnection) -> Result<Vec<String>> {
+        RevisionTag::find_by_revision(conn, self)
+    }
+    /// Removes the tag `name`. Returns whether the revision had it.
+    pub fn remove_tag(&self, conn: &PgConnection, name: &str) -> Result<bool> {
+        RevisionTag::remove(conn, self, name)
+    }
+    /// Number of `Revision`s `actor` made before `before`.
+    pub fn count_by_actor(
+        conn: &PgConnection,
+        actor: &Actor,
+        before: NaiveDateTime,
+    ) -> Result<i64> {
+        let count = revisions::table
+            .filter(revisions::actor_id.eq(actor.id))
+            .filter(revisions::created_at.lt(before))
+            .count()
+            .get_result::<i64>(conn)?;
+        Ok(count)
+    }
 }
@@ -0,0 +1,169 @@
+use crate::models::{Revision, RevisionKind};
+use crate::schema::revision_tags;
+use anyhow::Result;
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+pub const NEW_PAGE_TAG: &str = "new-page";
+pub const BLANKING_TAG: &str = "blanking";
+pub const LARGE_REMOVAL_TAG: &str = "large-removal";
+pub const BOT_TAG: &str = "bot";
+pub const REVERT_TAG: &str = "revert";
+/// Tags only added by `RevisionTag::automatic`, never by hand.
+pub const AUTOMATIC_TAGS: &[&str] = &[
+    NEW_PAGE_TAG,
+    BLANKING_TAG,
+    LARGE_REMOVAL_TAG,
+    BOT_TAG,
+    REVERT_TAG,
+];
+
+/// Edits removing at least this many bytes are tagged `large-removal`.
+const LARGE_REMOVAL_BYTES: i32 = 2000;
+/// Revisions before the parent whose content an edit must restore to be tagged `revert`.
+const REVERT_DEPTH: i64 = 10;
+
+/// A label on a `Revision`, added automatically, by an abuse filter or by hand.
+#[derive(Serialize, Queryable, Insertable, Associations, Debug, PartialEq, Eq)]
+#[table_name = "revision_tags"]
+#[belongs_to(Revision)]
+pub struct RevisionTag {
+    pub revision_id: i32,
+    pub name: String,
+}
+
+impl RevisionTag {
+    /// Tags `revision` with `names`, keeping the tags it already has.
+    pub fn add(conn: &PgConnection, revision: &Revision, names: &[String]) -> Result<()> {
+        let mut tags: Vec<RevisionTag> = vec![];
+        for name in names.iter() {
+            let tag = RevisionTag {
+                revision_id: revision.id,
+                name: name.clone(),
+            };
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        diesel::insert_into(revision_tags::table)
+            .values(&tags)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Removes the tag `name` from `revision`. Returns whether it had it.
+    pub fn remove(conn: &PgConnection, revision: &Revision, name: &str) -> Result<bool> {
+        let count =
+            diesel::delete(Self::belonging_to(revision).filter(revision_tags::name.eq(name)))
+                .execute(conn)?;
+        Ok(count > 0)
+    }
+
+    /// Names of the tags of `revision`, sorted.
+    pub fn find_by_revision(conn: &PgConnection, revision: &Revision) -> Result<Vec<String>> {
+        let names = Self::belonging_to(revision)
+            .select(revision_tags::name)
+            .order(revision_tags::name.asc())
+            .load::<String>(conn)?;
+        Ok(names)
+    }
+
+    /// Sorted names of the tags of `revisions` by revision id, in a single query. Revisions
+    /// without tags are left out.
+    pub fn find_by_revisions(
+        conn: &PgConnection,
+        revisions: &[Revision],
+    ) -> Result<HashMap<i32, Vec<String>>> {
+        let ids = revisions.iter().map(|rev| rev.id).collect::<Vec<i32>>();
+        let tags = revision_tags::table
+            .filter(revision_tags::revision_id.eq_any(ids))
+            .order((revision_tags::revision_id.asc(), revision_tags::name.asc()))
+            .load::<Self>(conn)?;
+        let mut names: HashMap<i32, Vec<String>> = HashMap::new();
+        for tag in tags {
+            names.entry(tag.revision_id).or_default().push(tag.name);
+        }
+        Ok(names)
+    }
+
+    /// Names of the tags describing what `revision` did: creating a page, blanking it, removing
+    /// much of it, reverting to an earlier version or being made by a bot.
+    pub fn automatic(conn: &PgConnection, revision: &Revision) -> Result<Vec<String>> {
+        let mut names = vec![];
+        match revision.kind {
+            RevisionKind::Create => names.push(NEW_PAGE_TAG),
+            RevisionKind::Edit => {
+                let size = revision.get_size(conn)?;
+                let parent_size = match revision.get_parent(conn)? {
+                    Some(parent) => parent.get_size(conn)?,
+                    None => 0,
+                };
+                if size == 0 && parent_size > 0 {
+                    names.push(BLANKING_TAG);
+                } else if parent_size - size >= LARGE_REMOVAL_BYTES {
+                    names.push(LARGE_REMOVAL_TAG);
+                }
+                if revision.is_revert(conn, REVERT_DEPTH)? {
+                    names.push(REVERT_TAG);
+                }
+            }
+            _ => {}
+        }
+        if revision.is_bot {
+            names.push(BOT_TAG);
+        }
+        Ok(names.into_iter().map(str::to_owned).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use crate::models::{Actor, Article, Namespace};
+    use ipnetwork::IpNetwork;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_automatic() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let mut article = Article::create(
+                &conn,
+                &Namespace::get_default(&conn).expect("must succeed"),
+                "Tagged",
+                "Original",
+                "",
+                &actor,
+                false,
+            )
+            .expect("must succeed");
+            let created = article.get_latest_revision(&conn).expect("must succeed");
+            let tags = RevisionTag::automatic(&conn, &created).expect("must succeed");
+            assert_eq!(tags, vec![NEW_PAGE_TAG.to_owned()]);
+
+            let blanked = article
+                .edit(&conn, "", "", &actor, false)
+                .expect("must succeed");
+            let tags = RevisionTag::automatic(&conn, &blanked).expect("must succeed");
+            assert_eq!(tags, vec![BLANKING_TAG.to_owned()]);
+
+            let reverted = article
+                .edit(&conn, "Original", "", &actor, false)
+                .expect("must succeed");
+            let tags = RevisionTag::automatic(&conn, &reverted).expect("must succeed");
+            assert_eq!(tags, vec![REVERT_TAG.to_owned()]);
+
+            reverted.add_tags(&conn, &tags).expect("must succeed");
+            assert!(reverted
+                .remove_tag(&conn, REVERT_TAG)
+                .expect("must succeed"));
+            assert!(reverted.get_tags(&conn).expect("must succeed").is_empty());
+            Ok(())
+        });
+    }
+}
@@ -1,8 +1,17 @@
-use crate::models::{Article, ArticlePermission, Namespace, NamespacePermission, User};
-use crate::schema::{article_permissions, namespace_permissions, roles, user_roles};
-use anyhow::Result;
+use crate::models::{
+    Article, ArticlePermission, Namespace, NamespacePermission, Permissions, User,
+};
+use crate::schema::{protections, role_rights, roles, user_roles, users};
+use anyhow::{anyhow, Result};
+use chrono::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
 use diesel::prelude::*;
-use serde::Serialize;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
 
 #[derive(Serialize, Queryable, Identifiable, Debug, Eq, Clone)]
 pub struct Role {
@@ -17,6 +26,63 @@ pub struct Role {
 pub struct UserRole {
     pub user_id: i32,
     pub role_id: i32,
+    /// The role is ignored from this time on, for temporary rights.
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// A site-wide right which is not tied to a namespace.
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[sql_type = "Text"]
+pub enum Right {
+    ManageRoles,
+    /// Blocking users and addresses from editing.
+    Block,
+    /// Creating, changing and testing abuse filters.
+    ManageAbuseFilters,
+    /// Marking revisions as patrolled and tagging them by hand.
+    Patrol,
+    /// Having one's own revisions patrolled automatically, e.g. for trusted bots.
+    Autopatrol,
+}
+
+impl Right {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Right::ManageRoles => "manage_roles",
+            Right::Block => "block",
+            Right::ManageAbuseFilters => "manage_abuse_filters",
+            Right::Patrol => "patrol",
+            Right::Autopatrol => "autopatrol",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for Right {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for Right {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"manage_roles" => Ok(Right::ManageRoles),
+            b"block" => Ok(Right::Block),
+            b"manage_abuse_filters" => Ok(Right::ManageAbuseFilters),
+            b"patrol" => Ok(Right::Patrol),
+            b"autopatrol" => Ok(Right::Autopatrol),
+            _ => Err("Unrecognized right".into()),
+        }
+    }
+}
+
+#[derive(Serialize, Associations, Identifiable, Insertable, Queryable, Debug)]
+#[primary_key(role_id, name)]
+#[belongs_to(Role)]
+pub struct RoleRight {
+    pub role_id: i32,
+    pub name: Right,
 }
 
 impl PartialEq for Role {
@@ -29,18 +95,9 @@ impl PartialEq for Role {
 macro_rules! permission_checker_for_article {
     ($x:ident) => {
         pub fn $x(&self, conn: &PgConnection, article: &Article) -> Result<bool> {
-            let article_permission = ArticlePermission::belonging_to(self)
-                .filter(article_permissions::article_id.eq(article.id))
-                .filter(article_permissions::$x.is_not_null())
-                .first::<ArticlePermission>(conn)
-                .optional()?;
-            if let Some(article_permission) = article_permission {
-                return Ok(article_permission.$x.unwrap());
-            }
-            let namespace_permission = NamespacePermission::belonging_to(self)
-                .filter(namespace_permissions::namespace_id.eq(article.namespace_id))
-                .first::<NamespacePermission>(conn)?;
-            Ok(namespace_permission.$x)
+            let permissions =
+                Permissions::of_role(conn, self, article.namespace_id, Some(article.id))?;
+            Ok(permissions.$x)
         }
     };
 }
@@ -49,10 +106,8 @@ macro_rules! permission_checker_for_article {
 macro_rules! permission_checker_for_namespace {
     ($x:ident) => {
         pub fn $x(&self, conn: &PgConnection, namespace: &Namespace) -> Result<bool> {
-            let namespace_permission = NamespacePermission::belonging_to(self)
-                .filter(namespace_permissions::namespace_id.eq(namespace.id))
-                .first::<NamespacePermission>(conn)?;
-            Ok(namespace_permission.$x)
+            let permissions = Permissions::of_role(conn, self, namespace.id, None)?;
+            Ok(permissions.$x)
         }
     };
 }
@@ -79,6 +134,24 @@ impl Role {
         }
     }
 
+    pub fn find_by_id(conn: &PgConnection, id: i32) -> Result<Option<Self>> {
+        let role = roles::table.find(id).first::<Self>(conn).optional()?;
+        Ok(role)
+    }
+
+    pub fn find_by_name(conn: &PgConnection, name: &str) -> Result<Option<Self>> {
+        let role = roles::table
+            .filter(roles::name.eq(name))
+            .first::<Self>(conn)
+            .optional()?;
+        Ok(role)
+    }
+
+    pub fn list(conn: &PgConnection) -> Result<Vec<Self>> {
+        let roles = roles::table.order(roles::id.asc()).load::<Self>(conn)?;
+        Ok(roles)
+    }
+
     pub fn create(conn: &PgConnection, name: &str) -> Result<Self> {
         let role = diesel::insert_into(roles::table)
             .values(roles::name.eq(name))
@@ -86,10 +159,76 @@ impl Role {
         Ok(role)
     }
 
+    /// Whether this is `Root`, `Anonymous` or `LoggedIn`, which the code depends on.
+    pub fn is_builtin(&self) -> bool {
+        [Self::root(), Self::anonymous(), Self::logged_in()].contains(self)
+    }
+
+    /// Deletes the role with its permissions, rights and memberships.
+    pub fn delete(&self, conn: &PgConnection) -> Result<()> {
+        if self.is_builtin() {
+            return Err(anyhow!("Role {} cannot be deleted", self.name));
+        }
+        conn.transaction(|| {
+            diesel::delete(NamespacePermission::belonging_to(self)).execute(conn)?;
+            diesel::delete(ArticlePermission::belonging_to(self)).execute(conn)?;
+            diesel::delete(RoleRight::belonging_to(self)).execute(conn)?;
+            diesel::delete(UserRole::belonging_to(self)).execute(conn)?;
+            // Titles protected for this role stay protected for Root only
+            diesel::update(protections::table.filter(protections::allowed_role_id.eq(self.id)))
+                .set(protections::allowed_role_id.eq(Role::root().id))
+                .execute(conn)?;
+            diesel::delete(roles::table.find(self.id)).execute(conn)?;
+            Ok(())
+        })
+    }
+
     pub fn add_user(&self, conn: &PgConnection, user: &User) -> Result<()> {
         user.add_role(conn, self)
     }
 
+    pub fn get_rights(&self, conn: &PgConnection) -> Result<Vec<Right>> {
+        let rights = RoleRight::belonging_to(self)
+            .select(role_rights::name)
+            .load::<Right>(conn)?;
+        Ok(rights)
+    }
+
+    pub fn set_rights(&self, conn: &PgConnection, rights: &[Right]) -> Result<()> {
+        conn.transaction(|| {
+            diesel::delete(RoleRight::belonging_to(self)).execute(conn)?;
+            let rows = rights
+                .iter()
+                .map(|right| RoleRight {
+                    role_id: self.id,
+                    name: *right,
+                })
+                .collect::<Vec<RoleRight>>();
+            diesel::insert_into(role_rights::table)
+                .values(&rows)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    /// Lists users having this role, with when it expires. Expired memberships are left out.
+    pub fn get_members(&self, conn: &PgConnection) -> Result<Vec<(User, Option<NaiveDateTime>)>> {
+        let now = Utc::now().naive_utc();
+        let members = user_roles::table
+            .inner_join(users::table)
+            .filter(user_roles::role_id.eq(self.id))
+            .filter(
+                user_roles::expires_at
+                    .is_null()
+                    .or(user_roles::expires_at.gt(now)),
+            )
+            .select((users::all_columns, user_roles::expires_at))
+            .order(users::username.asc())
+            .load::<(User, Option<NaiveDateTime>)>(conn)?;
+        Ok(members)
+    }
+
     permission_checker_for_article!(can_read);
     permission_checker_for_article!(can_edit);
     permission_checker_for_article!(can_rename);
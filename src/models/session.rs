@@ -0,0 +1,201 @@
+use crate::auth::{self, TokenClaims};
+use crate::models::User;
+use crate::schema::sessions;
+use anyhow::Result;
+use chrono::prelude::*;
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+use ipnetwork::IpNetwork;
+
+/// Days a session lasts without being refreshed.
+const LIFETIME_DAYS: i64 = 30;
+
+/// A sign in of a user, kept alive by refreshing its refresh token.
+#[derive(Queryable, Identifiable, Associations, Debug)]
+#[belongs_to(User)]
+pub struct Session {
+    pub id: i32,
+    pub user_id: i32,
+    /// The `jti` of the refresh token which may be used next.
+    pub token_id: String,
+    pub user_agent: Option<String>,
+    pub ip_address: IpNetwork,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "sessions"]
+struct NewSession<'a> {
+    pub user_id: i32,
+    pub token_id: &'a str,
+    pub user_agent: Option<&'a str>,
+    pub ip_address: &'a IpNetwork,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+/// The outcome of `Session::rotate`.
+#[derive(Debug)]
+pub enum Rotation {
+    /// The session with the refresh token replacing the presented one.
+    Rotated(Session, String),
+    /// The token was already rotated, so the session was revoked.
+    Reused,
+    /// The session is unknown, expired or revoked.
+    Invalid,
+}
+
+impl Session {
+    /// Starts a session for `user`. Returns it with its first refresh token.
+    pub fn create(
+        conn: &PgConnection,
+        user: &User,
+        user_agent: Option<&str>,
+        ip_address: &IpNetwork,
+    ) -> Result<(Self, String)> {
+        let now = Utc::now().naive_utc();
+        let session = diesel::insert_into(sessions::table)
+            .values(NewSession {
+                user_id: user.id,
+                token_id: &auth::generate_token(),
+                user_agent,
+                ip_address,
+                created_at: now,
+                last_used_at: now,
+                expires_at: now + Duration::days(LIFETIME_DAYS),
+            })
+            .get_result::<Self>(conn)?;
+        let refresh_token = session.refresh_token()?;
+        Ok((session, refresh_token))
+    }
+
+    fn refresh_token(&self) -> Result<String> {
+        let claims = TokenClaims::new(
+            self.user_id,
+            DateTime::from_utc(self.last_used_at, Utc),
+            DateTime::from_utc(self.expires_at, Utc),
+            "refresh",
+        )
+        .with_session(self.id, Some(self.token_id.clone()));
+        auth::encode(&claims)
+    }
+
+    /// Replaces the refresh token `token_id` of the session `id` and extends the session.
+    ///
+    /// Presenting a token which was already replaced revokes the session, as either the token
+    /// was stolen or the thief already used it.
+    pub fn rotate(conn: &PgConnection, id: i32, token_id: &str) -> Result<Rotation> {
+        let now = Utc::now().naive_utc();
+        conn.transaction(|| {
+            let session = sessions::table
+                .find(id)
+                .filter(sessions::revoked_at.is_null())
+                .filter(sessions::expires_at.gt(now))
+                .for_update()
+                .first::<Self>(conn)
+                .optional()?;
+            let session = match session {
+                Some(session) => session,
+                None => return Ok(Rotation::Invalid),
+            };
+            if session.token_id != token_id {
+                Self::revoke(conn, session.user_id, session.id)?;
+                return Ok(Rotation::Reused);
+            }
+            let session = diesel::update(sessions::table.find(session.id))
+                .set((
+                    sessions::token_id.eq(auth::generate_token()),
+                    sessions::last_used_at.eq(now),
+                    sessions::expires_at.eq(now + Duration::days(LIFETIME_DAYS)),
+                ))
+                .get_result::<Self>(conn)?;
+            let refresh_token = session.refresh_token()?;
+            Ok(Rotation::Rotated(session, refresh_token))
+        })
+    }
+
+    /// Whether the session `id` of the user `user_id` is neither expired nor revoked, so that its
+    /// access tokens are still honored.
+    pub fn is_active(conn: &PgConnection, user_id: i32, id: i32) -> Result<bool> {
+        let count = sessions::table
+            .find(id)
+            .filter(sessions::user_id.eq(user_id))
+            .filter(sessions::revoked_at.is_null())
+            .filter(sessions::expires_at.gt(Utc::now().naive_utc()))
+            .count()
+            .get_result::<i64>(conn)?;
+        Ok(count > 0)
+    }
+
+    /// Ends the session `id` of the user `user_id`. Returns whether it was active.
+    pub fn revoke(conn: &PgConnection, user_id: i32, id: i32) -> Result<bool> {
+        let count = diesel::update(
+            sessions::table
+                .find(id)
+                .filter(sessions::user_id.eq(user_id))
+                .filter(sessions::revoked_at.is_null()),
+        )
+        .set(sessions::revoked_at.eq(Utc::now().naive_utc()))
+        .execute(conn)?;
+        Ok(count > 0)
+    }
+
+    /// Ends every session of `user`.
+    pub fn revoke_all(conn: &PgConnection, user: &User) -> Result<usize> {
+        let count = diesel::update(Self::belonging_to(user).filter(sessions::revoked_at.is_null()))
+            .set(sessions::revoked_at.eq(Utc::now().naive_utc()))
+            .execute(conn)?;
+        Ok(count)
+    }
+
+    /// The unexpired and unrevoked sessions of `user`, recently used first.
+    pub fn list_active(conn: &PgConnection, user: &User) -> Result<Vec<Self>> {
+        let sessions = Self::belonging_to(user)
+            .filter(sessions::revoked_at.is_null())
+            .filter(sessions::expires_at.gt(Utc::now().naive_utc()))
+            .order_by(sessions::last_used_at.desc())
+            .load::<Self>(conn)?;
+        Ok(sessions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_rotate_and_reuse() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let user = User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let (session, _) =
+                Session::create(&conn, &user, Some("test"), &ip_address).expect("must succeed");
+            let rotated = match Session::rotate(&conn, session.id, &session.token_id)
+                .expect("must succeed")
+            {
+                Rotation::Rotated(rotated, _) => rotated,
+                rotation => panic!("unexpected {:?}", rotation),
+            };
+            assert_ne!(rotated.token_id, session.token_id);
+            // The first token was replaced, so it must have been stolen
+            let rotation =
+                Session::rotate(&conn, session.id, &session.token_id).expect("must succeed");
+            assert!(matches!(rotation, Rotation::Reused));
+            let rotation =
+                Session::rotate(&conn, session.id, &rotated.token_id).expect("must succeed");
+            assert!(matches!(rotation, Rotation::Invalid));
+            assert!(Session::list_active(&conn, &user)
+                .expect("must succeed")
+                .is_empty());
+            assert!(!Session::is_active(&conn, user.id, session.id).expect("must succeed"));
+            Ok(())
+        });
+    }
+}
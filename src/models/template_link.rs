@@ -0,0 +1,88 @@
+use crate::models::{Article, Namespace};
+use crate::schema::template_links;
+use anyhow::Result;
+use diesel::prelude::*;
+use serde::Serialize;
+
+/// A template transcluded by an `Article`. The template need not exist.
+#[derive(Serialize, Queryable, Insertable, Associations, Debug, PartialEq, Eq)]
+#[table_name = "template_links"]
+#[belongs_to(Article)]
+pub struct TemplateLink {
+    pub article_id: i32,
+    pub namespace_id: i32,
+    pub title: String,
+}
+
+impl TemplateLink {
+    /// Replaces the template links of `article` with the templates in `wikitext`.
+    pub fn update(conn: &PgConnection, article: &Article, wikitext: &str) -> Result<()> {
+        let parsed = crate::parser::parse(wikitext);
+        let mut links: Vec<TemplateLink> = vec![];
+        for name in crate::parser::find_templates(&parsed.nodes) {
+            // Names which are not valid titles cannot be transcluded
+            let (namespace, title) = match Namespace::parse_full_title(conn, &name) {
+                Ok(parsed) => parsed,
+                Err(_) => continue,
+            };
+            let link = TemplateLink {
+                article_id: article.id,
+                namespace_id: namespace.id,
+                title: title.into_string(),
+            };
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+        conn.transaction(|| {
+            diesel::delete(Self::belonging_to(article)).execute(conn)?;
+            diesel::insert_into(template_links::table)
+                .values(&links)
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    /// Points the links to the title `article` was renamed from at its new title, so cascading
+    /// protections keep covering it.
+    pub fn follow_rename(
+        conn: &PgConnection,
+        old_namespace_id: i32,
+        old_title: &str,
+        article: &Article,
+    ) -> Result<()> {
+        // Articles linking both titles keep a single link
+        let linking_new_title = template_links::table
+            .filter(template_links::namespace_id.eq(article.namespace_id))
+            .filter(template_links::title.eq(&article.title))
+            .select(template_links::article_id);
+        diesel::delete(
+            template_links::table
+                .filter(template_links::namespace_id.eq(old_namespace_id))
+                .filter(template_links::title.eq(old_title))
+                .filter(template_links::article_id.eq_any(linking_new_title)),
+        )
+        .execute(conn)?;
+        diesel::update(
+            template_links::table
+                .filter(template_links::namespace_id.eq(old_namespace_id))
+                .filter(template_links::title.eq(old_title)),
+        )
+        .set((
+            template_links::namespace_id.eq(article.namespace_id),
+            template_links::title.eq(&article.title),
+        ))
+        .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn find_by_article(conn: &PgConnection, article: &Article) -> Result<Vec<Self>> {
+        let links = Self::belonging_to(article)
+            .order((
+                template_links::namespace_id.asc(),
+                template_links::title.asc(),
+            ))
+            .load::<Self>(conn)?;
+        Ok(links)
+    }
+}
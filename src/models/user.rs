@@ -1,11 +1,15 @@
 use crate::auth;
-use crate::models::{Article, Namespace, Role, UserRole};
+use crate::models::{Article, Namespace, Permissions, Right, Role, UserRole};
 use crate::schema::{authentications, users};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use chrono::prelude::*;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use serde::Serialize;
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+sql_function!(fn lower(x: Text) -> Text);
 
 #[derive(Serialize, Queryable, Identifiable, Debug)]
 pub struct User {
@@ -14,6 +18,37 @@ pub struct User {
     pub email: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub email_verified_at: Option<NaiveDateTime>,
+    pub display_name: Option<String>,
+    pub bio: String,
+    pub language: Option<String>,
+    pub timezone: Option<String>,
+    pub preferences: Value,
+}
+
+/// How a user likes articles to be shown, applied when rendering articles for them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UserPreferences {
+    pub show_toc: bool,
+}
+
+impl Default for UserPreferences {
+    fn default() -> Self {
+        Self { show_toc: true }
+    }
+}
+
+/// Changes to the profile of a `User`. `None` keeps the current value.
+#[derive(AsChangeset, Default, Debug)]
+#[table_name = "users"]
+pub struct ProfileChanges {
+    pub username: Option<String>,
+    pub display_name: Option<Option<String>>,
+    pub bio: Option<String>,
+    pub language: Option<Option<String>>,
+    pub timezone: Option<Option<String>>,
+    pub preferences: Option<Value>,
 }
 
 #[derive(Insertable)]
@@ -33,6 +68,9 @@ pub struct Authentication {
     pub provider: String,
     pub provider_user_id: String,
     pub created_at: NaiveDateTime,
+    /// Argon2 hash of the password of a `LOCAL_PROVIDER` authentication.
+    #[serde(skip)]
+    pub password_hash: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -42,8 +80,12 @@ pub struct NewAuthentication<'a> {
     pub provider: &'a str,
     pub provider_user_id: &'a str,
     pub created_at: NaiveDateTime,
+    pub password_hash: Option<&'a str>,
 }
 
+/// Provider of authentications by username or email and password.
+pub const LOCAL_PROVIDER: &str = "local";
+
 pub enum UserFindResult {
     Exists(User),
     WrongProvider(User), // Same email, but different provider
@@ -54,12 +96,8 @@ pub enum UserFindResult {
 macro_rules! permission_checker_for_article {
     ($x:ident) => {
         pub fn $x(&self, conn: &PgConnection, article: &Article) -> Result<bool> {
-            for role in self.get_roles(conn)?.iter() {
-                if role.$x(conn, article)? {
-                    return Ok(true);
-                }
-            }
-            Ok(false)
+            let permissions = self.get_permissions(conn, article.namespace_id, Some(article.id))?;
+            Ok(permissions.$x)
         }
     };
 }
@@ -68,12 +106,8 @@ macro_rules! permission_checker_for_article {
 macro_rules! permission_checker_for_namespace {
     ($x:ident) => {
         pub fn $x(&self, conn: &PgConnection, namespace: &Namespace) -> Result<bool> {
-            for role in self.get_roles(conn)?.iter() {
-                if role.$x(conn, namespace)? {
-                    return Ok(true);
-                }
-            }
-            Ok(false)
+            let permissions = self.get_permissions(conn, namespace.id, None)?;
+            Ok(permissions.$x)
         }
     };
 }
@@ -82,6 +116,13 @@ impl User {
     pub fn find_by_id(conn: &PgConnection, id: i32) -> Result<User> {
         Ok(users::table.find(id).first(conn)?)
     }
+    pub fn find_by_username(conn: &PgConnection, username: &str) -> Result<Option<User>> {
+        let user = users::table
+            .filter(users::username.eq(username))
+            .first(conn)
+            .optional()?;
+        Ok(user)
+    }
     pub fn find_by_provider(
         conn: &PgConnection,
         email: &str,
@@ -121,75 +162,281 @@ impl User {
         Ok(user)
     }
 
+    /// Links an account of `provider`. Returns `None` if the account is linked to a user
+    /// already, or the user has another account of `provider`.
     pub fn add_authentication(
         &self,
         conn: &PgConnection,
         provider: &str,
         provider_user_id: &str,
-    ) -> Result<Authentication> {
+    ) -> Result<Option<Authentication>> {
         let now = Utc::now().naive_utc();
         let new_auth = NewAuthentication {
             user_id: self.id,
             provider,
             provider_user_id,
             created_at: now,
+            password_hash: None,
         };
         let auth = diesel::insert_into(authentications::table)
             .values(new_auth)
-            .get_result::<Authentication>(conn)?;
+            .on_conflict_do_nothing()
+            .get_result::<Authentication>(conn)
+            .optional()?;
         Ok(auth)
     }
 
-    pub fn issue_refresh_token(&self) -> String {
-        use chrono::Duration;
-        use jsonwebtoken::{encode, EncodingKey, Header};
-        use std::env;
-        let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-        let claims = auth::TokenClaims::new(
-            self.id,
-            Utc::now(),
-            Utc::now() + Duration::days(30),
-            "refresh",
-        );
+    /// A free username for a new user of an external provider, based on their `name` or, if
+    /// they have none, on their email.
+    ///
+    /// Providers allow names which are not valid usernames, so `@`, `/` and control characters
+    /// are replaced and surrounding whitespace is trimmed.
+    pub fn available_username(conn: &PgConnection, name: &str, email: &str) -> Result<String> {
+        let base = match name.trim() {
+            "" => email.split('@').next().unwrap_or(""),
+            name => name,
+        };
+        // Leaves room for a number within the 50 characters of a username
+        let base = base
+            .chars()
+            .map(|c| match c {
+                '@' | '/' => '_',
+                c if c.is_control() => ' ',
+                c => c,
+            })
+            .take(40)
+            .collect::<String>();
+        let base = base.trim().to_owned();
+        let mut username = base.clone();
+        let mut n = 1;
+        while username.is_empty() || Self::find_by_username(conn, &username)?.is_some() {
+            n += 1;
+            username = format!("{}{}", base, n);
+        }
+        Ok(username)
+    }
+
+    /// Creates a user who logs in with a password.
+    pub fn register(
+        conn: &PgConnection,
+        email: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Self> {
+        let password_hash = auth::hash_password(password)?;
+        conn.transaction(|| {
+            let user = Self::create(conn, email, username)?;
+            let new_auth = NewAuthentication {
+                user_id: user.id,
+                provider: LOCAL_PROVIDER,
+                provider_user_id: &user.id.to_string(),
+                created_at: Utc::now().naive_utc(),
+                password_hash: Some(&password_hash),
+            };
+            diesel::insert_into(authentications::table)
+                .values(new_auth)
+                .execute(conn)?;
+            user.add_role(conn, &Role::logged_in())?;
+            Ok(user)
+        })
+    }
+
+    /// Finds a user by username, or by email if `name` contains `@`.
+    pub fn find_by_username_or_email(conn: &PgConnection, name: &str) -> Result<Option<User>> {
+        let query = users::table.into_boxed();
+        let query = if name.contains('@') {
+            query.filter(lower(users::email).eq(lower(name)))
+        } else {
+            query.filter(users::username.eq(name))
+        };
+        Ok(query.first(conn).optional()?)
+    }
+
+    /// Whether `password` is the password of the user. Users without one never match.
+    pub fn verify_password(&self, conn: &PgConnection, password: &str) -> Result<bool> {
+        let password_hash = Authentication::belonging_to(self)
+            .filter(authentications::provider.eq(LOCAL_PROVIDER))
+            .select(authentications::password_hash)
+            .first::<Option<String>>(conn)
+            .optional()?
+            .flatten();
+        match password_hash {
+            Some(password_hash) => auth::verify_password(&password_hash, password),
+            None => auth::verify_no_password(password),
+        }
+    }
+
+    /// Sets the password, letting users of other providers log in with it too.
+    pub fn set_password(&self, conn: &PgConnection, password: &str) -> Result<()> {
+        let password_hash = auth::hash_password(password)?;
+        let new_auth = NewAuthentication {
+            user_id: self.id,
+            provider: LOCAL_PROVIDER,
+            provider_user_id: &self.id.to_string(),
+            created_at: Utc::now().naive_utc(),
+            password_hash: Some(&password_hash),
+        };
+        diesel::insert_into(authentications::table)
+            .values(new_auth)
+            .on_conflict((authentications::user_id, authentications::provider))
+            .do_update()
+            .set(authentications::password_hash.eq(&password_hash))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn get_authentications(&self, conn: &PgConnection) -> Result<Vec<Authentication>> {
+        let auths = Authentication::belonging_to(self)
+            .order(authentications::created_at.asc())
+            .load::<Authentication>(conn)?;
+        Ok(auths)
+    }
 
-        encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(secret.as_ref()),
-        )
-        .expect("JWT encoding failed")
+    /// Unlinks `provider` from the user. Returns whether it was linked.
+    ///
+    /// The last provider cannot be unlinked, as the user could not log in anymore.
+    pub fn remove_authentication(&self, conn: &PgConnection, provider: &str) -> Result<bool> {
+        conn.transaction(|| {
+            let auths = self.get_authentications(conn)?;
+            if !auths.iter().any(|auth| auth.provider == provider) {
+                return Ok(false);
+            }
+            if auths.len() == 1 {
+                return Err(anyhow!("The last provider of a user cannot be removed"));
+            }
+            diesel::delete(
+                Authentication::belonging_to(self).filter(authentications::provider.eq(provider)),
+            )
+            .execute(conn)?;
+            Ok(true)
+        })
+    }
+
+    /// Applies `changes`. A new username shows up in the history right away, as revisions are
+    /// attributed to the user through its `Actor`.
+    pub fn update_profile(&mut self, conn: &PgConnection, changes: &ProfileChanges) -> Result<()> {
+        *self = diesel::update(users::table.find(self.id))
+            .set((changes, users::updated_at.eq(Utc::now().naive_utc())))
+            .get_result::<Self>(conn)?;
+        Ok(())
+    }
+
+    /// The preferences, with defaults for the ones never set.
+    pub fn get_preferences(&self) -> UserPreferences {
+        serde_json::from_value(self.preferences.clone()).unwrap_or_default()
+    }
+
+    pub fn is_email_verified(&self) -> bool {
+        self.email_verified_at.is_some()
+    }
+
+    pub fn verify_email(&mut self, conn: &PgConnection) -> Result<()> {
+        let now = Utc::now().naive_utc();
+        diesel::update(users::table.find(self.id))
+            .set(users::email_verified_at.eq(now))
+            .execute(conn)?;
+        self.email_verified_at = Some(now);
+        Ok(())
     }
 
     pub fn add_role(&self, conn: &PgConnection, role: &Role) -> Result<()> {
+        self.assign_role(conn, role, None)
+    }
+
+    /// Gives `role` to the user until `expires_at`, or forever if `None`.
+    ///
+    /// Assigning a role the user already has replaces its expiry.
+    pub fn assign_role(
+        &self,
+        conn: &PgConnection,
+        role: &Role,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<()> {
         use crate::schema::user_roles;
         let user_role = UserRole {
             user_id: self.id,
             role_id: role.id,
+            expires_at,
         };
-        user_role.insert_into(user_roles::table).execute(conn)?;
+        diesel::insert_into(user_roles::table)
+            .values(&user_role)
+            .on_conflict((user_roles::user_id, user_roles::role_id))
+            .do_update()
+            .set(user_roles::expires_at.eq(expires_at))
+            .execute(conn)?;
         Ok(())
     }
 
+    /// Takes `role` away from the user. Returns whether the user had it.
+    pub fn remove_role(&self, conn: &PgConnection, role: &Role) -> Result<bool> {
+        use crate::schema::user_roles;
+        let count = diesel::delete(user_roles::table.find((self.id, role.id))).execute(conn)?;
+        Ok(count > 0)
+    }
+
+    /// Roles of the user, leaving out expired ones.
     pub fn get_roles(&self, conn: &PgConnection) -> Result<Vec<Role>> {
         use crate::schema::{roles, user_roles};
+        let now = Utc::now().naive_utc();
         let res = roles::table
             .inner_join(user_roles::table.inner_join(users::table))
             .select(roles::all_columns)
             .filter(users::id.eq(self.id))
+            .filter(
+                user_roles::expires_at
+                    .is_null()
+                    .or(user_roles::expires_at.gt(now)),
+            )
             .load::<Role>(conn)?;
         Ok(res)
     }
 
     pub fn has_any_role(&self, conn: &PgConnection, roles: &[Role]) -> Result<bool> {
         use crate::schema::user_roles;
+        let now = Utc::now().naive_utc();
         let user_role = user_roles::table
             .filter(user_roles::user_id.eq(self.id))
             .filter(user_roles::role_id.eq_any(roles.iter().map(|r| r.id).collect::<Vec<i32>>()))
+            .filter(
+                user_roles::expires_at
+                    .is_null()
+                    .or(user_roles::expires_at.gt(now)),
+            )
             .first::<UserRole>(conn)
             .optional()?;
         Ok(user_role.is_some())
     }
 
+    /// Whether any role of the user has `right`. `Root` has every right.
+    pub fn has_right(&self, conn: &PgConnection, right: Right) -> Result<bool> {
+        use crate::schema::role_rights;
+        if self.has_any_role(conn, &[Role::root()])? {
+            return Ok(true);
+        }
+        let role_ids = self
+            .get_roles(conn)?
+            .iter()
+            .map(|role| role.id)
+            .collect::<Vec<i32>>();
+        let role_right = role_rights::table
+            .filter(role_rights::role_id.eq_any(role_ids))
+            .filter(role_rights::name.eq(right))
+            .select(role_rights::role_id)
+            .first::<i32>(conn)
+            .optional()?;
+        Ok(role_right.is_some())
+    }
+
+    /// Resolves the permissions of the user on a namespace, or on an article in it.
+    pub fn get_permissions(
+        &self,
+        conn: &PgConnection,
+        namespace_id: i32,
+        article_id: Option<i32>,
+    ) -> Result<Permissions> {
+        Permissions::of_user(conn, Some(self), namespace_id, article_id)
+    }
+
     permission_checker_for_article!(can_read);
     permission_checker_for_article!(can_edit);
     permission_checker_for_article!(can_rename);
@@ -199,6 +446,19 @@ impl User {
 }
 
 impl Authentication {
+    pub fn find(
+        conn: &PgConnection,
+        provider: &str,
+        provider_user_id: &str,
+    ) -> Result<Option<Self>> {
+        let auth = authentications::table
+            .filter(authentications::provider.eq(provider))
+            .filter(authentications::provider_user_id.eq(provider_user_id))
+            .first::<Self>(conn)
+            .optional()?;
+        Ok(auth)
+    }
+
     pub fn get_user(&self, conn: &PgConnection) -> Result<User> {
         let user = users::table.find(self.user_id).get_result::<User>(conn)?;
         Ok(user)
@@ -209,6 +469,7 @@ impl Authentication {
 mod tests {
     use super::*;
     use crate::db::create_connection;
+    use crate::models::Actor;
 
     #[test]
     fn test_has_any_role() {
@@ -222,6 +483,102 @@ mod tests {
             user.add_role(&conn, &roles[0]).expect("must succeed");
             let has = user.has_any_role(&conn, &roles).expect("must succeed");
             assert_eq!(has, true);
+            let expired = Utc::now().naive_utc() - chrono::Duration::days(1);
+            user.assign_role(&conn, &roles[0], Some(expired))
+                .expect("must succeed");
+            let has = user.has_any_role(&conn, &roles).expect("must succeed");
+            assert_eq!(has, false);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_has_right() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let user = User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            let role = Role::create(&conn, "Test Role").expect("must succeed");
+            user.add_role(&conn, &role).expect("must succeed");
+            let has = user
+                .has_right(&conn, Right::ManageRoles)
+                .expect("must succeed");
+            assert_eq!(has, false);
+            role.set_rights(&conn, &[Right::ManageRoles])
+                .expect("must succeed");
+            let has = user
+                .has_right(&conn, Right::ManageRoles)
+                .expect("must succeed");
+            assert_eq!(has, true);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_password() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let user = User::register(&conn, "test@testtest.com", "tester22", "password1")
+                .expect("must succeed");
+            let found =
+                User::find_by_username_or_email(&conn, "test@testtest.com").expect("must succeed");
+            assert_eq!(found.map(|found| found.id), Some(user.id));
+            assert!(user
+                .verify_password(&conn, "password1")
+                .expect("must succeed"));
+            user.set_password(&conn, "password2").expect("must succeed");
+            assert!(!user
+                .verify_password(&conn, "password1")
+                .expect("must succeed"));
+            assert!(user
+                .verify_password(&conn, "password2")
+                .expect("must succeed"));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_remove_authentication() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let user = User::register(&conn, "test@testtest.com", "tester22", "password1")
+                .expect("must succeed");
+            user.add_authentication(&conn, "github", "42")
+                .expect("must succeed");
+            let other =
+                User::create(&conn, "other@testtest.com", "tester23").expect("must succeed");
+            let linked = other
+                .add_authentication(&conn, "github", "42")
+                .expect("must succeed");
+            assert!(linked.is_none());
+            let removed = user
+                .remove_authentication(&conn, "google")
+                .expect("must succeed");
+            assert_eq!(removed, false);
+            let removed = user
+                .remove_authentication(&conn, LOCAL_PROVIDER)
+                .expect("must succeed");
+            assert_eq!(removed, true);
+            assert!(user.remove_authentication(&conn, "github").is_err());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_available_username() {
+        use crate::routes::users::validate_username;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            let username = User::available_username(&conn, " tester22 ", "").expect("must succeed");
+            assert_eq!(username, "tester222");
+            let username = User::available_username(&conn, "a@b/c\n", "").expect("must succeed");
+            assert_eq!(username, "a_b_c");
+            let username =
+                User::available_username(&conn, "", "name@example.com").expect("must succeed");
+            assert_eq!(username, "name");
+            for username in &["tester222", "a_b_c", "name"] {
+                assert!(validate_username(username).is_ok());
+            }
             Ok(())
         });
     }
@@ -234,10 +591,32 @@ mod tests {
             let role = Role::logged_in();
             user.add_role(&conn, &role).expect("must succeed");
             let can_create = user
-                .can_create(&conn, &Namespace::default())
+                .can_create(&conn, &Namespace::get_default(&conn).expect("must succeed"))
                 .expect("must succeed");
             assert_eq!(can_create, true);
             Ok(())
         });
     }
+
+    #[test]
+    fn test_update_profile_keeps_attribution() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let mut user =
+                User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            let actor = Actor::find_or_create_from_user_id(&conn, user.id).expect("must succeed");
+            let changes = ProfileChanges {
+                username: Some("tester23".to_owned()),
+                display_name: Some(Some("Tester".to_owned())),
+                ..Default::default()
+            };
+            user.update_profile(&conn, &changes).expect("must succeed");
+            assert_eq!(user.username, "tester23");
+            assert_eq!(user.display_name, Some("Tester".to_owned()));
+            assert_eq!(user.get_preferences(), UserPreferences::default());
+            let attributed = actor.get_user(&conn).expect("must succeed");
+            assert_eq!(attributed.username, "tester23");
+            Ok(())
+        });
+    }
 }
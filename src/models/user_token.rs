@@ -0,0 +1,160 @@
+use crate::auth::hash_token;
+use crate::models::User;
+use crate::schema::{user_tokens, users};
+use anyhow::Result;
+use chrono::prelude::*;
+use chrono::{Duration, NaiveDateTime};
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// What a `UserToken` may be used for.
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[sql_type = "Text"]
+pub enum TokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::VerifyEmail => "verify_email",
+            TokenPurpose::ResetPassword => "reset_password",
+        }
+    }
+
+    /// How long a token stays usable.
+    pub fn lifetime(&self) -> Duration {
+        match self {
+            TokenPurpose::VerifyEmail => Duration::days(7),
+            TokenPurpose::ResetPassword => Duration::hours(1),
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for TokenPurpose {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for TokenPurpose {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"verify_email" => Ok(TokenPurpose::VerifyEmail),
+            b"reset_password" => Ok(TokenPurpose::ResetPassword),
+            _ => Err("Unrecognized token purpose".into()),
+        }
+    }
+}
+
+/// A single-use token sent to a user by email.
+#[derive(Queryable, Identifiable, Associations, Debug)]
+#[belongs_to(User)]
+pub struct UserToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub purpose: TokenPurpose,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "user_tokens"]
+struct NewUserToken<'a> {
+    pub user_id: i32,
+    pub purpose: TokenPurpose,
+    pub token_hash: &'a str,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+impl UserToken {
+    /// Issues a token for `purpose`, revoking unused ones issued before. Returns the token, of
+    /// which only the hash is kept.
+    pub fn issue(conn: &PgConnection, user: &User, purpose: TokenPurpose) -> Result<String> {
+        let token = crate::auth::generate_token();
+        let now = Utc::now().naive_utc();
+        conn.transaction(|| {
+            diesel::delete(
+                Self::belonging_to(user)
+                    .filter(user_tokens::purpose.eq(purpose))
+                    .filter(user_tokens::used_at.is_null()),
+            )
+            .execute(conn)?;
+            diesel::insert_into(user_tokens::table)
+                .values(NewUserToken {
+                    user_id: user.id,
+                    purpose,
+                    token_hash: &hash_token(&token),
+                    expires_at: now + purpose.lifetime(),
+                    created_at: now,
+                })
+                .execute(conn)?;
+            Ok(token)
+        })
+    }
+
+    /// Uses up `token` and returns its user, or `None` if it is unknown, expired or already used.
+    pub fn consume(
+        conn: &PgConnection,
+        token: &str,
+        purpose: TokenPurpose,
+    ) -> Result<Option<User>> {
+        let now = Utc::now().naive_utc();
+        let user_id = diesel::update(
+            user_tokens::table
+                .filter(user_tokens::token_hash.eq(hash_token(token)))
+                .filter(user_tokens::purpose.eq(purpose))
+                .filter(user_tokens::used_at.is_null())
+                .filter(user_tokens::expires_at.gt(now)),
+        )
+        .set(user_tokens::used_at.eq(now))
+        .returning(user_tokens::user_id)
+        .get_result::<i32>(conn)
+        .optional()?;
+        match user_id {
+            Some(user_id) => Ok(Some(users::table.find(user_id).first::<User>(conn)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+
+    #[test]
+    fn test_consume_once() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let user = User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            let revoked =
+                UserToken::issue(&conn, &user, TokenPurpose::ResetPassword).expect("must succeed");
+            let token =
+                UserToken::issue(&conn, &user, TokenPurpose::ResetPassword).expect("must succeed");
+            let consumed = UserToken::consume(&conn, &revoked, TokenPurpose::ResetPassword)
+                .expect("must succeed");
+            assert!(consumed.is_none());
+            let consumed =
+                UserToken::consume(&conn, &token, TokenPurpose::VerifyEmail).expect("must succeed");
+            assert!(consumed.is_none());
+            let consumed = UserToken::consume(&conn, &token, TokenPurpose::ResetPassword)
+                .expect("must succeed");
+            assert_eq!(consumed.map(|user| user.id), Some(user.id));
+            let consumed = UserToken::consume(&conn, &token, TokenPurpose::ResetPassword)
+                .expect("must succeed");
+            assert!(consumed.is_none());
+            Ok(())
+        });
+    }
+}
@@ -1,9 +1,74 @@
-use parse_wiki_text::{Configuration, Output};
+use parse_wiki_text::{Configuration, Node, Output};
 
 pub fn parse(wikitext: &str) -> Output {
     Configuration::default().parse(wikitext)
 }
 
+/// The full title a template name refers to.
+pub fn template_name(name: &[Node]) -> String {
+    name.iter()
+        .map(|node| {
+            if let Node::Text { value, .. } = node {
+                value
+            } else {
+                ""
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("")
+}
+
+/// Collects the names of templates transcluded in `nodes`, including those in template
+/// parameters, in order of appearance.
+pub fn find_templates(nodes: &[Node]) -> Vec<String> {
+    let mut templates = vec![];
+    collect_templates(nodes, &mut templates);
+    templates
+}
+
+fn collect_templates(nodes: &[Node], templates: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            Node::Template {
+                name, parameters, ..
+            } => {
+                templates.push(template_name(name));
+                for parameter in parameters {
+                    collect_templates(&parameter.value, templates);
+                }
+            }
+            Node::Heading { nodes, .. }
+            | Node::Preformatted { nodes, .. }
+            | Node::ExternalLink { nodes, .. }
+            | Node::Tag { nodes, .. } => collect_templates(nodes, templates),
+            Node::Link { text, .. } | Node::Image { text, .. } => {
+                collect_templates(text, templates)
+            }
+            Node::OrderedList { items, .. } | Node::UnorderedList { items, .. } => {
+                for item in items {
+                    collect_templates(&item.nodes, templates);
+                }
+            }
+            Node::DefinitionList { items, .. } => {
+                for item in items {
+                    collect_templates(&item.nodes, templates);
+                }
+            }
+            Node::Table { captions, rows, .. } => {
+                for caption in captions {
+                    collect_templates(&caption.content, templates);
+                }
+                for row in rows {
+                    for cell in &row.cells {
+                        collect_templates(&cell.content, templates);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -18,4 +83,20 @@ mod tests {
         );
         parse(wikitext);
     }
+
+    #[test]
+    fn test_find_templates() {
+        use super::*;
+
+        let wikitext = concat!(
+            "{{Infobox|name={{Flag}}}}\n",
+            "==A {{Heading}}==\n",
+            "*[[Link|{{Icon}}]]\n",
+        );
+        let parsed = parse(wikitext);
+        assert_eq!(
+            find_templates(&parsed.nodes),
+            vec!["Infobox", "Flag", "Heading", "Icon"]
+        );
+    }
 }
@@ -0,0 +1,312 @@
+//! Limits on how often an actor may write, counted in fixed windows.
+//!
+//! Limits are configured per action with `RATE_LIMIT_<ACTION>` as comma separated
+//! `Role=max/seconds` pairs, e.g. `RATE_LIMIT_EDIT=Anonymous=10/60,LoggedIn=60/60`. Users get the
+//! most generous limit among their roles and `LoggedIn`; `Root` is never limited. Counters are kept
+//! in memory, or in Postgres with `RATE_LIMIT_STORE=postgres` so that they are shared by every
+//! instance.
+
+use crate::db::DbPool;
+use crate::models::RateLimitCounter;
+use anyhow::{anyhow, Result};
+use chrono::prelude::*;
+use chrono::{Duration, NaiveDateTime};
+use diesel::PgConnection;
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+/// At most this many counters are kept in memory. Ended windows are pruned first, then the
+/// counters ending soonest.
+const MAX_MEMORY_COUNTERS: usize = 10000;
+
+/// Ended windows are pruned from Postgres at most this often.
+const PRUNE_INTERVAL_SECONDS: i64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitAction {
+    /// Editing, renaming and deleting articles.
+    Edit,
+    /// Creating articles.
+    Create,
+    /// Logging in.
+    Login,
+}
+
+impl RateLimitAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateLimitAction::Edit => "edit",
+            RateLimitAction::Create => "create",
+            RateLimitAction::Login => "login",
+        }
+    }
+}
+
+/// At most `max` hits every `period`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    pub max: i64,
+    pub period: Duration,
+}
+
+impl Limit {
+    /// Parses `max/seconds`.
+    fn parse(value: &str) -> Result<Self> {
+        let mut parts = value.splitn(2, '/');
+        match (parts.next(), parts.next()) {
+            (Some(max), Some(seconds)) => {
+                let max = max.trim().parse::<i64>()?;
+                let seconds = seconds.trim().parse::<i64>()?;
+                if seconds <= 0 {
+                    return Err(anyhow!("Invalid rate limit {}", value));
+                }
+                Ok(Self {
+                    max,
+                    period: Duration::seconds(seconds),
+                })
+            }
+            _ => Err(anyhow!("Invalid rate limit {}", value)),
+        }
+    }
+
+    /// Whether it allows more hits over time than `other`.
+    fn is_more_generous(&self, other: &Limit) -> bool {
+        self.max * other.period.num_seconds() > other.max * self.period.num_seconds()
+    }
+}
+
+/// Where the hits of each window are counted.
+pub trait RateLimitStore: Send + Sync {
+    /// Counts a hit of `key` in the window starting at `window_start` and ending at `expires_at`.
+    /// Returns the hits in the window so far.
+    fn hit(&self, key: &str, window_start: NaiveDateTime, expires_at: NaiveDateTime)
+        -> Result<i64>;
+}
+
+/// Counters of a single instance.
+#[derive(Default)]
+pub struct MemoryStore {
+    /// Window start, window end and hits by key.
+    counters: Mutex<HashMap<String, (NaiveDateTime, NaiveDateTime, i64)>>,
+}
+
+impl RateLimitStore for MemoryStore {
+    fn hit(
+        &self,
+        key: &str,
+        window_start: NaiveDateTime,
+        expires_at: NaiveDateTime,
+    ) -> Result<i64> {
+        let mut counters = self
+            .counters
+            .lock()
+            .map_err(|_| anyhow!("Rate limit counters are poisoned"))?;
+        if counters.len() >= MAX_MEMORY_COUNTERS && !counters.contains_key(key) {
+            let now = Utc::now().naive_utc();
+            counters.retain(|_, (_, expires_at, _)| *expires_at > now);
+            if counters.len() >= MAX_MEMORY_COUNTERS {
+                let soonest = counters
+                    .iter()
+                    .min_by_key(|(_, (_, expires_at, _))| *expires_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(soonest) = soonest {
+                    counters.remove(&soonest);
+                }
+            }
+        }
+        let counter = counters
+            .entry(key.to_owned())
+            .or_insert((window_start, expires_at, 0));
+        if counter.0 != window_start {
+            *counter = (window_start, expires_at, 0);
+        }
+        counter.2 += 1;
+        Ok(counter.2)
+    }
+}
+
+/// Counters shared by every instance using the database.
+pub struct PostgresStore {
+    pool: DbPool,
+    /// When ended windows were last pruned, as a Unix timestamp.
+    pruned_at: AtomicI64,
+}
+
+impl PostgresStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self {
+            pool,
+            pruned_at: AtomicI64::new(0),
+        }
+    }
+
+    /// Prunes ended windows unless another hit did so recently.
+    fn prune(&self, conn: &PgConnection) -> Result<()> {
+        let now = Utc::now().timestamp();
+        let pruned_at = self.pruned_at.load(Ordering::Relaxed);
+        if now - pruned_at < PRUNE_INTERVAL_SECONDS
+            || self
+                .pruned_at
+                .compare_exchange(pruned_at, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            return Ok(());
+        }
+        RateLimitCounter::prune(conn)?;
+        Ok(())
+    }
+}
+
+impl RateLimitStore for PostgresStore {
+    fn hit(
+        &self,
+        key: &str,
+        window_start: NaiveDateTime,
+        expires_at: NaiveDateTime,
+    ) -> Result<i64> {
+        let conn = self.pool.get()?;
+        self.prune(&conn)?;
+        let hits = RateLimitCounter::hit(&conn, key, window_start, expires_at)?;
+        Ok(hits.into())
+    }
+}
+
+/// The outcome of `RateLimiter::check`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RateLimitDecision {
+    Allowed,
+    /// Over the limit until the window ends in this many seconds.
+    Limited(i64),
+}
+
+pub struct RateLimiter {
+    /// Limits by action and role name.
+    limits: HashMap<RateLimitAction, HashMap<String, Limit>>,
+    store: Box<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        limits: HashMap<RateLimitAction, HashMap<String, Limit>>,
+        store: Box<dyn RateLimitStore>,
+    ) -> Self {
+        Self { limits, store }
+    }
+
+    /// Reads the limits from `RATE_LIMIT_EDIT`, `RATE_LIMIT_CREATE` and `RATE_LIMIT_LOGIN`, and
+    /// the store from `RATE_LIMIT_STORE`.
+    pub fn from_env(pool: &DbPool) -> Result<Self> {
+        let mut limits = HashMap::new();
+        for action in &[
+            RateLimitAction::Edit,
+            RateLimitAction::Create,
+            RateLimitAction::Login,
+        ] {
+            let key = format!("RATE_LIMIT_{}", action.as_str().to_uppercase());
+            let by_role = env::var(&key)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let mut parts = pair.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(role), Some(limit)) => {
+                            Ok((role.trim().to_owned(), Limit::parse(limit)?))
+                        }
+                        _ => Err(anyhow!("Invalid {} entry {}", key, pair)),
+                    }
+                })
+                .collect::<Result<HashMap<String, Limit>>>()?;
+            limits.insert(*action, by_role);
+        }
+        let store: Box<dyn RateLimitStore> = match env::var("RATE_LIMIT_STORE").ok().as_deref() {
+            None | Some("") | Some("memory") => Box::new(MemoryStore::default()),
+            Some("postgres") => Box::new(PostgresStore::new(pool.clone())),
+            Some(store) => return Err(anyhow!("Unsupported RATE_LIMIT_STORE {}", store)),
+        };
+        Ok(Self::new(limits, store))
+    }
+
+    /// The most generous limit of `action` among the roles named `roles`.
+    pub fn find_limit(&self, action: RateLimitAction, roles: &[&str]) -> Option<Limit> {
+        let by_role = self.limits.get(&action)?;
+        roles.iter().filter_map(|role| by_role.get(*role)).fold(
+            None,
+            |best: Option<Limit>, limit| match best {
+                Some(best) if !limit.is_more_generous(&best) => Some(best),
+                _ => Some(*limit),
+            },
+        )
+    }
+
+    /// Counts a hit of `action` by the actor `actor_key` under `limit`.
+    pub fn check(
+        &self,
+        action: RateLimitAction,
+        actor_key: &str,
+        limit: &Limit,
+    ) -> Result<RateLimitDecision> {
+        let now = Utc::now().naive_utc();
+        let period = limit.period.num_seconds();
+        let window_start = NaiveDateTime::from_timestamp(now.timestamp() / period * period, 0);
+        let expires_at = window_start + limit.period;
+        let key = format!("{}:{}", action.as_str(), actor_key);
+        let hits = self.store.hit(&key, window_start, expires_at)?;
+        if hits > limit.max {
+            let retry_after = (expires_at - now).num_seconds().max(1);
+            Ok(RateLimitDecision::Limited(retry_after))
+        } else {
+            Ok(RateLimitDecision::Allowed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut by_role = HashMap::new();
+        by_role.insert(
+            "Anonymous".to_owned(),
+            Limit::parse("2/3600").expect("must succeed"),
+        );
+        by_role.insert(
+            "LoggedIn".to_owned(),
+            Limit::parse("10/3600").expect("must succeed"),
+        );
+        let mut limits = HashMap::new();
+        limits.insert(RateLimitAction::Edit, by_role);
+        let limiter = RateLimiter::new(limits, Box::new(MemoryStore::default()));
+
+        let limit = limiter
+            .find_limit(RateLimitAction::Edit, &["LoggedIn", "Anonymous"])
+            .expect("must exist");
+        assert_eq!(limit.max, 10);
+        assert!(limiter
+            .find_limit(RateLimitAction::Create, &["Anonymous"])
+            .is_none());
+
+        let limit = limiter
+            .find_limit(RateLimitAction::Edit, &["Anonymous"])
+            .expect("must exist");
+        for _ in 0..2 {
+            let decision = limiter
+                .check(RateLimitAction::Edit, "ip:192.0.2.1", &limit)
+                .expect("must succeed");
+            assert_eq!(decision, RateLimitDecision::Allowed);
+        }
+        let decision = limiter
+            .check(RateLimitAction::Edit, "ip:192.0.2.1", &limit)
+            .expect("must succeed");
+        assert!(matches!(decision, RateLimitDecision::Limited(retry_after) if retry_after <= 3600));
+        let decision = limiter
+            .check(RateLimitAction::Edit, "ip:192.0.2.2", &limit)
+            .expect("must succeed");
+        assert_eq!(decision, RateLimitDecision::Allowed);
+    }
+}
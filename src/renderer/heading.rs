@@ -1,10 +1,24 @@
+use htmlescape::encode_minimal;
 use parse_wiki_text::Node;
 
 pub fn render_heading(level: &u8, nodes: &[Node], state: &mut super::State) -> String {
+    let index = state.headings_rendered;
+    state.headings_rendered += 1;
+    let id = match state.toc.get(index) {
+        Some(entry) => format!(" id=\"{}\"", encode_minimal(&entry.id)),
+        None => String::new(),
+    };
+    // The table of contents comes before the first heading
+    let toc = match (index, state.toc.is_empty()) {
+        (0, false) => super::toc::render_toc(&state.toc),
+        _ => String::new(),
+    };
     format!(
-        "{}<h{}>{}</h{}>{}",
+        "{}{}<h{}{}>{}</h{}>{}",
         super::paragraph::close_paragraph(state),
+        toc,
         level,
+        id,
         super::render_nodes(&nodes, state),
         level,
         super::paragraph::open_paragraph()
@@ -40,4 +54,25 @@ mod tests {
             Ok(())
         })
     }
+
+    #[test]
+    fn test_render_toc() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let wikitext = "intro\n==a==\n==b c==\n===a===\n==d==";
+            let result = Configuration::default().parse(wikitext);
+            let options = RenderOptions { show_toc: true };
+            let html = render_with_options(&conn, &result, &options);
+            assert!(html.starts_with(
+                "<p>intro\n</p><div class=\"toc\"><ul><li class=\"toclevel-2\"><a href=\"#a\">a</a></li>"
+            ));
+            assert!(html.contains("<h2 id=\"b_c\">b c</h2>"));
+            assert!(html.contains("<h3 id=\"a_2\">a</h3>"));
+            // Too few headings
+            let result = Configuration::default().parse("==a==\n==b==");
+            let html = render_with_options(&conn, &result, &options);
+            assert_eq!(html, render(&conn, &result));
+            Ok(())
+        })
+    }
 }
@@ -1,21 +1,48 @@
-use crate::models::Article;
+use crate::models::{Article, Namespace};
+use crate::title::Title;
 use parse_wiki_text::Node;
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
 pub fn render_internal_link(target: &str, text: &[Node], state: &mut super::State) -> String {
-    state.internal_links.push(target.to_owned());
+    // A fragment points to a section and is not part of the title
+    let (target, fragment) = match target.find('#') {
+        Some(index) => (
+            &target[..index],
+            format!(
+                "#{}",
+                utf8_percent_encode(&target[index + 1..], NON_ALPHANUMERIC)
+            ),
+        ),
+        None => (target, String::new()),
+    };
+    if target.trim().is_empty() && !fragment.is_empty() {
+        let text_rendered = super::render_nodes(text, state);
+        return format!(r#"<a href="{}">{}</a>"#, fragment, text_rendered);
+    }
+    if Title::new(target).is_err() {
+        // Not a link target; show the text only
+        return super::render_nodes(text, state);
+    }
+    let (namespace, title) = match Namespace::parse_full_title(state.conn, target) {
+        Ok(parsed) => parsed,
+        Err(_) => return "Error".to_owned(),
+    };
+    // Link to the canonical title so that the case rule of the namespace applies
+    let full_title = namespace.join(&title);
+    state.internal_links.push(full_title.clone());
     let text_rendered = super::render_nodes(text, state);
-    match Article::find_by_full_title(state.conn, target) {
-        Ok(Some(article)) => format!(
-            r#"<a href="{}{}">{}</a>"#,
+    match Article::find(state.conn, &namespace, &title) {
+        Ok(Some(_)) => format!(
+            r#"<a href="{}{}{}">{}</a>"#,
             state.read_base_url,
-            utf8_percent_encode(target, NON_ALPHANUMERIC).to_owned(),
+            utf8_percent_encode(&full_title, NON_ALPHANUMERIC).to_owned(),
+            fragment,
             text_rendered
         ),
         Ok(None) => format!(
             r#"<a class="new" href="{}{}">{}</a>"#,
             state.edit_base_url,
-            utf8_percent_encode(target, NON_ALPHANUMERIC).to_owned(),
+            utf8_percent_encode(&full_title, NON_ALPHANUMERIC).to_owned(),
             text_rendered
         ),
         Err(_) => "Error".to_owned(),
@@ -12,6 +12,7 @@ mod paragraph;
 mod preformatted;
 mod table;
 mod template;
+mod toc;
 
 pub struct CategoryLink {
     target: String,
@@ -26,9 +27,23 @@ pub struct State<'a> {
     external_link_auto_number: i32,
     internal_links: Vec<String>,
     categories: Vec<CategoryLink>,
+    /// Entries of the table of contents, empty if there is none.
+    toc: Vec<toc::TocEntry>,
+    headings_rendered: usize,
+}
+
+/// How a reader likes articles to be shown.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderOptions {
+    /// Show a table of contents before the first heading of articles with enough headings.
+    pub show_toc: bool,
 }
 
 pub fn render(conn: &PgConnection, ast: &Output) -> String {
+    render_with_options(conn, ast, &RenderOptions::default())
+}
+
+pub fn render_with_options(conn: &PgConnection, ast: &Output, options: &RenderOptions) -> String {
     let mut state = State {
         conn,
         read_base_url: "/wiki/".to_owned(),
@@ -37,6 +52,12 @@ pub fn render(conn: &PgConnection, ast: &Output) -> String {
         external_link_auto_number: 0,
         internal_links: vec![],
         categories: vec![],
+        toc: if options.show_toc {
+            toc::collect(&ast.nodes)
+        } else {
+            vec![]
+        },
+        headings_rendered: 0,
     };
     format!("<p>{}</p>", render_nodes(&ast.nodes, &mut state))
         .replace("<p></p>", "")
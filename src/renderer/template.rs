@@ -7,7 +7,7 @@ pub fn render_template(
     state: &mut super::State,
 ) -> String {
     // @TODO: add template namespace automatically
-    let full_title = render_template_name(name);
+    let full_title = crate::parser::template_name(name);
     let article = match Article::find_by_full_title(state.conn, &full_title) {
         Ok(Some(article)) => article,
         Ok(None) => return render_template_not_found(&full_title),
@@ -19,19 +19,6 @@ pub fn render_template(
     }
 }
 
-fn render_template_name(name: &[Node]) -> String {
-    name.iter()
-        .map(|node| {
-            if let Node::Text { value, .. } = node {
-                value
-            } else {
-                ""
-            }
-        })
-        .collect::<Vec<&str>>()
-        .join("")
-}
-
 fn render_template_not_found(title: &str) -> String {
     format!("{{{}}}", title)
 }
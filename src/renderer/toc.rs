@@ -0,0 +1,69 @@
+use htmlescape::encode_minimal;
+use parse_wiki_text::Node;
+
+/// Articles with fewer headings get no table of contents.
+const MIN_HEADINGS: usize = 4;
+
+pub struct TocEntry {
+    level: u8,
+    text: String,
+    pub id: String,
+}
+
+/// The plain text of heading `nodes`, without markup.
+fn heading_text(nodes: &[Node]) -> String {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Text { value, .. } => (*value).to_owned(),
+            Node::CharacterEntity { character, .. } => character.to_string(),
+            Node::Link { text, .. } => heading_text(text),
+            _ => String::new(),
+        })
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+/// The entries of the headings in `nodes`, or none if there are too few of them. Headings
+/// with the same text get ids numbered from the second one on.
+pub fn collect(nodes: &[Node]) -> Vec<TocEntry> {
+    let mut entries: Vec<TocEntry> = vec![];
+    for node in nodes {
+        if let Node::Heading { level, nodes, .. } = node {
+            let text = heading_text(nodes);
+            let base = text.replace(' ', "_");
+            let mut id = base.clone();
+            let mut n = 1;
+            while entries.iter().any(|entry| entry.id == id) {
+                n += 1;
+                id = format!("{}_{}", base, n);
+            }
+            entries.push(TocEntry {
+                level: *level,
+                text,
+                id,
+            });
+        }
+    }
+    if entries.len() < MIN_HEADINGS {
+        entries.clear();
+    }
+    entries
+}
+
+pub fn render_toc(entries: &[TocEntry]) -> String {
+    let items = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "<li class=\"toclevel-{}\"><a href=\"#{}\">{}</a></li>",
+                entry.level,
+                encode_minimal(&entry.id),
+                encode_minimal(&entry.text)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("");
+    format!("<div class=\"toc\"><ul>{}</ul></div>\n", items)
+}
@@ -0,0 +1,680 @@
+use super::articles::ActorEntity;
+use super::error::ApiError;
+use super::helpers::{find_filter, find_revision, has_right};
+use super::revisions::check_manual_tag;
+use super::Response;
+use crate::abuse_filter::{validate_rule, FilterVariables, Rule};
+use crate::extractors::{DbConnection, Query, UserInfo};
+use crate::models::{
+    AbuseFilter, AbuseFilterChanges, AbuseFilterHit, Actor, FilterAction, FilteredChange,
+    Namespace, Right, Scope,
+};
+use actix_web::{
+    delete, error::ErrorInternalServerError, get, http::StatusCode, patch, post, web, Error,
+    HttpResponse,
+};
+use actix_web_validator::Json;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use serde::{Deserialize, Deserializer, Serialize};
+use validator::Validate;
+
+const MAX_LIMIT: i64 = 500;
+/// Past revisions one test may evaluate a rule on.
+const MAX_TEST_REVISIONS: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseFilterEntity {
+    id: i32,
+    name: String,
+    description: String,
+    pattern: String,
+    action: FilterAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    throttle_count: Option<i32>,
+    /// In seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    throttle_period: Option<i32>,
+    enabled: bool,
+    created_by: ActorEntity,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl AbuseFilterEntity {
+    pub fn from_filter(conn: &PgConnection, filter: &AbuseFilter) -> Result<Self> {
+        Ok(Self {
+            id: filter.id,
+            name: filter.name.clone(),
+            description: filter.description.clone(),
+            pattern: filter.pattern.clone(),
+            action: filter.action,
+            tag: filter.tag.clone(),
+            throttle_count: filter.throttle_count,
+            throttle_period: filter.throttle_period,
+            enabled: filter.enabled,
+            created_by: ActorEntity::from_actor(conn, &filter.get_actor(conn)?)?,
+            created_at: filter.created_at,
+            updated_at: filter.updated_at,
+        })
+    }
+}
+
+pub type AbuseFiltersGetResponse = Vec<AbuseFilterEntity>;
+
+/// A filter as shown to the editor whose change it matched.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseFilterMatchEntity {
+    id: i32,
+    name: String,
+    description: String,
+}
+
+impl From<&AbuseFilter> for AbuseFilterMatchEntity {
+    fn from(filter: &AbuseFilter) -> Self {
+        Self {
+            id: filter.id,
+            name: filter.name.clone(),
+            description: filter.description.clone(),
+        }
+    }
+}
+
+/// Evaluates the abuse filters on `change`. Responds with 403 naming the filter when one
+/// disallows it, or with 422 listing the warnings unless `acknowledge_warnings`. Otherwise
+/// returns the tags to add to the revision.
+pub fn check_abuse_filters(
+    conn: &PgConnection,
+    change: &FilteredChange,
+    acknowledge_warnings: bool,
+) -> Result<Vec<String>, ApiError> {
+    let outcome = AbuseFilter::check(conn, change, acknowledge_warnings)?;
+    if let Some(filter) = &outcome.disallowed_by {
+        return Err(ApiError::rejected(
+            StatusCode::FORBIDDEN,
+            "Disallowed",
+            AbuseFilterMatchEntity::from(filter),
+        ));
+    }
+    if !outcome.warnings.is_empty() && !acknowledge_warnings {
+        return Err(ApiError::rejected(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Warning",
+            outcome
+                .warnings
+                .iter()
+                .map(AbuseFilterMatchEntity::from)
+                .collect::<Vec<AbuseFilterMatchEntity>>(),
+        ));
+    }
+    Ok(outcome.tags)
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseFilterCreateRequest {
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    #[serde(default)]
+    #[validate(length(max = 1000))]
+    description: String,
+    #[validate(length(min = 1, max = 10000), custom = "validate_rule")]
+    pattern: String,
+    action: FilterAction,
+    /// Required when `action` is `tag`.
+    #[validate(length(min = 1, max = 100))]
+    tag: Option<String>,
+    /// Required when `action` is `throttle`.
+    #[validate(range(min = 0))]
+    throttle_count: Option<i32>,
+    #[validate(range(min = 1))]
+    throttle_period: Option<i32>,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Deserializes a present field, even `null`, as `Some`, so that `null` can be told apart from
+/// a field left out.
+fn deserialize_present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Fields left out are kept. `null` clears the tag and the throttle count and period.
+#[derive(Serialize, Deserialize, Validate, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseFilterUpdateRequest {
+    #[validate(length(min = 1, max = 100))]
+    name: Option<String>,
+    #[validate(length(max = 1000))]
+    description: Option<String>,
+    #[validate(length(min = 1, max = 10000), custom = "validate_rule")]
+    pattern: Option<String>,
+    action: Option<FilterAction>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[validate(length(min = 1, max = 100))]
+    tag: Option<Option<String>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[validate(range(min = 0))]
+    throttle_count: Option<Option<i32>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[validate(range(min = 1))]
+    throttle_period: Option<Option<i32>>,
+    enabled: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AbuseFilterHitsQuery {
+    pub offset: i64,
+    pub limit: i64,
+}
+
+impl Default for AbuseFilterHitsQuery {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            limit: 50,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseFilterHitEntity {
+    id: i32,
+    actor: ActorEntity,
+    full_title: String,
+    action: FilterAction,
+    created_at: NaiveDateTime,
+}
+
+impl AbuseFilterHitEntity {
+    pub fn from_hit(conn: &PgConnection, hit: &AbuseFilterHit) -> Result<Self> {
+        let full_title = match Namespace::find_by_id(conn, hit.namespace_id)? {
+            Some(namespace) => namespace.join(&hit.title),
+            None => hit.title.clone(),
+        };
+        Ok(Self {
+            id: hit.id,
+            actor: ActorEntity::from_actor(conn, &hit.get_actor(conn)?)?,
+            full_title,
+            action: hit.action,
+            created_at: hit.created_at,
+        })
+    }
+}
+
+pub type AbuseFilterHitsGetResponse = Vec<AbuseFilterHitEntity>;
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseFilterTestRequest {
+    #[validate(length(min = 1, max = 10000), custom = "validate_rule")]
+    pattern: String,
+    revision_ids: Vec<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseFilterTestResult {
+    revision_id: i32,
+    matched: bool,
+    /// Why the rule could not be evaluated, e.g. comparing a string with an integer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    variables: FilterVariables,
+}
+
+pub type AbuseFilterTestResponse = Vec<AbuseFilterTestResult>;
+
+/// `Root` and roles with `Right::ManageAbuseFilters` may see and change filters.
+fn can_manage(conn: &PgConnection, user_info: &UserInfo) -> Result<bool> {
+    has_right(conn, user_info, Scope::Admin, Right::ManageAbuseFilters)
+}
+
+/// Responds with 400 unless a tagging filter has a tag and a throttling one a count and period,
+/// or when the tag is one only added automatically.
+fn check_action(
+    action: FilterAction,
+    tag: Option<&str>,
+    throttle_count: Option<i32>,
+    throttle_period: Option<i32>,
+) -> Result<(), ApiError> {
+    if let Some(tag) = tag {
+        check_manual_tag(tag)?;
+    }
+    match action {
+        FilterAction::Tag if tag.is_none() => Err(ApiError::BadRequest(
+            "A tag is required to tag revisions".to_owned(),
+        )),
+        FilterAction::Throttle if throttle_count.is_none() || throttle_period.is_none() => Err(
+            ApiError::BadRequest("A throttle count and period are required to throttle".to_owned()),
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[get("/abuse-filters")]
+pub async fn get_abuse_filters(
+    user_info: UserInfo,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    if !can_manage(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let data = AbuseFilter::list(&conn)
+        .and_then(|filters| {
+            filters
+                .iter()
+                .map(|filter| AbuseFilterEntity::from_filter(&conn, filter))
+                .collect::<Result<AbuseFiltersGetResponse>>()
+        })
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[post("/abuse-filters")]
+pub async fn create_abuse_filter(
+    user_info: UserInfo,
+    conn: DbConnection,
+    data: Json<AbuseFilterCreateRequest>,
+) -> Result<HttpResponse, Error> {
+    if !can_manage(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    check_action(
+        data.action,
+        data.tag.as_deref(),
+        data.throttle_count,
+        data.throttle_period,
+    )?;
+    let actor = Actor::find_or_create_from_user_id(&conn, user_info.id)
+        .map_err(ErrorInternalServerError)?;
+    let filter = AbuseFilter::create(
+        &conn,
+        &data.name,
+        &data.description,
+        &data.pattern,
+        data.action,
+        data.tag.as_deref(),
+        data.throttle_count,
+        data.throttle_period,
+        data.enabled,
+        &actor,
+    )
+    .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: AbuseFilterEntity::from_filter(&conn, &filter).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Created().json(resp))
+}
+
+#[get("/abuse-filters/{id}")]
+pub async fn get_abuse_filter(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((id,)): web::Path<(i32,)>,
+) -> Result<HttpResponse, Error> {
+    if !can_manage(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let filter = find_filter(&conn, id)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: AbuseFilterEntity::from_filter(&conn, &filter).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[patch("/abuse-filters/{id}")]
+pub async fn update_abuse_filter(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((id,)): web::Path<(i32,)>,
+    data: Json<AbuseFilterUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    if !can_manage(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let mut filter = find_filter(&conn, id)?;
+    let tag = data.tag.clone().unwrap_or_else(|| filter.tag.clone());
+    check_action(
+        data.action.unwrap_or(filter.action),
+        tag.as_deref(),
+        data.throttle_count.unwrap_or(filter.throttle_count),
+        data.throttle_period.unwrap_or(filter.throttle_period),
+    )?;
+    let changes = AbuseFilterChanges {
+        name: data.name.clone(),
+        description: data.description.clone(),
+        pattern: data.pattern.clone(),
+        action: data.action,
+        tag: data.tag.clone(),
+        throttle_count: data.throttle_count,
+        throttle_period: data.throttle_period,
+        enabled: data.enabled,
+    };
+    filter
+        .update(&conn, &changes)
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: AbuseFilterEntity::from_filter(&conn, &filter).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[delete("/abuse-filters/{id}")]
+pub async fn delete_abuse_filter(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((id,)): web::Path<(i32,)>,
+) -> Result<HttpResponse, Error> {
+    if !can_manage(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if !AbuseFilter::delete(&conn, id).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::NotFound().body(format!("No abuse filter found with id: {}", id)));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/abuse-filters/{id}/hits")]
+pub async fn get_abuse_filter_hits(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((id,)): web::Path<(i32,)>,
+    query: Option<Query<AbuseFilterHitsQuery>>,
+) -> Result<HttpResponse, Error> {
+    if !can_manage(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let filter = find_filter(&conn, id)?;
+    let query = query.unwrap_or_default();
+    let data = AbuseFilterHit::find_by_filter(
+        &conn,
+        &filter,
+        query.offset.max(0),
+        query.limit.max(1).min(MAX_LIMIT),
+    )
+    .and_then(|hits| {
+        hits.iter()
+            .map(|hit| AbuseFilterHitEntity::from_hit(&conn, hit))
+            .collect::<Result<AbuseFilterHitsGetResponse>>()
+    })
+    .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+/// Evaluates a rule on past revisions without saving it or recording hits.
+#[post("/abuse-filters/test")]
+pub async fn test_abuse_filter(
+    user_info: UserInfo,
+    conn: DbConnection,
+    data: Json<AbuseFilterTestRequest>,
+) -> Result<HttpResponse, Error> {
+    if !can_manage(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if data.revision_ids.len() > MAX_TEST_REVISIONS {
+        return Ok(HttpResponse::BadRequest().body(format!(
+            "At most {} revisions can be tested at once",
+            MAX_TEST_REVISIONS
+        )));
+    }
+    let rule = match Rule::parse(&data.pattern) {
+        Ok(rule) => rule,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e.to_string())),
+    };
+    let mut results: AbuseFilterTestResponse = vec![];
+    for revision_id in data.revision_ids.iter() {
+        let revision = find_revision(&conn, *revision_id)?;
+        let variables = FilteredChange::variables_of_revision(&conn, &revision)
+            .map_err(ErrorInternalServerError)?;
+        let (matched, error) = match rule.matches(&variables) {
+            Ok(matched) => (matched, None),
+            Err(e) => (false, Some(e.to_string())),
+        };
+        results.push(AbuseFilterTestResult {
+            revision_id: revision.id,
+            matched,
+            error,
+            variables,
+        });
+    }
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: results,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::routes::test_helpers::{create_article, create_root_access_token};
+    use actix_web::{test, App};
+    use diesel::prelude::*;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    #[actix_rt::test]
+    async fn test_get_abuse_filters_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(get_abuse_filters)).await;
+        let req = test::TestRequest::get().uri("/abuse-filters").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[actix_rt::test]
+    async fn test_test_abuse_filter_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(test_abuse_filter)).await;
+        let data = AbuseFilterTestRequest {
+            pattern: "size_delta < -1000".to_owned(),
+            revision_ids: vec![1],
+        };
+        let req = test::TestRequest::post()
+            .set_json(&data)
+            .uri("/abuse-filters/test")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[actix_rt::test]
+    async fn test_create_and_update_abuse_filter() {
+        let pool = db::create_test_connection_pool();
+        let access_token = {
+            let conn = pool.get().expect("must succeed");
+            create_root_access_token(&conn, "filter_admin")
+        };
+        let mut app = test::init_service(
+            App::new()
+                .data(pool.clone())
+                .service(create_abuse_filter)
+                .service(update_abuse_filter),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .header("Authorization", format!("Bearer {}", access_token))
+            .set_json(&json!({
+                "name": "Untagged",
+                "pattern": "size_delta < 0",
+                "action": "tag",
+            }))
+            .uri("/abuse-filters")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let req = test::TestRequest::post()
+            .header("Authorization", format!("Bearer {}", access_token))
+            .set_json(&json!({
+                "name": "Fake bot",
+                "pattern": "size_delta < 0",
+                "action": "tag",
+                "tag": "bot",
+            }))
+            .uri("/abuse-filters")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let req = test::TestRequest::post()
+            .header("Authorization", format!("Bearer {}", access_token))
+            .set_json(&json!({
+                "name": "Blanking",
+                "pattern": "size_delta < -100",
+                "action": "tag",
+                "tag": "possible-blanking",
+            }))
+            .uri("/abuse-filters")
+            .to_request();
+        let created: Response<AbuseFilterEntity> = test::read_response_json(&mut app, req).await;
+        assert_eq!(created.data.tag.as_deref(), Some("possible-blanking"));
+
+        // Clearing the tag of a tagging filter is rejected, but not once it disallows
+        let req = test::TestRequest::patch()
+            .header("Authorization", format!("Bearer {}", access_token))
+            .set_json(&json!({ "tag": null }))
+            .uri(&format!("/abuse-filters/{}", created.data.id))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let req = test::TestRequest::patch()
+            .header("Authorization", format!("Bearer {}", access_token))
+            .set_json(&json!({ "action": "disallow", "tag": null }))
+            .uri(&format!("/abuse-filters/{}", created.data.id))
+            .to_request();
+        let updated: Response<AbuseFilterEntity> = test::read_response_json(&mut app, req).await;
+        assert_eq!(updated.data.action, FilterAction::Disallow);
+        assert!(updated.data.tag.is_none());
+        assert_eq!(updated.data.name, "Blanking");
+    }
+
+    #[actix_rt::test]
+    async fn test_test_abuse_filter() {
+        let pool = db::create_test_connection_pool();
+        let (access_token, revision_id) = {
+            let conn = pool.get().expect("must succeed");
+            let article = create_article(&conn, "192.0.2.9", "filter test", "Cheap pills");
+            let revision = article.get_latest_revision(&conn).expect("must succeed");
+            (
+                create_root_access_token(&conn, "filter_tester"),
+                revision.id,
+            )
+        };
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(test_abuse_filter)).await;
+        let data = AbuseFilterTestRequest {
+            pattern: "added_lines irlike 'cheap pills' & is_anonymous".to_owned(),
+            revision_ids: vec![revision_id],
+        };
+        let req = test::TestRequest::post()
+            .header("Authorization", format!("Bearer {}", access_token))
+            .set_json(&data)
+            .uri("/abuse-filters/test")
+            .to_request();
+        let result: Response<AbuseFilterTestResponse> =
+            test::read_response_json(&mut app, req).await;
+        assert_eq!(result.data.len(), 1);
+        assert!(result.data[0].matched);
+        assert!(result.data[0].error.is_none());
+        assert_eq!(result.data[0].variables.action, "create");
+
+        // Testing records no hits
+        let conn = pool.get().expect("must succeed");
+        let hits = crate::schema::abuse_filter_hits::table
+            .filter(crate::schema::abuse_filter_hits::title.eq("filter test"))
+            .count()
+            .get_result::<i64>(&conn)
+            .expect("must succeed");
+        assert_eq!(hits, 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_disallow() {
+        use crate::routes::articles::create_article as create_article_route;
+
+        let pool = db::create_test_connection_pool();
+        let filter = {
+            let conn = pool.get().expect("must succeed");
+            let ip_address = ipnetwork::IpNetwork::from_str("192.0.2.10").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            AbuseFilter::create(
+                &conn,
+                "No sandboxes",
+                "Sandboxes are elsewhere",
+                "action == 'create' & title contains 'Sandbox'",
+                FilterAction::Disallow,
+                None,
+                None,
+                None,
+                true,
+                &actor,
+            )
+            .expect("must succeed")
+        };
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(create_article_route)).await;
+        let req = test::TestRequest::post()
+            .peer_addr("192.0.2.11:22342".parse().unwrap())
+            .set_json(&json!({
+                "fullTitle": "My Sandbox",
+                "wikitext": "Testing",
+                "comment": "",
+            }))
+            .uri("/articles")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 403);
+
+        let conn = pool.get().expect("must succeed");
+        let hits = AbuseFilterHit::find_by_filter(&conn, &filter, 0, 10).expect("must succeed");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].title, "My Sandbox");
+        let namespace = Namespace::get_default(&conn).expect("must succeed");
+        let article =
+            crate::models::Article::find(&conn, &namespace, "My Sandbox").expect("must succeed");
+        assert!(article.is_none());
+    }
+}
@@ -0,0 +1,209 @@
+use super::Response;
+use crate::extractors::{DbConnection, UserInfo};
+use crate::models::{ApiToken, Scope, User};
+use actix_web::{delete, error::ErrorInternalServerError, get, post, web, Error, HttpResponse};
+use actix_web_validator::Json;
+use chrono::prelude::*;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenEntity {
+    id: i32,
+    name: String,
+    scopes: Vec<Scope>,
+    expires_at: Option<NaiveDateTime>,
+    last_used_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+impl From<ApiToken> for ApiTokenEntity {
+    fn from(api_token: ApiToken) -> Self {
+        Self {
+            id: api_token.id,
+            name: api_token.name,
+            scopes: api_token.scopes,
+            expires_at: api_token.expires_at,
+            last_used_at: api_token.last_used_at,
+            created_at: api_token.created_at,
+        }
+    }
+}
+
+pub type ApiTokensGetResponse = Vec<ApiTokenEntity>;
+
+#[get("/me/api-tokens")]
+pub async fn get_api_tokens(
+    user_info: UserInfo,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    user_info.require_session()?;
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    let data = ApiToken::find_by_user(&conn, &user)
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(ApiTokenEntity::from)
+        .collect::<ApiTokensGetResponse>();
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenCreateRequest {
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    #[validate(length(min = 1))]
+    scopes: Vec<Scope>,
+    /// Never expires when missing.
+    #[serde(default)]
+    expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenCreateResponse {
+    #[serde(flatten)]
+    api_token: ApiTokenEntity,
+    /// Shown only once.
+    token: String,
+}
+
+#[post("/me/api-tokens")]
+pub async fn create_api_token(
+    user_info: UserInfo,
+    conn: DbConnection,
+    data: Json<ApiTokenCreateRequest>,
+) -> Result<HttpResponse, Error> {
+    user_info.require_session()?;
+    if matches!(data.expires_at, Some(expires_at) if expires_at <= Utc::now().naive_utc()) {
+        return Ok(HttpResponse::BadRequest().body("Expiry must be in the future"));
+    }
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    if ApiToken::find_by_name(&conn, &user, &data.name)
+        .map_err(ErrorInternalServerError)?
+        .is_some()
+    {
+        return Ok(HttpResponse::Conflict().body(format!("Token {} already exists", &data.name)));
+    }
+    let (api_token, token) =
+        ApiToken::create(&conn, &user, &data.name, &data.scopes, data.expires_at)
+            .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: ApiTokenCreateResponse {
+            api_token: api_token.into(),
+            token,
+        },
+    };
+    Ok(HttpResponse::Created().json(resp))
+}
+
+#[delete("/me/api-tokens/{id}")]
+pub async fn delete_api_token(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((id,)): web::Path<(i32,)>,
+) -> Result<HttpResponse, Error> {
+    user_info.require_session()?;
+    if !ApiToken::delete(&conn, user_info.id, id).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::NotFound().body(format!("No API token found: {}", id)));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_create_api_token_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(create_api_token)).await;
+        let data = ApiTokenCreateRequest {
+            name: "bot".to_owned(),
+            scopes: vec![Scope::Read],
+            expires_at: None,
+        };
+        let req = test::TestRequest::post()
+            .set_json(&data)
+            .uri("/me/api-tokens")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[actix_rt::test]
+    async fn test_create_expired_api_token() {
+        use crate::models::Session;
+        use ipnetwork::IpNetwork;
+        use std::str::FromStr;
+
+        let pool = db::create_test_connection_pool();
+        let access_token = {
+            let conn = pool.get().expect("must succeed");
+            let user = User::create(&conn, "test@testtest.com", "tester22").expect("must succeed");
+            let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+            let (session, _) =
+                Session::create(&conn, &user, None, &ip_address).expect("must succeed");
+            crate::auth::issue_access_token(user.id, session.id).expect("must succeed")
+        };
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(create_api_token)).await;
+        let data = ApiTokenCreateRequest {
+            name: "bot".to_owned(),
+            scopes: vec![Scope::Read],
+            expires_at: Some(Utc::now().naive_utc() - chrono::Duration::days(1)),
+        };
+        let req = test::TestRequest::post()
+            .header("Authorization", format!("Bearer {}", access_token))
+            .set_json(&data)
+            .uri("/me/api-tokens")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid_api_token() {
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(get_api_tokens)).await;
+        let req = test::TestRequest::get()
+            .header("Authorization", "Bearer wikist_unknown")
+            .uri("/me/api-tokens")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[actix_rt::test]
+    async fn test_malformed_authorization_header() {
+        use actix_web::http::HeaderValue;
+
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(get_api_tokens)).await;
+        let malformed = vec![
+            HeaderValue::from_static("Basic dXNlcjpwYXNz"),
+            HeaderValue::from_static("Bearer"),
+            HeaderValue::from_bytes(b"Bearer \xff\xfe").expect("must succeed"),
+        ];
+        for header in malformed {
+            let req = test::TestRequest::get()
+                .header("Authorization", header)
+                .uri("/me/api-tokens")
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status().as_u16(), 401);
+        }
+    }
+}
@@ -1,12 +1,29 @@
+use super::abuse_filters::check_abuse_filters;
+use super::blocks::blocked;
+use super::error::ApiError;
+use super::helpers::find_article;
+use super::protections::ProtectionEntity;
 use super::Response;
-use crate::extractors::{ConnectionInfo, DbConnection, Query, UserInfo};
+use crate::client_ip::CLIENT_IP;
+use crate::extractors::{
+    ConnectionInfo, Creations, DbConnection, Edits, Query, RateLimit, UserInfo,
+};
+use crate::models::{
+    Actor, Block, FilteredChange, Namespace, Protection, ProtectionAction, Revision, RevisionKind,
+    RevisionPayload, RevisionTag, Right, Scope, User, UserPreferences,
+};
 use crate::parser;
+use crate::renderer::RenderOptions;
+use crate::title::validate_title;
 use actix_web::{
     delete, error::ErrorInternalServerError, get, post, put, web, Error, HttpResponse,
 };
 use actix_web_validator::Json;
 use anyhow::{anyhow, Result};
+use chrono::prelude::*;
 use chrono::NaiveDateTime;
+use diesel::{Connection, PgConnection};
+use ipnetwork::IpNetwork;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use validator::Validate;
@@ -41,6 +58,98 @@ pub struct ArticleGetResponse {
     html: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     wikitext: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    talk_page: Option<ArticleLinkEntity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject_page: Option<ArticleLinkEntity>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    protections: Vec<ProtectionEntity>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticleLinkEntity {
+    full_title: String,
+    exists: bool,
+}
+
+impl ArticleLinkEntity {
+    fn new(conn: &PgConnection, namespace: &Namespace, title: &str) -> Result<Self> {
+        use crate::models::Article;
+        Ok(Self {
+            full_title: namespace.join(title),
+            exists: Article::find(conn, namespace, title)?.is_some(),
+        })
+    }
+}
+
+/// Responds with 403 when an active protection of the title forbids `action` to `user`.
+fn check_protection(
+    conn: &PgConnection,
+    user: Option<&User>,
+    namespace: &Namespace,
+    title: &str,
+    action: ProtectionAction,
+) -> Result<(), ApiError> {
+    if Protection::allows(conn, user, namespace, title, action)? {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden(format!(
+            "{} is protected against {}",
+            namespace.join(title),
+            action.as_str()
+        )))
+    }
+}
+
+/// Responds with 403 describing the block when `user`, or an anonymous visitor if `None`, is
+/// blocked from editing in `namespace` from `ip_address`.
+fn check_block(
+    conn: &PgConnection,
+    user: Option<&User>,
+    ip_address: &IpNetwork,
+    namespace: &Namespace,
+) -> Result<(), ApiError> {
+    match Block::find_blocking_edit(conn, user, ip_address, namespace)? {
+        Some(block) => Err(blocked(conn, &block)),
+        None => Ok(()),
+    }
+}
+
+/// Tags `revision` with its automatic tags and `tags`.
+fn tag_revision(conn: &PgConnection, revision: &Revision, tags: &[String]) -> Result<()> {
+    let mut names = RevisionTag::automatic(conn, revision)?;
+    names.extend_from_slice(tags);
+    revision.add_tags(conn, &names)
+}
+
+/// Patrols `revision` automatically when its author may patrol or has `Right::Autopatrol`, so
+/// that their revisions never wait for review.
+fn autopatrol(conn: &PgConnection, revision: &mut Revision) -> Result<()> {
+    let user_id = match revision.get_actor(conn)?.user_id {
+        Some(user_id) => user_id,
+        None => return Ok(()),
+    };
+    let user = User::find_by_id(conn, user_id)?;
+    if user.has_right(conn, Right::Patrol)? || user.has_right(conn, Right::Autopatrol)? {
+        revision.autopatrol(conn)?;
+    }
+    Ok(())
+}
+
+/// Makes the change `change`, tagging the revision it returns with `tags` and patrolling it if
+/// its author needs no review.
+fn make_change(
+    conn: &PgConnection,
+    tags: &[String],
+    change: impl FnOnce() -> Result<Revision>,
+) -> Result<Revision> {
+    conn.transaction(|| {
+        let mut revision = change()?;
+        tag_revision(conn, &revision, tags)?;
+        autopatrol(conn, &mut revision)?;
+        Ok(revision)
+    })
 }
 
 #[get("/articles/{full_title}")]
@@ -50,19 +159,13 @@ pub async fn get_article(
     query: Option<Query<ArticleGetQuery>>,
     conn: DbConnection,
 ) -> Result<HttpResponse, Error> {
-    use crate::models::{Article, Role, User};
+    use crate::models::Role;
     let ArticleGetQuery { fields } = &*query.unwrap_or_default();
     // let full_title = path.0.clone();
-    let article =
-        match Article::find_by_full_title(&conn, &full_title).map_err(ErrorInternalServerError)? {
-            Some(article) => article,
-            None => {
-                return Ok(HttpResponse::NotFound()
-                    .body(format!("No article found with full title: {}", &full_title)));
-            }
-        };
-    match user_info {
+    let article = find_article(&conn, &full_title)?;
+    let preferences = match user_info {
         Some(user_info) => {
+            user_info.require_scope(Scope::Read)?;
             let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
             let can_read = user
                 .can_read(&conn, &article)
@@ -70,6 +173,7 @@ pub async fn get_article(
             if !can_read {
                 return Ok(HttpResponse::Forbidden().finish());
             }
+            user.get_preferences()
         }
         None => {
             let can_read = Role::anonymous()
@@ -78,6 +182,7 @@ pub async fn get_article(
             if !can_read {
                 return Ok(HttpResponse::Forbidden().finish());
             }
+            UserPreferences::default()
         }
     };
     let revision = article
@@ -86,14 +191,41 @@ pub async fn get_article(
     let wikitext = revision
         .get_wikitext(&conn)
         .map_err(ErrorInternalServerError)?;
-    let full_title = article
-        .get_full_title(&conn)
+    let namespace = article
+        .get_namespace(&conn)
+        .map_err(ErrorInternalServerError)?;
+    let full_title = namespace.join(&article.title);
+    let talk_page = namespace
+        .get_talk(&conn)
+        .and_then(|talk| {
+            talk.map(|talk| ArticleLinkEntity::new(&conn, &talk, &article.title))
+                .transpose()
+        })
+        .map_err(ErrorInternalServerError)?;
+    let subject_page = namespace
+        .get_subject(&conn)
+        .and_then(|subject| {
+            subject
+                .map(|subject| ArticleLinkEntity::new(&conn, &subject, &article.title))
+                .transpose()
+        })
+        .map_err(ErrorInternalServerError)?;
+    let protections = Protection::find_active(&conn, &namespace, &article.title)
+        .and_then(|protections| {
+            protections
+                .iter()
+                .map(|protection| ProtectionEntity::from_protection(&conn, protection))
+                .collect::<Result<Vec<ProtectionEntity>>>()
+        })
         .map_err(ErrorInternalServerError)?;
     let html = if fields.contains(&ArticleGetQueryFields::Html) {
         let wikitext = wikitext.clone();
+        let options = RenderOptions {
+            show_toc: preferences.show_toc,
+        };
         let html = web::block(move || -> Result<String> {
             let parsed = parser::parse(&wikitext);
-            let html = crate::renderer::render(&conn, &parsed);
+            let html = crate::renderer::render_with_options(&conn, &parsed, &options);
             Ok(html)
         })
         .await
@@ -112,6 +244,9 @@ pub async fn get_article(
             } else {
                 None
             },
+            talk_page,
+            subject_page,
+            protections,
         },
     };
     Ok(HttpResponse::Ok().json(resp))
@@ -126,6 +261,33 @@ pub enum ActorEntity {
     Anonymous { ip_address: String },
 }
 
+impl ActorEntity {
+    pub fn from_actor(conn: &PgConnection, actor: &Actor) -> Result<Self> {
+        match actor {
+            Actor {
+                user_id: Some(_), ..
+            } => Ok(ActorEntity::User {
+                username: actor.get_user(conn)?.username,
+            }),
+            Actor {
+                ip_address: Some(ip_address),
+                ..
+            } => Ok(ActorEntity::Anonymous {
+                ip_address: CLIENT_IP.display_ip(ip_address.ip()),
+            }),
+            _ => Err(anyhow!("Both user_id and ip_address are null.")),
+        }
+    }
+
+    /// Name to show as the author of a change.
+    pub fn name(&self) -> &str {
+        match self {
+            ActorEntity::User { username } => username,
+            ActorEntity::Anonymous { ip_address } => ip_address,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ArticleRevisionEntity {
@@ -133,27 +295,42 @@ pub struct ArticleRevisionEntity {
     created_at: NaiveDateTime,
     actor: ActorEntity,
     comment: String,
+    kind: RevisionKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<RevisionPayload>,
+    size: i32,
+    size_delta: i32,
+    /// An edit which did not change the content.
+    no_op: bool,
+    /// Made with an API token.
+    is_bot: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    tags: Vec<String>,
+    patrolled: bool,
 }
 
 pub type ArticleRevisionsGetResponse = Vec<ArticleRevisionEntity>;
 
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ArticleRevisionsQuery {
+    /// Only revisions with this tag.
+    pub tag: Option<String>,
+}
+
 #[get("/articles/{full_title}/revisions")]
 pub async fn get_revisions(
     web::Path((full_title,)): web::Path<(String,)>,
     user_info: Option<UserInfo>,
+    query: Option<Query<ArticleRevisionsQuery>>,
     conn: DbConnection,
 ) -> Result<HttpResponse, Error> {
-    use crate::models::{Actor, Article, Role, User};
-    let article =
-        match Article::find_by_full_title(&conn, &full_title).map_err(ErrorInternalServerError)? {
-            Some(article) => article,
-            None => {
-                return Ok(HttpResponse::NotFound()
-                    .body(format!("No article found with full title: {}", &full_title)));
-            }
-        };
+    use crate::models::Role;
+    let ArticleRevisionsQuery { tag } = &*query.unwrap_or_default();
+    let article = find_article(&conn, &full_title)?;
     match user_info {
         Some(user_info) => {
+            user_info.require_scope(Scope::Read)?;
             let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
             let can_read = user
                 .can_read(&conn, &article)
@@ -171,40 +348,32 @@ pub async fn get_revisions(
             }
         }
     };
-    let revisions = article
-        .get_all_revisions(&conn)
-        .map_err(ErrorInternalServerError)?;
-    let data: Vec<ArticleRevisionEntity> = revisions
-        .iter()
-        .map(|rev| {
-            let actor = rev.get_actor(&conn)?;
-            match actor {
-                Actor {
-                    user_id: Some(_), ..
-                } => Ok(ArticleRevisionEntity {
-                    id: rev.id,
-                    created_at: rev.created_at,
-                    actor: ActorEntity::User {
-                        username: actor.get_user(&conn)?.username,
-                    },
-                    comment: rev.comment.clone(),
-                }),
-                Actor {
-                    ip_address: Some(ip_address),
-                    ..
-                } => Ok(ArticleRevisionEntity {
-                    id: rev.id,
-                    created_at: rev.created_at,
-                    actor: ActorEntity::Anonymous {
-                        ip_address: ip_address.ip().to_string(),
-                    },
-                    comment: rev.comment.clone(),
-                }),
-                _ => Err(anyhow!("Both user_id and ip_address are null.")),
-            }
-        })
-        .collect::<Result<Vec<ArticleRevisionEntity>, _>>()
+    let revisions = Revision::find_by_article(&conn, &article, tag.as_deref())
         .map_err(ErrorInternalServerError)?;
+    let sizes = Revision::get_sizes(&conn, &revisions).map_err(ErrorInternalServerError)?;
+    let mut tags =
+        RevisionTag::find_by_revisions(&conn, &revisions).map_err(ErrorInternalServerError)?;
+    let mut data: Vec<ArticleRevisionEntity> = vec![];
+    for rev in revisions.iter() {
+        let size = sizes
+            .get(&rev.id)
+            .ok_or_else(|| ErrorInternalServerError("Cannot find revision size"))?;
+        let actor = rev.get_actor(&conn).map_err(ErrorInternalServerError)?;
+        data.push(ArticleRevisionEntity {
+            id: rev.id,
+            created_at: rev.created_at,
+            actor: ActorEntity::from_actor(&conn, &actor).map_err(ErrorInternalServerError)?,
+            comment: rev.comment.clone(),
+            kind: rev.kind,
+            payload: rev.get_payload().map_err(ErrorInternalServerError)?,
+            size: size.size,
+            size_delta: size.size_delta,
+            no_op: rev.is_no_op(size.parent_content_id),
+            is_bot: rev.is_bot,
+            tags: tags.remove(&rev.id).unwrap_or_default(),
+            patrolled: rev.is_patrolled(),
+        });
+    }
     let resp = Response {
         status: "OK".to_owned(),
         data,
@@ -215,12 +384,15 @@ pub async fn get_revisions(
 #[derive(Serialize, Deserialize, Validate, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ArticleCreateRequest {
-    #[validate(length(min = 1, max = 300))]
+    #[validate(length(min = 1, max = 300), custom = "validate_title")]
     full_title: String,
     #[validate(length(min = 0, max = 1000000))]
     wikitext: String,
     #[validate(length(min = 0, max = 1000))]
     comment: String,
+    /// Save even though abuse filters warn about the change.
+    #[serde(default)]
+    acknowledge_warnings: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -236,12 +408,15 @@ pub async fn create_article(
     user_info: Option<UserInfo>,
     conn: DbConnection,
     data: Json<ArticleCreateRequest>,
+    rate_limit: RateLimit<Creations>,
 ) -> Result<HttpResponse, Error> {
-    use crate::models::{Actor, Article, Namespace, Role, User};
-    let (namespace, _) =
-        Namespace::parse_full_title(&conn, &data.full_title).map_err(ErrorInternalServerError)?;
+    use crate::models::{Article, Role};
+    let (namespace, title) =
+        Namespace::parse_full_title(&conn, &data.full_title).map_err(ApiError::from)?;
+    let is_bot = user_info.as_ref().map_or(false, UserInfo::is_bot);
     let actor = match user_info {
         Some(user_info) => {
+            user_info.require_scope(Scope::Edit)?;
             let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
             let can_create = user
                 .can_create(&conn, &namespace)
@@ -249,6 +424,14 @@ pub async fn create_article(
             if !can_create {
                 return Ok(HttpResponse::Forbidden().finish());
             }
+            check_protection(
+                &conn,
+                Some(&user),
+                &namespace,
+                &title,
+                ProtectionAction::Create,
+            )?;
+            check_block(&conn, Some(&user), &ip_address, &namespace)?;
             Actor::find_or_create_from_user_id(&conn, user_info.id)
                 .map_err(ErrorInternalServerError)?
         }
@@ -259,18 +442,50 @@ pub async fn create_article(
             if !can_create {
                 return Ok(HttpResponse::Forbidden().finish());
             }
+            check_protection(&conn, None, &namespace, &title, ProtectionAction::Create)?;
+            check_block(&conn, None, &ip_address, &namespace)?;
             Actor::find_or_create_from_ip(&conn, &ip_address).map_err(ErrorInternalServerError)?
         }
     };
-    let article = Article::create(
-        &conn,
-        &Namespace::default(),
-        &data.full_title,
-        &data.wikitext,
-        &data.comment,
-        &actor,
-    )
-    .map_err(ErrorInternalServerError)?;
+    if Article::find(&conn, &namespace, &title)
+        .map_err(ErrorInternalServerError)?
+        .is_some()
+    {
+        return Err(ApiError::Conflict(format!(
+            "Article {} already exists",
+            namespace.join(&title)
+        ))
+        .into());
+    }
+    rate_limit.hit()?;
+    let change = FilteredChange {
+        actor: &actor,
+        namespace: &namespace,
+        title: &title,
+        kind: RevisionKind::Create,
+        old_wikitext: "",
+        new_wikitext: &data.wikitext,
+        summary: &data.comment,
+        at: Utc::now().naive_utc(),
+    };
+    let tags = check_abuse_filters(&conn, &change, data.acknowledge_warnings)?;
+    let article = conn
+        .transaction::<_, anyhow::Error, _>(|| {
+            let article = Article::create(
+                &conn,
+                &namespace,
+                &title,
+                &data.wikitext,
+                &data.comment,
+                &actor,
+                is_bot,
+            )?;
+            let mut revision = article.get_latest_revision(&conn)?;
+            tag_revision(&conn, &revision, &tags)?;
+            autopatrol(&conn, &mut revision)?;
+            Ok(article)
+        })
+        .map_err(ErrorInternalServerError)?;
     let resp = Response {
         status: "OK".to_owned(),
         data: ArticleCreateResponse {
@@ -290,6 +505,12 @@ pub struct ArticleEditRequest {
     wikitext: String,
     #[validate(length(min = 0, max = 1000))]
     comment: String,
+    /// Respond with 409 instead of creating a revision when the wikitext is unchanged.
+    #[serde(default)]
+    reject_no_op: bool,
+    /// Save even though abuse filters warn about the change.
+    #[serde(default)]
+    acknowledge_warnings: bool,
 }
 
 pub type ArticleEditResponse = ArticleCreateResponse;
@@ -301,20 +522,19 @@ pub async fn edit_article(
     conn: DbConnection,
     web::Path((full_title,)): web::Path<(String,)>,
     data: Json<ArticleEditRequest>,
+    rate_limit: RateLimit<Edits>,
 ) -> Result<HttpResponse, Error> {
-    use crate::models::{Actor, Article, Role, User};
-
-    let mut article =
-        match Article::find_by_full_title(&conn, &full_title).map_err(ErrorInternalServerError)? {
-            Some(article) => article,
-            None => {
-                return Ok(HttpResponse::NotFound()
-                    .body(format!("No article found with full title: {}", &full_title)));
-            }
-        };
+    use crate::models::{Content, Role};
+
+    let mut article = find_article(&conn, &full_title)?;
+    let namespace = article
+        .get_namespace(&conn)
+        .map_err(ErrorInternalServerError)?;
 
+    let is_bot = user_info.as_ref().map_or(false, UserInfo::is_bot);
     let actor = match user_info {
         Some(user_info) => {
+            user_info.require_scope(Scope::Edit)?;
             let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
             let can_edit = user
                 .can_edit(&conn, &article)
@@ -322,6 +542,14 @@ pub async fn edit_article(
             if !can_edit {
                 return Ok(HttpResponse::Forbidden().finish());
             }
+            check_protection(
+                &conn,
+                Some(&user),
+                &namespace,
+                &article.title,
+                ProtectionAction::Edit,
+            )?;
+            check_block(&conn, Some(&user), &ip_address, &namespace)?;
             Actor::find_or_create_from_user_id(&conn, user_info.id)
                 .map_err(ErrorInternalServerError)?
         }
@@ -332,13 +560,46 @@ pub async fn edit_article(
             if !can_edit {
                 return Ok(HttpResponse::Forbidden().finish());
             }
+            check_protection(
+                &conn,
+                None,
+                &namespace,
+                &article.title,
+                ProtectionAction::Edit,
+            )?;
+            check_block(&conn, None, &ip_address, &namespace)?;
             Actor::find_or_create_from_ip(&conn, &ip_address).map_err(ErrorInternalServerError)?
         }
     };
 
-    let revision = article
-        .edit(&conn, &data.wikitext, &data.comment, &actor)
+    let latest_content = article
+        .get_latest_revision(&conn)
+        .and_then(|revision| revision.get_content(&conn))
+        .map_err(ErrorInternalServerError)?;
+    if data.reject_no_op && latest_content.sha256 == Content::hash(&data.wikitext) {
+        return Ok(HttpResponse::Conflict().body("Wikitext is not changed"));
+    }
+
+    rate_limit.hit()?;
+    let old_wikitext = latest_content
+        .get_wikitext(&conn)
         .map_err(ErrorInternalServerError)?;
+    let change = FilteredChange {
+        actor: &actor,
+        namespace: &namespace,
+        title: &article.title,
+        kind: RevisionKind::Edit,
+        old_wikitext: &old_wikitext,
+        new_wikitext: &data.wikitext,
+        summary: &data.comment,
+        at: Utc::now().naive_utc(),
+    };
+    let tags = check_abuse_filters(&conn, &change, data.acknowledge_warnings)?;
+
+    let revision = make_change(&conn, &tags, || {
+        article.edit(&conn, &data.wikitext, &data.comment, &actor, is_bot)
+    })
+    .map_err(ErrorInternalServerError)?;
 
     let resp = Response {
         status: "OK".to_owned(),
@@ -353,7 +614,7 @@ pub async fn edit_article(
 #[derive(Serialize, Deserialize, Validate, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ArticleRenameRequest {
-    #[validate(length(min = 1, max = 300))]
+    #[validate(length(min = 1, max = 300), custom = "validate_title")]
     full_title: String,
     #[validate(length(min = 0, max = 1000))]
     comment: String,
@@ -368,20 +629,26 @@ pub async fn rename_article(
     conn: DbConnection,
     web::Path((full_title,)): web::Path<(String,)>,
     data: Json<ArticleRenameRequest>,
+    rate_limit: RateLimit<Edits>,
 ) -> Result<HttpResponse, Error> {
-    use crate::models::{Actor, Article, Namespace, Role, User};
-    let mut article =
-        match Article::find_by_full_title(&conn, &full_title).map_err(ErrorInternalServerError)? {
-            Some(article) => article,
-            None => {
-                return Ok(HttpResponse::NotFound()
-                    .body(format!("No article found with full title: {}", &full_title)));
-            }
-        };
-    let (namespace, _) =
-        Namespace::parse_full_title(&conn, &data.full_title).map_err(ErrorInternalServerError)?;
+    use crate::models::{Article, Role};
+    let mut article = find_article(&conn, &full_title)?;
+    let source_namespace = article
+        .get_namespace(&conn)
+        .map_err(ErrorInternalServerError)?;
+    let (namespace, title) =
+        Namespace::parse_full_title(&conn, &data.full_title).map_err(ApiError::from)?;
+    if Article::find(&conn, &namespace, &title)
+        .map_err(ErrorInternalServerError)?
+        .is_some()
+    {
+        return Ok(HttpResponse::Conflict()
+            .body(format!("Article {} already exists", namespace.join(&title))));
+    }
+    let is_bot = user_info.as_ref().map_or(false, UserInfo::is_bot);
     let actor = match user_info {
         Some(user_info) => {
+            user_info.require_scope(Scope::Edit)?;
             let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
             let can_rename = user
                 .can_rename(&conn, &article)
@@ -392,6 +659,19 @@ pub async fn rename_article(
             if !can_rename || !can_create {
                 return Ok(HttpResponse::Forbidden().finish());
             }
+            for (namespace, title, action) in &[
+                (
+                    &source_namespace,
+                    article.title.as_str(),
+                    ProtectionAction::Rename,
+                ),
+                (&namespace, title.as_str(), ProtectionAction::Create),
+            ] {
+                check_protection(&conn, Some(&user), namespace, title, *action)?;
+            }
+            for namespace in &[&source_namespace, &namespace] {
+                check_block(&conn, Some(&user), &ip_address, namespace)?;
+            }
             Actor::find_or_create_from_user_id(&conn, user_info.id)
                 .map_err(ErrorInternalServerError)?
         }
@@ -406,12 +686,27 @@ pub async fn rename_article(
             if !can_rename || !can_create {
                 return Ok(HttpResponse::Forbidden().finish());
             }
+            for (namespace, title, action) in &[
+                (
+                    &source_namespace,
+                    article.title.as_str(),
+                    ProtectionAction::Rename,
+                ),
+                (&namespace, title.as_str(), ProtectionAction::Create),
+            ] {
+                check_protection(&conn, None, namespace, title, *action)?;
+            }
+            for namespace in &[&source_namespace, &namespace] {
+                check_block(&conn, None, &ip_address, namespace)?;
+            }
             Actor::find_or_create_from_ip(&conn, &ip_address).map_err(ErrorInternalServerError)?
         }
     };
-    let revision = article
-        .rename(&conn, &data.full_title, &data.comment, &actor)
-        .map_err(ErrorInternalServerError)?;
+    rate_limit.hit()?;
+    let revision = make_change(&conn, &[], || {
+        article.rename(&conn, &namespace, &title, &data.comment, &actor, is_bot)
+    })
+    .map_err(ErrorInternalServerError)?;
     let resp = Response {
         status: "OK".to_owned(),
         data: ArticleRenameResponse {
@@ -440,18 +735,17 @@ pub async fn delete_article(
     conn: DbConnection,
     web::Path((full_title,)): web::Path<(String,)>,
     data: Json<ArticleDeleteRequest>,
+    rate_limit: RateLimit<Edits>,
 ) -> Result<HttpResponse, Error> {
-    use crate::models::{Actor, Article, Role, User};
-    let mut article =
-        match Article::find_by_full_title(&conn, &full_title).map_err(ErrorInternalServerError)? {
-            Some(article) => article,
-            None => {
-                return Ok(HttpResponse::NotFound()
-                    .body(format!("No article found with full title: {}", &full_title)));
-            }
-        };
+    use crate::models::Role;
+    let mut article = find_article(&conn, &full_title)?;
+    let namespace = article
+        .get_namespace(&conn)
+        .map_err(ErrorInternalServerError)?;
+    let is_bot = user_info.as_ref().map_or(false, UserInfo::is_bot);
     let actor = match user_info {
         Some(user_info) => {
+            user_info.require_scope(Scope::Delete)?;
             let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
             let can_delete = user
                 .can_delete(&conn, &article)
@@ -459,6 +753,7 @@ pub async fn delete_article(
             if !can_delete {
                 return Ok(HttpResponse::Forbidden().finish());
             }
+            check_block(&conn, Some(&user), &ip_address, &namespace)?;
             Actor::find_or_create_from_user_id(&conn, user_info.id)
                 .map_err(ErrorInternalServerError)?
         }
@@ -469,12 +764,15 @@ pub async fn delete_article(
             if !can_delete {
                 return Ok(HttpResponse::Forbidden().finish());
             }
+            check_block(&conn, None, &ip_address, &namespace)?;
             Actor::find_or_create_from_ip(&conn, &ip_address).map_err(ErrorInternalServerError)?
         }
     };
-    let revision = article
-        .delete(&conn, &data.comment, &actor)
-        .map_err(ErrorInternalServerError)?;
+    rate_limit.hit()?;
+    let revision = make_change(&conn, &[], || {
+        article.delete(&conn, &data.comment, &actor, is_bot)
+    })
+    .map_err(ErrorInternalServerError)?;
     let resp = Response {
         status: "OK".to_owned(),
         data: ArticleDeleteResponse {
@@ -502,6 +800,7 @@ mod tests {
             full_title: "AA".to_owned(),
             wikitext: "==AA==\nasdf".to_owned(),
             comment: "Comment!".to_owned(),
+            acknowledge_warnings: false,
         };
         let req = test::TestRequest::post()
             .peer_addr("127.0.0.1:22342".parse().unwrap())
@@ -512,6 +811,32 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 201);
     }
 
+    #[actix_rt::test]
+    async fn test_create_existing_article() {
+        use crate::routes::test_helpers::create_article;
+
+        let pool = db::create_test_connection_pool();
+        {
+            let conn = pool.get().expect("must succeed");
+            create_article(&conn, "127.0.0.1", "existing", "asdf");
+        }
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(super::create_article)).await;
+        let data = ArticleCreateRequest {
+            full_title: "existing".to_owned(),
+            wikitext: "asdf".to_owned(),
+            comment: "Comment!".to_owned(),
+            acknowledge_warnings: false,
+        };
+        let req = test::TestRequest::post()
+            .peer_addr("127.0.0.1:22342".parse().unwrap())
+            .set_json(&data)
+            .uri("/articles")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 409);
+    }
+
     #[actix_rt::test]
     async fn test_create_article_validation() {
         let pool = db::create_connection_pool();
@@ -522,6 +847,21 @@ mod tests {
             full_title: "".to_owned(),
             wikitext: "==AA==\nasdf".to_owned(),
             comment: "Comment!".to_owned(),
+            acknowledge_warnings: false,
+        };
+        let req = test::TestRequest::post()
+            .peer_addr("127.0.0.1:22342".parse().unwrap())
+            .set_json(&data)
+            .uri("/articles")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let data = ArticleCreateRequest {
+            full_title: "[[AA]]".to_owned(),
+            wikitext: "==AA==\nasdf".to_owned(),
+            comment: "Comment!".to_owned(),
+            acknowledge_warnings: false,
         };
         let req = test::TestRequest::post()
             .peer_addr("127.0.0.1:22342".parse().unwrap())
@@ -530,6 +870,138 @@ mod tests {
             .to_request();
         let resp = test::call_service(&mut app, req).await;
         assert_eq!(resp.status().as_u16(), 400);
+
+        // Valid full titles whose title after the namespace is not
+        for full_title in &["Talk:", "Talk::AA"] {
+            let data = ArticleCreateRequest {
+                full_title: (*full_title).to_owned(),
+                wikitext: "==AA==\nasdf".to_owned(),
+                comment: "Comment!".to_owned(),
+                acknowledge_warnings: false,
+            };
+            let req = test::TestRequest::post()
+                .peer_addr("127.0.0.1:22342".parse().unwrap())
+                .set_json(&data)
+                .uri("/articles")
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status().as_u16(), 400);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_edit_renamed_protected_article() {
+        use crate::models::Role;
+        use crate::routes::test_helpers::create_article;
+        use std::str::FromStr;
+
+        let pool = db::create_test_connection_pool();
+        {
+            let conn = pool.get().expect("must succeed");
+            let mut article = create_article(&conn, "127.0.0.4", "protected", "asdf");
+            let namespace = Namespace::get_default(&conn).expect("must succeed");
+            let ip_address = IpNetwork::from_str("127.0.0.4").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            Protection::protect(
+                &conn,
+                &namespace,
+                "protected",
+                ProtectionAction::Edit,
+                &Role::root(),
+                "Vandalism",
+                &actor,
+                false,
+                None,
+            )
+            .expect("must succeed");
+            article
+                .rename(&conn, &namespace, "renamed", "Comment!", &actor, false)
+                .expect("must succeed");
+        }
+        let mut app = test::init_service(App::new().data(pool.clone()).service(edit_article)).await;
+        let data = ArticleEditRequest {
+            wikitext: "vandalism".to_owned(),
+            comment: "Comment!".to_owned(),
+            reject_no_op: false,
+            acknowledge_warnings: false,
+        };
+        let req = test::TestRequest::put()
+            .peer_addr("127.0.0.4:22342".parse().unwrap())
+            .set_json(&data)
+            .uri("/articles/renamed")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 403);
+    }
+
+    #[actix_rt::test]
+    async fn test_rejected_edits_are_not_rate_limited() {
+        use crate::models::Role;
+        use crate::rate_limit::{Limit, MemoryStore, RateLimitAction, RateLimiter};
+        use crate::routes::test_helpers::create_article;
+        use std::collections::HashMap;
+        use std::str::FromStr;
+
+        let pool = db::create_test_connection_pool();
+        {
+            let conn = pool.get().expect("must succeed");
+            create_article(&conn, "127.0.0.5", "limited protected", "asdf");
+            create_article(&conn, "127.0.0.5", "limited", "asdf");
+            let namespace = Namespace::get_default(&conn).expect("must succeed");
+            let ip_address = IpNetwork::from_str("127.0.0.5").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            Protection::protect(
+                &conn,
+                &namespace,
+                "limited protected",
+                ProtectionAction::Edit,
+                &Role::root(),
+                "Vandalism",
+                &actor,
+                false,
+                None,
+            )
+            .expect("must succeed");
+        }
+        let mut by_role = HashMap::new();
+        by_role.insert(
+            Role::anonymous().name,
+            Limit {
+                max: 1,
+                period: chrono::Duration::hours(1),
+            },
+        );
+        let mut limits = HashMap::new();
+        limits.insert(RateLimitAction::Edit, by_role);
+        let limiter = web::Data::new(RateLimiter::new(limits, Box::new(MemoryStore::default())));
+        let mut app = test::init_service(
+            App::new()
+                .data(pool.clone())
+                .app_data(limiter)
+                .service(edit_article),
+        )
+        .await;
+        let edit = |title: &str, wikitext: &str| {
+            test::TestRequest::put()
+                .peer_addr("127.0.0.5:22342".parse().unwrap())
+                .set_json(&ArticleEditRequest {
+                    wikitext: wikitext.to_owned(),
+                    comment: "Comment!".to_owned(),
+                    reject_no_op: false,
+                    acknowledge_warnings: false,
+                })
+                .uri(&format!("/articles/{}", title))
+                .to_request()
+        };
+        // Forbidden edits leave the single edit allowed
+        for _ in 0..2 {
+            let resp = test::call_service(&mut app, edit("limited%20protected", "a")).await;
+            assert_eq!(resp.status().as_u16(), 403);
+        }
+        let resp = test::call_service(&mut app, edit("limited", "b")).await;
+        assert!(resp.status().is_success());
+        let resp = test::call_service(&mut app, edit("limited", "c")).await;
+        assert_eq!(resp.status().as_u16(), 429);
     }
 
     #[actix_rt::test]
@@ -558,6 +1030,7 @@ mod tests {
             full_title: "title".to_owned(),
             wikitext: "==AA==\nasdf".to_owned(),
             comment: "Comment!".to_owned(),
+            acknowledge_warnings: false,
         };
         let req = test::TestRequest::post()
             .peer_addr("127.0.0.1:22342".parse().unwrap())
@@ -575,8 +1048,14 @@ mod tests {
             full_title,
             html,
             wikitext,
+            talk_page,
+            ..
         } = result.data;
         assert_eq!(full_title, "title");
+        assert_eq!(
+            talk_page.map(|talk| talk.full_title),
+            Some("Talk:title".to_owned())
+        );
         assert_eq!(html, Some("<h2>AA</h2>\n<p>asdf</p>".to_owned()));
         assert_eq!(wikitext.is_none(), true);
     }
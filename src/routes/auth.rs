@@ -1,45 +1,25 @@
+use super::blocks::blocked;
+use super::error::ApiError;
+use super::users::validate_username;
 use super::Response;
-use crate::extractors::DbConnection;
-use actix_web::{post, web, Error, HttpResponse};
+use crate::auth::keys::{Jwk, KEYS};
+use crate::auth::provider::{code_challenge, Identity, Providers};
+use crate::extractors::{ConnectionInfo, DbConnection, Logins, RateLimit, UserInfo};
+use crate::models::{
+    Authentication, Block, LoginAttempt, OauthState, Rotation, Session, TokenPurpose, User,
+    UserToken,
+};
+use actix_web::{
+    delete, error::ErrorInternalServerError, get, http::header, post, web, Error, HttpRequest,
+    HttpResponse,
+};
+use actix_web_validator::Json;
 use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use ipnetwork::IpNetwork;
 use serde::{Deserialize, Serialize};
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct FbAuthRequest {
-    access_token: String,
-    user_id: String,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct FbGraphResponse {
-    id: String,
-    name: String,
-    email: Option<String>,
-}
-
-fn token_refresh(refresh_token: &str) -> Result<String> {
-    use crate::auth::{decode, TokenClaims};
-    use chrono::{Duration, Utc};
-    use jsonwebtoken::{encode, EncodingKey, Header};
-    use std::env;
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let token = decode(&refresh_token)?;
-    let claims = TokenClaims::new(
-        token.claims.sub,
-        Utc::now(),
-        Utc::now() + Duration::hours(1),
-        "access",
-    );
-    let access_token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
-    .expect("JWT encoding failed");
-    Ok(access_token)
-}
+use validator::Validate;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -47,26 +27,52 @@ pub struct RefreshRequest {
     refresh_token: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct RefreshResponse {
-    access_token: String,
-}
-
+/// Trades a refresh token for a new access token and a new refresh token. The old refresh token
+/// stops working, and using it again signs out its session.
 #[post("/auth/refresh")]
-pub async fn refresh(refresh_request: web::Json<RefreshRequest>) -> Result<HttpResponse, Error> {
-    match token_refresh(&refresh_request.refresh_token) {
-        Ok(access_token) => {
+pub async fn refresh(
+    conn: DbConnection,
+    refresh_request: web::Json<RefreshRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = match crate::auth::decode(&refresh_request.refresh_token) {
+        Ok(token) if token.claims.t == "refresh" => token.claims,
+        _ => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let (session_id, token_id) = match (claims.sid, &claims.jti) {
+        (Some(session_id), Some(token_id)) => (session_id, token_id),
+        _ => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    match Session::rotate(&conn, session_id, token_id).map_err(ErrorInternalServerError)? {
+        Rotation::Rotated(session, refresh_token) => {
+            let access_token = crate::auth::issue_access_token(session.user_id, session.id)
+                .map_err(ErrorInternalServerError)?;
             let resp = Response {
                 status: "OK".to_owned(),
-                data: RefreshResponse { access_token },
+                data: AuthResponse {
+                    refresh_token,
+                    access_token,
+                },
             };
             Ok(HttpResponse::Ok().json(resp))
         }
-        Err(_) => Ok(HttpResponse::Unauthorized().finish()),
+        Rotation::Reused | Rotation::Invalid => Ok(HttpResponse::Unauthorized().finish()),
     }
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JwksGetResponse {
+    keys: Vec<Jwk>,
+}
+
+/// The public keys verifying issued tokens, in the standard JWK Set format rather than wrapped in
+/// a `Response`, so that other services can verify tokens with off-the-shelf libraries.
+#[get("/.well-known/jwks.json")]
+pub async fn get_jwks() -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(JwksGetResponse {
+        keys: KEYS.jwks().to_vec(),
+    }))
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct AuthResponse {
@@ -74,81 +80,435 @@ struct AuthResponse {
     access_token: String,
 }
 
-#[post("/auth/facebook")]
-pub async fn auth_facebook(
+/// Starts a session for `user` signing in with the request `req`.
+fn issue_tokens(
+    conn: &PgConnection,
+    user: &User,
+    req: &HttpRequest,
+    ip_address: &IpNetwork,
+) -> Result<AuthResponse> {
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok());
+    let (session, refresh_token) = Session::create(conn, user, user_agent, ip_address)?;
+    let access_token = crate::auth::issue_access_token(user.id, session.id)?;
+    Ok(AuthResponse {
+        refresh_token,
+        access_token,
+    })
+}
+
+/// Signs out the session of the access token.
+#[post("/auth/logout")]
+pub async fn logout(user_info: UserInfo, conn: DbConnection) -> Result<HttpResponse, Error> {
+    let session_id = match user_info.session_id {
+        Some(session_id) => session_id,
+        None => return Ok(HttpResponse::BadRequest().body("Token has no session")),
+    };
+    Session::revoke(&conn, user_info.id, session_id).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Signs out every session of the current user.
+#[post("/auth/logout-all")]
+pub async fn logout_all(user_info: UserInfo, conn: DbConnection) -> Result<HttpResponse, Error> {
+    user_info.require_session()?;
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    Session::revoke_all(&conn, &user).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionEntity {
+    id: i32,
+    user_agent: Option<String>,
+    ip_address: String,
+    created_at: NaiveDateTime,
+    last_used_at: NaiveDateTime,
+    expires_at: NaiveDateTime,
+    /// Whether the request was made with this session.
+    current: bool,
+}
+
+pub type SessionsGetResponse = Vec<SessionEntity>;
+
+#[get("/auth/sessions")]
+pub async fn get_sessions(user_info: UserInfo, conn: DbConnection) -> Result<HttpResponse, Error> {
+    user_info.require_session()?;
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    let data = Session::list_active(&conn, &user)
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(|session| SessionEntity {
+            id: session.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address.ip().to_string(),
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            expires_at: session.expires_at,
+            current: user_info.session_id == Some(session.id),
+        })
+        .collect::<SessionsGetResponse>();
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+/// Signs out another session of the current user.
+#[delete("/auth/sessions/{id}")]
+pub async fn delete_session(
+    user_info: UserInfo,
     conn: DbConnection,
-    fb_auth_request: web::Json<FbAuthRequest>,
+    web::Path((id,)): web::Path<(i32,)>,
 ) -> Result<HttpResponse, Error> {
-    use crate::models::{Role, User, UserFindResult};
-    use anyhow::Error;
-    use reqwest::Url;
-    let url = Url::parse(
-        format!(
-            "https://graph.facebook.com/me?fields=id,name,email&access_token={}",
-            fb_auth_request.access_token
-        )
-        .as_str(),
+    user_info.require_session()?;
+    if !Session::revoke(&conn, user_info.id, id).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::NotFound().body(format!("No session found: {}", id)));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn send_verification_email(conn: &PgConnection, user: &User) -> Result<()> {
+    let token = UserToken::issue(conn, user, TokenPurpose::VerifyEmail)?;
+    crate::mail::send(
+        &user.email,
+        "Verify your email address",
+        &format!("Use this token to verify your email address: {}", token),
     )
-    .unwrap();
-    let fb_resp = reqwest::get(url)
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterRequest {
+    #[validate(length(min = 1, max = 50), custom = "validate_username")]
+    username: String,
+    #[validate(email, length(max = 255))]
+    email: String,
+    #[validate(length(min = 8, max = 128))]
+    password: String,
+}
+
+#[post("/auth/register")]
+pub async fn register(
+    req: HttpRequest,
+    ConnectionInfo { ip_address }: ConnectionInfo,
+    conn: DbConnection,
+    data: Json<RegisterRequest>,
+) -> Result<HttpResponse, Error> {
+    if let Some(block) = Block::find_blocking_account_creation(&conn, &ip_address)
+        .map_err(ErrorInternalServerError)?
+    {
+        return Err(blocked(&conn, &block).into());
+    }
+    if User::find_by_username(&conn, &data.username)
+        .map_err(ErrorInternalServerError)?
+        .is_some()
+    {
+        return Ok(HttpResponse::Conflict().body(format!("User {} already exists", &data.username)));
+    }
+    if User::find_by_username_or_email(&conn, &data.email)
+        .map_err(ErrorInternalServerError)?
+        .is_some()
+    {
+        return Ok(HttpResponse::Conflict().body("Email is already in use"));
+    }
+    let user = User::register(&conn, &data.email, &data.username, &data.password)
+        .map_err(ErrorInternalServerError)?;
+    send_verification_email(&conn, &user).map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: issue_tokens(&conn, &user, &req, &ip_address).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Created().json(resp))
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginRequest {
+    /// Username or email.
+    #[validate(length(min = 1, max = 255))]
+    username: String,
+    #[validate(length(min = 1, max = 128))]
+    password: String,
+}
+
+#[post("/auth/login")]
+pub async fn login(
+    req: HttpRequest,
+    ConnectionInfo { ip_address }: ConnectionInfo,
+    conn: DbConnection,
+    data: Json<LoginRequest>,
+    rate_limit: RateLimit<Logins>,
+) -> Result<HttpResponse, Error> {
+    rate_limit.hit()?;
+    if LoginAttempt::is_throttled(&conn, &data.username, &ip_address)
+        .map_err(ErrorInternalServerError)?
+    {
+        return Ok(HttpResponse::TooManyRequests().body("Too many failed logins, try again later"));
+    }
+    let user =
+        User::find_by_username_or_email(&conn, &data.username).map_err(ErrorInternalServerError)?;
+    let verified = match &user {
+        Some(user) => user.verify_password(&conn, &data.password),
+        None => crate::auth::verify_no_password(&data.password),
+    }
+    .map_err(ErrorInternalServerError)?;
+    let user = match user {
+        Some(user) if verified => user,
+        _ => {
+            LoginAttempt::record(&conn, &data.username, &ip_address, false)
+                .map_err(ErrorInternalServerError)?;
+            return Ok(HttpResponse::Unauthorized().finish());
+        }
+    };
+    LoginAttempt::record(&conn, &data.username, &ip_address, true)
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: issue_tokens(&conn, &user, &req, &ip_address).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailVerificationRequest {
+    token: String,
+}
+
+#[post("/auth/verify-email")]
+pub async fn verify_email(
+    conn: DbConnection,
+    data: Json<EmailVerificationRequest>,
+) -> Result<HttpResponse, Error> {
+    let user = UserToken::consume(&conn, &data.token, TokenPurpose::VerifyEmail)
+        .map_err(ErrorInternalServerError)?;
+    match user {
+        Some(mut user) => {
+            user.verify_email(&conn).map_err(ErrorInternalServerError)?;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        None => Ok(HttpResponse::BadRequest().body("Invalid or expired token")),
+    }
+}
+
+#[post("/auth/verify-email/resend")]
+pub async fn resend_verification_email(
+    user_info: UserInfo,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    if user.is_email_verified() {
+        return Ok(HttpResponse::Conflict().body("Email is already verified"));
+    }
+    send_verification_email(&conn, &user).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordResetRequest {
+    #[validate(email)]
+    email: String,
+}
+
+/// Mails a password reset token. Responds the same whether or not the email is known.
+#[post("/auth/password-reset")]
+pub async fn request_password_reset(
+    conn: DbConnection,
+    data: Json<PasswordResetRequest>,
+) -> Result<HttpResponse, Error> {
+    let user =
+        User::find_by_username_or_email(&conn, &data.email).map_err(ErrorInternalServerError)?;
+    if let Some(user) = user {
+        let token = UserToken::issue(&conn, &user, TokenPurpose::ResetPassword)
+            .map_err(ErrorInternalServerError)?;
+        // A failure must not tell that the email is known
+        if let Err(e) = crate::mail::send(
+            &user.email,
+            "Reset your password",
+            &format!("Use this token to reset your password: {}", token),
+        ) {
+            log::error!("Failed to mail a password reset token: {}", e);
+        }
+    }
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordResetConfirmRequest {
+    token: String,
+    #[validate(length(min = 8, max = 128))]
+    password: String,
+}
+
+#[post("/auth/password-reset/confirm")]
+pub async fn confirm_password_reset(
+    conn: DbConnection,
+    data: Json<PasswordResetConfirmRequest>,
+) -> Result<HttpResponse, Error> {
+    let user = UserToken::consume(&conn, &data.token, TokenPurpose::ResetPassword)
+        .map_err(ErrorInternalServerError)?;
+    let mut user = match user {
+        Some(user) => user,
+        None => return Ok(HttpResponse::BadRequest().body("Invalid or expired token")),
+    };
+    user.set_password(&conn, &data.password)
+        .map_err(ErrorInternalServerError)?;
+    // Whoever knew the old password is signed out
+    Session::revoke_all(&conn, &user).map_err(ErrorInternalServerError)?;
+    // The token was mailed, so the address works
+    if !user.is_email_verified() {
+        user.verify_email(&conn).map_err(ErrorInternalServerError)?;
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub type ProvidersGetResponse = Vec<String>;
+
+#[get("/auth/providers")]
+pub async fn get_providers(providers: web::Data<Providers>) -> Result<HttpResponse, Error> {
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: providers
+            .names()
+            .iter()
+            .map(|name| (*name).to_owned())
+            .collect::<ProvidersGetResponse>(),
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizeResponse {
+    /// Where to send the user to sign in.
+    authorization_url: String,
+}
+
+/// Starts an authorization with the provider `name`, to link it to `user_id` if given or else to
+/// sign in.
+async fn start_authorization(
+    conn: &PgConnection,
+    providers: &Providers,
+    name: &str,
+    user_id: Option<i32>,
+) -> Result<HttpResponse, Error> {
+    let provider = match providers.get(name) {
+        Some(provider) => provider,
+        None => {
+            return Ok(HttpResponse::NotFound().body(format!("No provider found: {}", name)));
+        }
+    };
+    let state = OauthState::create(conn, name, user_id).map_err(ErrorInternalServerError)?;
+    let authorization_url = provider
+        .authorization_url(&state.state, &code_challenge(&state.code_verifier))
         .await
-        .map_err(|e| {
-            eprintln!("{}", e);
-            HttpResponse::Unauthorized().finish()
-        })?
-        .json::<FbGraphResponse>()
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: AuthorizeResponse {
+            authorization_url: authorization_url.to_string(),
+        },
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+/// Completes an authorization started by `start_authorization` for the same `user_id`.
+async fn complete_authorization(
+    conn: &PgConnection,
+    providers: &Providers,
+    name: &str,
+    data: &ProviderCallbackRequest,
+    user_id: Option<i32>,
+) -> Result<Identity, ApiError> {
+    let provider = providers
+        .get(name)
+        .ok_or_else(|| ApiError::NotFound(format!("No provider found: {}", name)))?;
+    let state = match OauthState::take(conn, name, &data.state)? {
+        Some(state) if state.user_id == user_id => state,
+        _ => return Err(ApiError::BadRequest("Invalid or expired state".to_owned())),
+    };
+    provider
+        .exchange(&data.code, &state.code_verifier)
         .await
-        .map_err(|e| {
-            eprintln!("{}", e);
-            HttpResponse::Unauthorized().finish()
-        })?;
-    let email = match fb_resp.email {
+        .map_err(|e| ApiError::Unauthorized(format!("Provider rejected the code: {}", e)))
+}
+
+/// Starts signing in with a provider.
+#[post("/auth/providers/{provider}/authorize")]
+pub async fn authorize(
+    conn: DbConnection,
+    providers: web::Data<Providers>,
+    web::Path((name,)): web::Path<(String,)>,
+) -> Result<HttpResponse, Error> {
+    start_authorization(&conn, &providers, &name, None).await
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderCallbackRequest {
+    code: String,
+    state: String,
+}
+
+/// Finishes signing in with the code and state the provider redirected back with, creating the
+/// user on first sign in.
+#[post("/auth/providers/{provider}/callback")]
+pub async fn provider_callback(
+    req: HttpRequest,
+    ConnectionInfo { ip_address }: ConnectionInfo,
+    conn: DbConnection,
+    providers: web::Data<Providers>,
+    web::Path((name,)): web::Path<(String,)>,
+    data: Json<ProviderCallbackRequest>,
+) -> Result<HttpResponse, Error> {
+    use crate::models::{Role, UserFindResult};
+    let identity = complete_authorization(&conn, &providers, &name, &data, None).await?;
+    let email = match &identity.email {
         Some(email) => email,
-        None => {
-            return Ok(HttpResponse::Unauthorized().finish());
-        }
+        None => return Ok(HttpResponse::BadRequest().body("Provider did not return an email")),
     };
-    if fb_resp.id != fb_auth_request.user_id {
-        return Ok(HttpResponse::Unauthorized().finish());
-    }
-    let name = fb_resp.name;
-    let mut is_new_user: bool = false;
-    let refresh_token: String = conn
-        .transaction::<_, Error, _>(|| {
-            let user_find_result =
-                User::find_by_provider(&conn, &email, "facebook", &fb_auth_request.user_id)?;
-            let refresh_token = match user_find_result {
-                UserFindResult::Exists(user) => {
-                    is_new_user = false;
-                    user.issue_refresh_token()
-                }
-                UserFindResult::WrongProvider(_) => {
-                    return Err(anyhow!("User with same email already exists"));
-                }
-                UserFindResult::NotExists => {
-                    is_new_user = true;
-                    let user = User::create(&conn, &email, &name)?;
-                    user.add_authentication(&conn, "facebook", &fb_auth_request.user_id)?;
+    let user_find_result = User::find_by_provider(&conn, email, &name, &identity.subject)
+        .map_err(ErrorInternalServerError)?;
+    let (user, is_new_user) = match user_find_result {
+        UserFindResult::Exists(user) => (user, false),
+        UserFindResult::WrongProvider(_) => {
+            return Ok(HttpResponse::Conflict().body(
+                "User with same email already exists. Log in another way and link this provider",
+            ));
+        }
+        UserFindResult::NotExists => {
+            if let Some(block) = Block::find_blocking_account_creation(&conn, &ip_address)
+                .map_err(ErrorInternalServerError)?
+            {
+                return Err(blocked(&conn, &block).into());
+            }
+            let name_claim = identity.name.as_deref().unwrap_or("");
+            let user = conn
+                .transaction::<_, anyhow::Error, _>(|| {
+                    let username = User::available_username(&conn, name_claim, email)?;
+                    let mut user = User::create(&conn, email, &username)?;
+                    user.add_authentication(&conn, &name, &identity.subject)?
+                        .ok_or_else(|| anyhow!("Account is linked to another user"))?;
                     user.add_role(&conn, &Role::logged_in())?;
-                    user.issue_refresh_token()
-                }
-            };
-            Ok(refresh_token)
-        })
-        .map_err(|e| {
-            eprintln!("{}", e);
-            HttpResponse::Unauthorized().finish()
-        })?;
-    let access_token = token_refresh(&refresh_token).map_err(|e| {
-        eprintln!("{}", e);
-        HttpResponse::Unauthorized().finish()
-    })?;
+                    if identity.email_verified {
+                        user.verify_email(&conn)?;
+                    }
+                    Ok(user)
+                })
+                .map_err(ErrorInternalServerError)?;
+            (user, true)
+        }
+    };
     let resp = Response {
         status: "OK".to_owned(),
-        data: AuthResponse {
-            refresh_token,
-            access_token,
-        },
+        data: issue_tokens(&conn, &user, &req, &ip_address).map_err(ErrorInternalServerError)?,
     };
     if is_new_user {
         Ok(HttpResponse::Created().json(resp))
@@ -157,44 +517,360 @@ pub async fn auth_facebook(
     }
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkedProviderEntity {
+    provider: String,
+    created_at: NaiveDateTime,
+}
+
+pub type LinkedProvidersGetResponse = Vec<LinkedProviderEntity>;
+
+#[get("/me/providers")]
+pub async fn get_linked_providers(
+    user_info: UserInfo,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    user_info.require_session()?;
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    let data = user
+        .get_authentications(&conn)
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(|auth| LinkedProviderEntity {
+            provider: auth.provider,
+            created_at: auth.created_at,
+        })
+        .collect::<LinkedProvidersGetResponse>();
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+/// Starts linking a provider to the current user.
+#[post("/me/providers/{provider}/authorize")]
+pub async fn authorize_link(
+    user_info: UserInfo,
+    conn: DbConnection,
+    providers: web::Data<Providers>,
+    web::Path((name,)): web::Path<(String,)>,
+) -> Result<HttpResponse, Error> {
+    user_info.require_session()?;
+    start_authorization(&conn, &providers, &name, Some(user_info.id)).await
+}
+
+/// Finishes linking a provider to the current user.
+#[post("/me/providers/{provider}/callback")]
+pub async fn link_provider_callback(
+    user_info: UserInfo,
+    conn: DbConnection,
+    providers: web::Data<Providers>,
+    web::Path((name,)): web::Path<(String,)>,
+    data: Json<ProviderCallbackRequest>,
+) -> Result<HttpResponse, Error> {
+    user_info.require_session()?;
+    let identity =
+        complete_authorization(&conn, &providers, &name, &data, Some(user_info.id)).await?;
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    if let Some(auth) =
+        Authentication::find(&conn, &name, &identity.subject).map_err(ErrorInternalServerError)?
+    {
+        if auth.user_id == user.id {
+            return Ok(HttpResponse::NoContent().finish());
+        }
+        return Ok(HttpResponse::Conflict().body("Account is linked to another user"));
+    }
+    let auths = user
+        .get_authentications(&conn)
+        .map_err(ErrorInternalServerError)?;
+    if auths.iter().any(|auth| auth.provider == name) {
+        return Ok(HttpResponse::Conflict().body(format!("Another {} account is linked", &name)));
+    }
+    // Someone else may have linked the account in the meantime
+    let auth = user
+        .add_authentication(&conn, &name, &identity.subject)
+        .map_err(ErrorInternalServerError)?;
+    if auth.is_none() {
+        return Ok(HttpResponse::Conflict().body("Account is linked to another user"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/me/providers/{provider}")]
+pub async fn unlink_provider(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((name,)): web::Path<(String,)>,
+) -> Result<HttpResponse, Error> {
+    user_info.require_session()?;
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    let auths = user
+        .get_authentications(&conn)
+        .map_err(ErrorInternalServerError)?;
+    if !auths.iter().any(|auth| auth.provider == name) {
+        return Ok(HttpResponse::NotFound().body(format!("{} is not linked", &name)));
+    }
+    if auths.len() == 1 {
+        return Ok(HttpResponse::BadRequest().body("The last provider cannot be unlinked"));
+    }
+    user.remove_authentication(&conn, &name)
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::db;
     use actix_web::{test, App};
     use dotenv::dotenv;
-    use std::env;
+    use std::collections::HashMap;
 
     #[actix_rt::test]
-    async fn test_auth_facebook() {
+    async fn test_register_and_login() {
         dotenv().ok();
         let pool = db::create_connection_pool();
-        let fb_test_user_id = env::var("FB_TEST_USER_ID").expect("FB_TEST_USER_ID must be set");
-        let fb_test_user_access_token =
-            env::var("FB_TEST_USER_ACCESS_TOKEN").expect("FB_TEST_USER_ACCESS_TOKEN must be set");
         let mut app = test::init_service(
             App::new()
                 .data(pool.clone())
-                .service(auth_facebook)
-                .service(refresh),
+                .service(register)
+                .service(login),
         )
         .await;
-        let data = FbAuthRequest {
-            access_token: fb_test_user_access_token,
-            user_id: fb_test_user_id,
+        let username = format!("tester{}", &crate::auth::generate_token()[..10]);
+        let data = RegisterRequest {
+            username: username.clone(),
+            email: format!("{}@example.com", &username),
+            password: "password1".to_owned(),
+        };
+        let req = test::TestRequest::post()
+            .peer_addr("127.0.0.1:22342".parse().unwrap())
+            .set_json(&data)
+            .uri("/auth/register")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 201);
+        let data = LoginRequest {
+            username: username.clone(),
+            password: "wrong password".to_owned(),
+        };
+        let req = test::TestRequest::post()
+            .peer_addr("127.0.0.1:22342".parse().unwrap())
+            .set_json(&data)
+            .uri("/auth/login")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let data = LoginRequest {
+            username,
+            password: "password1".to_owned(),
         };
         let req = test::TestRequest::post()
+            .peer_addr("127.0.0.1:22342".parse().unwrap())
             .set_json(&data)
-            .uri("/auth/facebook")
+            .uri("/auth/login")
             .to_request();
         let result: Response<AuthResponse> = test::read_response_json(&mut app, req).await;
-        let AuthResponse { refresh_token, .. } = result.data;
-        let data = RefreshRequest { refresh_token };
+        assert_eq!(result.status, "OK");
+    }
+
+    #[actix_rt::test]
+    async fn test_get_linked_providers_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(get_linked_providers)).await;
+        let req = test::TestRequest::get().uri("/me/providers").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_sessions_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(App::new().data(pool.clone()).service(get_sessions)).await;
+        let req = test::TestRequest::get().uri("/auth/sessions").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    mod mock_issuer {
+        use actix_web::{get, post, web, HttpRequest, HttpResponse};
+        use serde_json::json;
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+
+        pub struct MockState {
+            pub subject: String,
+            /// The PKCE challenge sent with the authorization URL.
+            pub code_challenge: Mutex<Option<String>>,
+        }
+
+        fn base_url(req: &HttpRequest) -> String {
+            let info = req.connection_info();
+            format!("{}://{}", info.scheme(), info.host())
+        }
+
+        #[get("/.well-known/openid-configuration")]
+        pub async fn discovery(req: HttpRequest) -> HttpResponse {
+            let base_url = base_url(&req);
+            HttpResponse::Ok().json(json!({
+                "issuer": &base_url,
+                "authorization_endpoint": format!("{}/authorize", &base_url),
+                "token_endpoint": format!("{}/token", &base_url),
+                "userinfo_endpoint": format!("{}/userinfo", &base_url),
+            }))
+        }
+
+        #[post("/token")]
+        pub async fn token(
+            state: web::Data<MockState>,
+            form: web::Form<HashMap<String, String>>,
+        ) -> HttpResponse {
+            let code_challenge = form
+                .get("code_verifier")
+                .map(|verifier| crate::auth::provider::code_challenge(verifier));
+            if form.get("code").map(String::as_str) != Some("mock-code")
+                || code_challenge != *state.code_challenge.lock().unwrap()
+            {
+                return HttpResponse::BadRequest().json(json!({"error": "invalid_grant"}));
+            }
+            HttpResponse::Ok().json(json!({
+                "access_token": "mock-access-token",
+                "token_type": "Bearer",
+            }))
+        }
+
+        #[get("/userinfo")]
+        pub async fn userinfo(state: web::Data<MockState>, req: HttpRequest) -> HttpResponse {
+            let authorization = req
+                .headers()
+                .get("Authorization")
+                .and_then(|value| value.to_str().ok());
+            if authorization != Some("Bearer mock-access-token") {
+                return HttpResponse::Unauthorized().finish();
+            }
+            HttpResponse::Ok().json(json!({
+                "sub": &state.subject,
+                "email": format!("{}@example.com", &state.subject),
+                "email_verified": true,
+                "name": "Mock User",
+            }))
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_provider_flow() {
+        use crate::auth::provider::{ClaimMapping, OAuthConfig, OAuthProvider, Provider};
+        use mock_issuer::MockState;
+        use reqwest::Url;
+        use std::sync::Mutex;
+        dotenv().ok();
+        let state = web::Data::new(MockState {
+            subject: format!("mock{}", &crate::auth::generate_token()[..10]),
+            code_challenge: Mutex::new(None),
+        });
+        let issuer_state = state.clone();
+        let issuer = test::start(move || {
+            App::new()
+                .app_data(issuer_state.clone())
+                .service(mock_issuer::discovery)
+                .service(mock_issuer::token)
+                .service(mock_issuer::userinfo)
+        });
+        let provider = OAuthProvider::new(OAuthConfig {
+            name: "mock".to_owned(),
+            client_id: "client".to_owned(),
+            client_secret: "secret".to_owned(),
+            redirect_uri: "http://localhost/callback".to_owned(),
+            issuer: Some(issuer.url("")),
+            authorization_endpoint: None,
+            token_endpoint: None,
+            userinfo_endpoint: None,
+            scopes: "openid email profile".to_owned(),
+            claims: ClaimMapping::default(),
+        });
+        let providers = Providers::new(vec![Box::new(provider) as Box<dyn Provider>]);
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool.clone())
+                .data(providers)
+                .service(authorize)
+                .service(provider_callback)
+                .service(refresh),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/auth/providers/mock/authorize")
+            .to_request();
+        let result: Response<AuthorizeResponse> = test::read_response_json(&mut app, req).await;
+        let url = Url::parse(&result.data.authorization_url).expect("must succeed");
+        let params = url.query_pairs().collect::<HashMap<_, _>>();
+        assert_eq!(params["code_challenge_method"], "S256");
+        *state.code_challenge.lock().unwrap() = Some(params["code_challenge"].to_string());
+
+        let data = ProviderCallbackRequest {
+            code: "mock-code".to_owned(),
+            state: params["state"].to_string(),
+        };
+        let req = test::TestRequest::post()
+            .peer_addr("127.0.0.1:22342".parse().unwrap())
+            .set_json(&data)
+            .uri("/auth/providers/mock/callback")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 201);
+        let result: Response<AuthResponse> = test::read_body_json(resp).await;
+
+        // A state can only be used once
+        let req = test::TestRequest::post()
+            .peer_addr("127.0.0.1:22342".parse().unwrap())
+            .set_json(&data)
+            .uri("/auth/providers/mock/callback")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        // Access tokens are not refresh tokens
+        let data = RefreshRequest {
+            refresh_token: result.data.access_token,
+        };
         let req = test::TestRequest::post()
             .set_json(&data)
             .uri("/auth/refresh")
             .to_request();
-        let result: Response<RefreshResponse> = test::read_response_json(&mut app, req).await;
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let first = RefreshRequest {
+            refresh_token: result.data.refresh_token,
+        };
+        let req = test::TestRequest::post()
+            .set_json(&first)
+            .uri("/auth/refresh")
+            .to_request();
+        let result: Response<AuthResponse> = test::read_response_json(&mut app, req).await;
         assert_eq!(result.status, "OK");
+        assert_ne!(result.data.refresh_token, first.refresh_token);
+
+        // Reusing a rotated token signs out the session, so the new token stops working too
+        let req = test::TestRequest::post()
+            .set_json(&first)
+            .uri("/auth/refresh")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let data = RefreshRequest {
+            refresh_token: result.data.refresh_token,
+        };
+        let req = test::TestRequest::post()
+            .set_json(&data)
+            .uri("/auth/refresh")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
     }
 }
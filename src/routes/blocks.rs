@@ -0,0 +1,279 @@
+use super::articles::ActorEntity;
+use super::error::ApiError;
+use super::helpers::{find_user, has_right};
+use super::Response;
+use crate::extractors::{DbConnection, Query, UserInfo};
+use crate::models::{Actor, Block, BlockTarget, Namespace, Right, Scope};
+use actix_web::{
+    delete, error::ErrorInternalServerError, get, http::StatusCode, post, web, Error, HttpResponse,
+};
+use actix_web_validator::Json;
+use anyhow::{anyhow, Result};
+use chrono::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use validator::Validate;
+
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BlocksQuery {
+    pub offset: i64,
+    pub limit: i64,
+}
+
+impl Default for BlocksQuery {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            limit: 50,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum BlockTargetEntity {
+    #[serde(rename_all = "camelCase")]
+    User { username: String },
+    #[serde(rename_all = "camelCase")]
+    Address { ip_address: String },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockEntity {
+    id: i32,
+    target: BlockTargetEntity,
+    reason: String,
+    blocked_by: ActorEntity,
+    /// Names of the namespaces edits are blocked in, or `None` for every namespace.
+    namespaces: Option<Vec<String>>,
+    prevent_account_creation: bool,
+    expires_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+impl BlockEntity {
+    pub fn from_block(conn: &PgConnection, block: &Block) -> Result<Self> {
+        let target = match (block.get_user(conn)?, block.ip_address) {
+            (Some(user), _) => BlockTargetEntity::User {
+                username: user.username,
+            },
+            (None, Some(ip_address)) => BlockTargetEntity::Address {
+                ip_address: ip_address.to_string(),
+            },
+            (None, None) => return Err(anyhow!("Both user_id and ip_address are null.")),
+        };
+        let namespaces = match &block.namespace_ids {
+            Some(namespace_ids) => {
+                let mut namespaces = vec![];
+                for namespace_id in namespace_ids.iter() {
+                    if let Some(namespace) = Namespace::find_by_id(conn, *namespace_id)? {
+                        namespaces.push(namespace.name);
+                    }
+                }
+                Some(namespaces)
+            }
+            None => None,
+        };
+        Ok(Self {
+            id: block.id,
+            target,
+            reason: block.reason.clone(),
+            blocked_by: ActorEntity::from_actor(conn, &block.get_actor(conn)?)?,
+            namespaces,
+            prevent_account_creation: block.prevent_account_creation,
+            expires_at: block.expires_at,
+            created_at: block.created_at,
+        })
+    }
+}
+
+pub type BlocksGetResponse = Vec<BlockEntity>;
+
+/// The 403 telling a blocked user why and until when they are blocked.
+pub fn blocked(conn: &PgConnection, block: &Block) -> ApiError {
+    match BlockEntity::from_block(conn, block) {
+        Ok(entity) => ApiError::rejected(StatusCode::FORBIDDEN, "Blocked", entity),
+        Err(e) => ApiError::Internal(e),
+    }
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockCreateRequest {
+    /// Either `username` or `ip_address` must be given.
+    username: Option<String>,
+    /// A single address or a range such as `192.0.2.0/24`.
+    ip_address: Option<String>,
+    #[validate(length(min = 0, max = 1000))]
+    reason: String,
+    /// Names of the namespaces to block edits in. Every namespace when missing.
+    namespaces: Option<Vec<String>>,
+    /// Only blocks of addresses can stop accounts from being created.
+    #[serde(default)]
+    prevent_account_creation: bool,
+    /// The block is lifted at this time.
+    expires_at: Option<NaiveDateTime>,
+}
+
+/// `Root` and roles with `Right::Block` may block and unblock.
+fn can_block(conn: &PgConnection, user_info: &UserInfo) -> Result<bool> {
+    has_right(conn, user_info, Scope::Admin, Right::Block)
+}
+
+#[get("/blocks")]
+pub async fn get_blocks(
+    query: Option<Query<BlocksQuery>>,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    let query = query.unwrap_or_default();
+    let data = Block::list_active(
+        &conn,
+        query.offset.max(0),
+        query.limit.max(1).min(MAX_LIMIT),
+    )
+    .and_then(|blocks| {
+        blocks
+            .iter()
+            .map(|block| BlockEntity::from_block(&conn, block))
+            .collect::<Result<BlocksGetResponse>>()
+    })
+    .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[post("/blocks")]
+pub async fn create_block(
+    user_info: UserInfo,
+    conn: DbConnection,
+    data: Json<BlockCreateRequest>,
+) -> Result<HttpResponse, Error> {
+    if !can_block(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if matches!(data.expires_at, Some(expires_at) if expires_at <= Utc::now().naive_utc()) {
+        return Ok(HttpResponse::BadRequest().body("Expiry must be in the future"));
+    }
+    let user = match &data.username {
+        Some(username) => Some(find_user(&conn, username)?),
+        None => None,
+    };
+    let ip_address = match &data.ip_address {
+        Some(address) => match IpNetwork::from_str(address) {
+            Ok(ip_address) => Some(ip_address),
+            Err(_) => {
+                return Ok(
+                    HttpResponse::BadRequest().body(format!("Invalid IP address: {}", address))
+                );
+            }
+        },
+        None => None,
+    };
+    let target = match (&user, &ip_address) {
+        (Some(user), None) => BlockTarget::User(user),
+        (None, Some(ip_address)) => BlockTarget::Address(ip_address),
+        _ => {
+            return Ok(HttpResponse::BadRequest()
+                .body("Exactly one of username and ipAddress is required"));
+        }
+    };
+    if data.prevent_account_creation && user.is_some() {
+        return Ok(HttpResponse::BadRequest().body("Only addresses can be blocked from signing up"));
+    }
+    let namespaces = match &data.namespaces {
+        Some(names) => {
+            let mut namespaces = vec![];
+            for name in names.iter() {
+                match Namespace::find_by_name(&conn, name).map_err(ErrorInternalServerError)? {
+                    Some(namespace) => namespaces.push(namespace),
+                    None => {
+                        return Ok(HttpResponse::BadRequest()
+                            .body(format!("No namespace found with name: {}", name)));
+                    }
+                }
+            }
+            Some(namespaces)
+        }
+        None => None,
+    };
+    let actor = Actor::find_or_create_from_user_id(&conn, user_info.id)
+        .map_err(ErrorInternalServerError)?;
+    let block = Block::create(
+        &conn,
+        target,
+        &data.reason,
+        &actor,
+        namespaces.as_deref(),
+        data.prevent_account_creation,
+        data.expires_at,
+    )
+    .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: BlockEntity::from_block(&conn, &block).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Created().json(resp))
+}
+
+#[delete("/blocks/{id}")]
+pub async fn delete_block(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((id,)): web::Path<(i32,)>,
+) -> Result<HttpResponse, Error> {
+    if !can_block(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if !Block::unblock(&conn, id).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::NotFound().body(format!("No block found with id: {}", id)));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_get_blocks() {
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(App::new().data(pool.clone()).service(get_blocks)).await;
+        let req = test::TestRequest::get()
+            .uri("/blocks?limit=10")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[actix_rt::test]
+    async fn test_create_block_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(App::new().data(pool.clone()).service(create_block)).await;
+        let data = BlockCreateRequest {
+            username: None,
+            ip_address: Some("192.0.2.0/24".to_owned()),
+            reason: "Vandalism".to_owned(),
+            namespaces: None,
+            prevent_account_creation: true,
+            expires_at: None,
+        };
+        let req = test::TestRequest::post()
+            .set_json(&data)
+            .uri("/blocks")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+}
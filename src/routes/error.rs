@@ -0,0 +1,132 @@
+use super::Response;
+use crate::models::NamespaceError;
+use crate::title::TitleError;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
+use std::fmt;
+
+/// Why a request failed, turned into the response by actix.
+///
+/// Errors of the models convert into `Internal`, except the ones caused by the request such as
+/// an invalid title, which convert into the matching client error.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    /// Responds with a `Response` whose status is `label`, for rejections the client reads.
+    Rejected {
+        status: StatusCode,
+        label: &'static str,
+        data: serde_json::Value,
+    },
+    Internal(anyhow::Error),
+}
+
+impl ApiError {
+    pub fn rejected(status: StatusCode, label: &'static str, data: impl Serialize) -> Self {
+        match serde_json::to_value(data) {
+            Ok(data) => Self::Rejected {
+                status,
+                label,
+                data,
+            },
+            Err(e) => Self::Internal(e.into()),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadRequest(message)
+            | Self::Unauthorized(message)
+            | Self::Forbidden(message)
+            | Self::NotFound(message)
+            | Self::Conflict(message) => write!(f, "{}", message),
+            Self::Rejected { label, .. } => write!(f, "{}", label),
+            Self::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Rejected { status, .. } => *status,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::Rejected { label, data, .. } => {
+                HttpResponse::build(self.status_code()).json(Response {
+                    status: (*label).to_owned(),
+                    data,
+                })
+            }
+            Self::Internal(_) => HttpResponse::InternalServerError().finish(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        if let Some(e) = e.downcast_ref::<TitleError>() {
+            return Self::BadRequest(e.to_string());
+        }
+        match e.downcast_ref::<NamespaceError>() {
+            Some(NamespaceError::Exists(_)) | Some(NamespaceError::NotEmpty(_)) => {
+                Self::Conflict(e.to_string())
+            }
+            Some(NamespaceError::Default(_)) | Some(NamespaceError::Talk(_)) => {
+                Self::BadRequest(e.to_string())
+            }
+            None => Self::Internal(e),
+        }
+    }
+}
+
+impl From<TitleError> for ApiError {
+    fn from(e: TitleError) -> Self {
+        Self::BadRequest(e.to_string())
+    }
+}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_model_errors() {
+        let status_of = |e: anyhow::Error| ApiError::from(e).status_code();
+        assert_eq!(status_of(TitleError::Empty.into()), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            status_of(NamespaceError::Exists("Test".to_owned()).into()),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            status_of(NamespaceError::Default("renamed").into()),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status_of(anyhow::anyhow!("Unexpected")),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
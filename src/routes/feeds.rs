@@ -0,0 +1,225 @@
+use super::articles::ActorEntity;
+use super::helpers::find_article;
+use crate::diff_summary::DiffSummary;
+use crate::extractors::{DbConnection, Query};
+use crate::feed::{Feed, FeedEntry, FeedFormat};
+use crate::models::{Article, Revision, RevisionKind, Role};
+use actix_web::{error::ErrorInternalServerError, get, web, Error, HttpRequest, HttpResponse};
+use anyhow::Result;
+use chrono::prelude::*;
+use diesel::PgConnection;
+use htmlescape::encode_minimal;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+/// Maximum number of added or removed lines shown in an entry.
+const MAX_DIFF_LINES: usize = 20;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FeedQuery {
+    pub format: FeedFormat,
+    pub limit: Option<i64>,
+    /// Leave out edits made with API tokens.
+    pub hide_bots: bool,
+    /// Only revisions with this tag.
+    pub tag: Option<String>,
+}
+
+impl FeedQuery {
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).max(1).min(MAX_LIMIT)
+    }
+}
+
+fn base_url(req: &HttpRequest) -> String {
+    let conn_info = req.connection_info();
+    format!("{}://{}", conn_info.scheme(), conn_info.host())
+}
+
+fn article_url(base_url: &str, full_title: &str) -> String {
+    format!(
+        "{}/wiki/{}",
+        base_url,
+        utf8_percent_encode(full_title, NON_ALPHANUMERIC)
+    )
+}
+
+fn render_summary(revision: &Revision, diff: &DiffSummary) -> String {
+    let mut summary = format!(
+        "<p>{}: {}</p>",
+        revision.kind.as_str(),
+        encode_minimal(&revision.comment)
+    );
+    if !diff.is_empty() {
+        let lines =
+            diff.removed_lines
+                .iter()
+                .take(MAX_DIFF_LINES)
+                .map(|line| format!(r#"<li class="removed">- {}</li>"#, encode_minimal(line)))
+                .chain(
+                    diff.added_lines.iter().take(MAX_DIFF_LINES).map(|line| {
+                        format!(r#"<li class="added">+ {}</li>"#, encode_minimal(line))
+                    }),
+                )
+                .collect::<Vec<String>>()
+                .join("");
+        summary += &format!(
+            "<p>+{} / -{} lines</p><ul>{}</ul>",
+            diff.added_lines.len(),
+            diff.removed_lines.len(),
+            lines
+        );
+    }
+    summary
+}
+
+fn build_entry(
+    conn: &PgConnection,
+    base_url: &str,
+    article: &Article,
+    revision: &Revision,
+) -> Result<FeedEntry> {
+    let full_title = article.get_full_title(conn)?;
+    let actor = ActorEntity::from_actor(conn, &revision.get_actor(conn)?)?;
+    let diff = match revision.kind {
+        RevisionKind::Create | RevisionKind::Edit => {
+            let old = match revision.get_parent(conn)? {
+                Some(parent) => parent.get_wikitext(conn)?,
+                None => "".to_owned(),
+            };
+            DiffSummary::new(&old, &revision.get_wikitext(conn)?)
+        }
+        _ => DiffSummary::default(),
+    };
+    let link = article_url(base_url, &full_title);
+    Ok(FeedEntry {
+        id: format!("{}?revision={}", link, revision.id),
+        title: full_title,
+        link,
+        updated: revision.created_at,
+        author: actor.name().to_owned(),
+        summary: render_summary(revision, &diff),
+    })
+}
+
+/// Recent changes of all articles readable by anonymous users.
+#[get("/recent-changes/feed")]
+pub async fn get_recent_changes_feed(
+    req: HttpRequest,
+    query: Option<Query<FeedQuery>>,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    let query = query.unwrap_or_default();
+    let base_url = base_url(&req);
+    let revisions =
+        Revision::get_recent(&conn, query.limit(), query.hide_bots, query.tag.as_deref())
+            .map_err(ErrorInternalServerError)?;
+    let anonymous = Role::anonymous();
+    let mut entries = vec![];
+    for revision in revisions.iter() {
+        let article = match Article::find_by_id(&conn, revision.article_id)
+            .map_err(ErrorInternalServerError)?
+        {
+            Some(article) => article,
+            None => continue,
+        };
+        let can_read = anonymous
+            .can_read(&conn, &article)
+            .map_err(ErrorInternalServerError)?;
+        if !can_read {
+            continue;
+        }
+        entries.push(
+            build_entry(&conn, &base_url, &article, revision).map_err(ErrorInternalServerError)?,
+        );
+    }
+    let feed = Feed {
+        title: "Recent changes".to_owned(),
+        description: "Recent changes of all articles".to_owned(),
+        link: format!("{}/wiki/", base_url),
+        self_link: format!("{}{}", base_url, req.uri()),
+        updated: entries
+            .first()
+            .map(|entry| entry.updated)
+            .unwrap_or_else(|| Utc::now().naive_utc()),
+        entries,
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .body(feed.render(query.format)))
+}
+
+/// Revision history of a single article, newest first.
+#[get("/articles/{full_title}/revisions/feed")]
+pub async fn get_article_feed(
+    req: HttpRequest,
+    web::Path((full_title,)): web::Path<(String,)>,
+    query: Option<Query<FeedQuery>>,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    let query = query.unwrap_or_default();
+    let base_url = base_url(&req);
+    let article = find_article(&conn, &full_title)?;
+    // Feed readers never authenticate, so only show what anonymous users can read.
+    let can_read = Role::anonymous()
+        .can_read(&conn, &article)
+        .map_err(ErrorInternalServerError)?;
+    if !can_read {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let full_title = article
+        .get_full_title(&conn)
+        .map_err(ErrorInternalServerError)?;
+    let revisions = article
+        .get_all_revisions(&conn)
+        .map_err(ErrorInternalServerError)?;
+    let entries = revisions
+        .iter()
+        .rev()
+        .take(query.limit() as usize)
+        .map(|revision| build_entry(&conn, &base_url, &article, revision))
+        .collect::<Result<Vec<FeedEntry>>>()
+        .map_err(ErrorInternalServerError)?;
+    let feed = Feed {
+        title: format!("{} - Revision history", full_title),
+        description: format!("Revision history of {}", full_title),
+        link: article_url(&base_url, &full_title),
+        self_link: format!("{}{}", base_url, req.uri()),
+        updated: article.updated_at,
+        entries,
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .body(feed.render(query.format)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::routes::test_helpers::create_article;
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_get_article_feed() {
+        let pool = db::create_test_connection_pool();
+        {
+            let conn = pool.get().expect("must succeed");
+            create_article(&conn, "127.0.0.1", "feedtest", "==AA==\nasdf");
+        }
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(get_article_feed)).await;
+        let req = test::TestRequest::get()
+            .uri("/articles/feedtest/revisions/feed?format=rss")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body = test::read_body(resp).await;
+        let body = std::str::from_utf8(&body).expect("must be utf-8");
+        assert!(body.contains("<title>feedtest</title>"));
+        assert!(body.contains("+ asdf"));
+    }
+}
@@ -0,0 +1,69 @@
+use super::error::ApiError;
+use crate::extractors::UserInfo;
+use crate::models::{AbuseFilter, Article, Namespace, Revision, Right, Role, Scope, User};
+use crate::title::Title;
+use anyhow::Result;
+use diesel::PgConnection;
+
+/// Whether the user is `Root`, with a token allowed to administer.
+pub fn is_root(conn: &PgConnection, user_info: &UserInfo) -> Result<bool> {
+    if !user_info.has_scope(Scope::Admin) {
+        return Ok(false);
+    }
+    User::find_by_id(conn, user_info.id)?.has_any_role(conn, &[Role::root()])
+}
+
+/// `Root` and roles with `right` may do what `right` allows, given a token with `scope`.
+pub fn has_right(
+    conn: &PgConnection,
+    user_info: &UserInfo,
+    scope: Scope,
+    right: Right,
+) -> Result<bool> {
+    if !user_info.has_scope(scope) {
+        return Ok(false);
+    }
+    User::find_by_id(conn, user_info.id)?.has_right(conn, right)
+}
+
+/// Only users whose roles grant `can_grant` on the namespace may manage its permissions and
+/// protect its titles.
+pub fn can_grant(conn: &PgConnection, user_info: &UserInfo, namespace: &Namespace) -> Result<bool> {
+    if !user_info.has_scope(Scope::Admin) {
+        return Ok(false);
+    }
+    User::find_by_id(conn, user_info.id)?.can_grant(conn, namespace)
+}
+
+pub fn find_namespace(conn: &PgConnection, name: &str) -> Result<Namespace, ApiError> {
+    Namespace::find_by_name(conn, name)?
+        .ok_or_else(|| ApiError::NotFound(format!("No namespace found with name: {}", name)))
+}
+
+/// Responds with 400 for an invalid title and 404 when no article has it.
+pub fn find_article(conn: &PgConnection, full_title: &str) -> Result<Article, ApiError> {
+    Title::new(full_title)?;
+    Article::find_by_full_title(conn, full_title)?.ok_or_else(|| {
+        ApiError::NotFound(format!("No article found with full title: {}", full_title))
+    })
+}
+
+pub fn find_role(conn: &PgConnection, role_id: i32) -> Result<Role, ApiError> {
+    Role::find_by_id(conn, role_id)?
+        .ok_or_else(|| ApiError::NotFound(format!("No role found with id: {}", role_id)))
+}
+
+pub fn find_user(conn: &PgConnection, username: &str) -> Result<User, ApiError> {
+    User::find_by_username(conn, username)?
+        .ok_or_else(|| ApiError::NotFound(format!("No user found with username: {}", username)))
+}
+
+pub fn find_revision(conn: &PgConnection, id: i32) -> Result<Revision, ApiError> {
+    Revision::find_by_id(conn, id)?
+        .ok_or_else(|| ApiError::NotFound(format!("No revision found with id: {}", id)))
+}
+
+pub fn find_filter(conn: &PgConnection, id: i32) -> Result<AbuseFilter, ApiError> {
+    AbuseFilter::find_by_id(conn, id)?
+        .ok_or_else(|| ApiError::NotFound(format!("No abuse filter found with id: {}", id)))
+}
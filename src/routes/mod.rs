@@ -1,7 +1,21 @@
 use actix_web::{get, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
+pub mod abuse_filters;
+pub mod api_tokens;
 pub mod articles;
 pub mod auth;
+pub mod blocks;
+pub mod error;
+pub mod feeds;
+pub mod helpers;
+pub mod namespaces;
+pub mod permissions;
+pub mod protections;
+pub mod revisions;
+pub mod roles;
+#[cfg(test)]
+pub(crate) mod test_helpers;
+pub mod users;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Response<T> {
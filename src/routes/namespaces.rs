@@ -0,0 +1,257 @@
+use super::error::ApiError;
+use super::helpers::{find_namespace, is_root};
+use super::Response;
+use crate::extractors::{DbConnection, UserInfo};
+use crate::models::Namespace;
+use actix_web::{
+    delete, error::ErrorInternalServerError, get, patch, post, put, web, Error, HttpResponse,
+};
+use actix_web_validator::Json;
+use anyhow::Result;
+use diesel::PgConnection;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespaceAliasEntity {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespaceEntity {
+    id: i32,
+    name: String,
+    capitalize_first_letter: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject_namespace_id: Option<i32>,
+    aliases: Vec<NamespaceAliasEntity>,
+}
+
+impl NamespaceEntity {
+    fn from_namespace(conn: &PgConnection, namespace: &Namespace) -> Result<Self> {
+        Ok(Self {
+            id: namespace.id,
+            name: namespace.name.clone(),
+            capitalize_first_letter: namespace.capitalize_first_letter,
+            subject_namespace_id: namespace.subject_namespace_id,
+            aliases: namespace
+                .get_aliases(conn)?
+                .into_iter()
+                .map(|alias| NamespaceAliasEntity {
+                    name: alias.name,
+                    language: alias.language,
+                })
+                .collect(),
+        })
+    }
+}
+
+pub type NamespacesGetResponse = Vec<NamespaceEntity>;
+
+fn validate_namespace_name(name: &str) -> Result<(), ValidationError> {
+    if name.contains(':') || name.trim() != name {
+        return Err(ValidationError::new("invalid_namespace_name"));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespaceCreateRequest {
+    #[validate(length(min = 1, max = 30), custom = "validate_namespace_name")]
+    name: String,
+}
+
+pub type NamespaceRenameRequest = NamespaceCreateRequest;
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespaceUpdateRequest {
+    capitalize_first_letter: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespaceAliasCreateRequest {
+    #[validate(length(min = 1, max = 30), custom = "validate_namespace_name")]
+    name: String,
+    #[validate(length(min = 2, max = 20))]
+    language: Option<String>,
+}
+
+#[get("/namespaces")]
+pub async fn get_namespaces(conn: DbConnection) -> Result<HttpResponse, Error> {
+    let data = Namespace::list(&conn)
+        .and_then(|namespaces| {
+            namespaces
+                .iter()
+                .map(|namespace| NamespaceEntity::from_namespace(&conn, namespace))
+                .collect::<Result<NamespacesGetResponse>>()
+        })
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[post("/namespaces")]
+pub async fn create_namespace(
+    user_info: UserInfo,
+    conn: DbConnection,
+    data: Json<NamespaceCreateRequest>,
+) -> Result<HttpResponse, Error> {
+    if !is_root(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let namespace = Namespace::create(&conn, &data.name).map_err(ApiError::from)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: NamespaceEntity::from_namespace(&conn, &namespace)
+            .map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Created().json(resp))
+}
+
+#[put("/namespaces/{name}")]
+pub async fn rename_namespace(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((name,)): web::Path<(String,)>,
+    data: Json<NamespaceRenameRequest>,
+) -> Result<HttpResponse, Error> {
+    if !is_root(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let mut namespace = find_namespace(&conn, &name)?;
+    namespace
+        .rename(&conn, &data.name)
+        .map_err(ApiError::from)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: NamespaceEntity::from_namespace(&conn, &namespace)
+            .map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[patch("/namespaces/{name}")]
+pub async fn update_namespace(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((name,)): web::Path<(String,)>,
+    data: Json<NamespaceUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    if !is_root(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let mut namespace = find_namespace(&conn, &name)?;
+    if let Some(capitalize_first_letter) = data.capitalize_first_letter {
+        namespace
+            .set_capitalize_first_letter(&conn, capitalize_first_letter)
+            .map_err(ErrorInternalServerError)?;
+    }
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: NamespaceEntity::from_namespace(&conn, &namespace)
+            .map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[post("/namespaces/{name}/aliases")]
+pub async fn create_namespace_alias(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((name,)): web::Path<(String,)>,
+    data: Json<NamespaceAliasCreateRequest>,
+) -> Result<HttpResponse, Error> {
+    if !is_root(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let namespace = find_namespace(&conn, &name)?;
+    namespace
+        .add_alias(&conn, &data.name, data.language.as_deref())
+        .map_err(ApiError::from)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: NamespaceEntity::from_namespace(&conn, &namespace)
+            .map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Created().json(resp))
+}
+
+#[delete("/namespaces/{name}/aliases/{alias}")]
+pub async fn delete_namespace_alias(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((name, alias)): web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    if !is_root(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let namespace = find_namespace(&conn, &name)?;
+    if !namespace
+        .remove_alias(&conn, &alias)
+        .map_err(ErrorInternalServerError)?
+    {
+        return Ok(HttpResponse::NotFound().body(format!("No alias found with name: {}", &alias)));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/namespaces/{name}")]
+pub async fn delete_namespace(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((name,)): web::Path<(String,)>,
+) -> Result<HttpResponse, Error> {
+    if !is_root(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let namespace = find_namespace(&conn, &name)?;
+    namespace.delete(&conn).map_err(ApiError::from)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_get_namespaces() {
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(get_namespaces)).await;
+        let req = test::TestRequest::get().uri("/namespaces").to_request();
+        let result: Response<NamespacesGetResponse> = test::read_response_json(&mut app, req).await;
+        assert_eq!(result.status, "OK");
+        assert!(result
+            .data
+            .iter()
+            .any(|namespace| namespace.name == "_DEFAULT"));
+    }
+
+    #[actix_rt::test]
+    async fn test_create_namespace_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(create_namespace)).await;
+        let data = NamespaceCreateRequest {
+            name: "Unauthorized".to_owned(),
+        };
+        let req = test::TestRequest::post()
+            .set_json(&data)
+            .uri("/namespaces")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+}
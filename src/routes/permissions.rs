@@ -0,0 +1,244 @@
+use super::helpers::{can_grant, find_article, find_namespace, find_role, find_user};
+use super::Response;
+use crate::extractors::{DbConnection, Query, UserInfo};
+use crate::models::{
+    Action, Actor, ArticlePermission, ArticlePermissionValues, NamespacePermission,
+    NamespacePermissionChanges, PermissionDecision, PermissionLog, Role,
+};
+use crate::schema::{article_permissions, namespace_permissions};
+use actix_web::{error::ErrorInternalServerError, get, patch, put, web, Error, HttpResponse};
+use anyhow::Result;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PermissionLogsQuery {
+    pub offset: i64,
+    pub limit: i64,
+}
+
+impl Default for PermissionLogsQuery {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            limit: 50,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PermissionExplainQuery {
+    pub username: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectivePermissionEntity {
+    action: Action,
+    allowed: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionExplainResponse {
+    username: String,
+    full_title: String,
+    /// What the user may do, allowed if any of the roles, including `LoggedIn`, allows it.
+    effective: Vec<EffectivePermissionEntity>,
+    /// How each role decides each action.
+    decisions: Vec<PermissionDecision>,
+}
+
+#[get("/namespaces/{name}/permissions")]
+pub async fn get_namespace_permissions(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((name,)): web::Path<(String,)>,
+) -> Result<HttpResponse, Error> {
+    let namespace = find_namespace(&conn, &name)?;
+    if !can_grant(&conn, &user_info, &namespace).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let permissions = NamespacePermission::belonging_to(&namespace)
+        .order(namespace_permissions::role_id.asc())
+        .load::<NamespacePermission>(&*conn)
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: permissions,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[patch("/namespaces/{name}/permissions/{role_id}")]
+pub async fn update_namespace_permission(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((name, role_id)): web::Path<(String, i32)>,
+    data: web::Json<NamespacePermissionChanges>,
+) -> Result<HttpResponse, Error> {
+    let namespace = find_namespace(&conn, &name)?;
+    if !can_grant(&conn, &user_info, &namespace).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let role = find_role(&conn, role_id)?;
+    let actor = Actor::find_or_create_from_user_id(&conn, user_info.id)
+        .map_err(ErrorInternalServerError)?;
+    let permission = NamespacePermission::update(&conn, &namespace, &role, &data, &actor)
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: permission,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[get("/namespaces/{name}/permission-logs")]
+pub async fn get_permission_logs(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((name,)): web::Path<(String,)>,
+    query: Option<Query<PermissionLogsQuery>>,
+) -> Result<HttpResponse, Error> {
+    let query = query.unwrap_or_default();
+    let namespace = find_namespace(&conn, &name)?;
+    if !can_grant(&conn, &user_info, &namespace).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let logs = PermissionLog::find_by_namespace(
+        &conn,
+        &namespace,
+        query.offset.max(0),
+        query.limit.max(1).min(MAX_LIMIT),
+    )
+    .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: logs,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[get("/articles/{full_title}/permissions")]
+pub async fn get_article_permissions(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((full_title,)): web::Path<(String,)>,
+) -> Result<HttpResponse, Error> {
+    let article = find_article(&conn, &full_title)?;
+    let namespace = article
+        .get_namespace(&conn)
+        .map_err(ErrorInternalServerError)?;
+    if !can_grant(&conn, &user_info, &namespace).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let permissions = ArticlePermission::belonging_to(&article)
+        .order(article_permissions::role_id.asc())
+        .load::<ArticlePermission>(&*conn)
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: permissions,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[put("/articles/{full_title}/permissions/{role_id}")]
+pub async fn set_article_permission(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((full_title, role_id)): web::Path<(String, i32)>,
+    data: web::Json<ArticlePermissionValues>,
+) -> Result<HttpResponse, Error> {
+    let article = find_article(&conn, &full_title)?;
+    let namespace = article
+        .get_namespace(&conn)
+        .map_err(ErrorInternalServerError)?;
+    if !can_grant(&conn, &user_info, &namespace).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let role = find_role(&conn, role_id)?;
+    let actor = Actor::find_or_create_from_user_id(&conn, user_info.id)
+        .map_err(ErrorInternalServerError)?;
+    let permission = ArticlePermission::set(&conn, &article, &role, &data, &actor)
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: permission,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+/// Explains the permissions of another user on an article without changing anything.
+#[get("/articles/{full_title}/permissions/explain")]
+pub async fn explain_article_permissions(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((full_title,)): web::Path<(String,)>,
+    query: Query<PermissionExplainQuery>,
+) -> Result<HttpResponse, Error> {
+    let article = find_article(&conn, &full_title)?;
+    let namespace = article
+        .get_namespace(&conn)
+        .map_err(ErrorInternalServerError)?;
+    if !can_grant(&conn, &user_info, &namespace).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let user = find_user(&conn, &query.username)?;
+    let mut roles = user.get_roles(&conn).map_err(ErrorInternalServerError)?;
+    if !roles.contains(&Role::logged_in()) {
+        roles.push(Role::logged_in());
+    }
+    let mut decisions = vec![];
+    for role in roles.iter() {
+        decisions.extend(
+            PermissionDecision::explain(&conn, role, &article).map_err(ErrorInternalServerError)?,
+        );
+    }
+    let permissions = user
+        .get_permissions(&conn, article.namespace_id, Some(article.id))
+        .map_err(ErrorInternalServerError)?;
+    let effective = Action::all()
+        .iter()
+        .map(|action| EffectivePermissionEntity {
+            action: *action,
+            allowed: permissions.get(*action),
+        })
+        .collect();
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: PermissionExplainResponse {
+            username: user.username,
+            full_title: namespace.join(&article.title),
+            effective,
+            decisions,
+        },
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_get_namespace_permissions_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool.clone())
+                .service(get_namespace_permissions),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/namespaces/_DEFAULT/permissions")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+}
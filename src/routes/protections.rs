@@ -0,0 +1,238 @@
+use super::articles::ActorEntity;
+use super::error::ApiError;
+use super::helpers::{can_grant, find_role};
+use super::Response;
+use crate::extractors::{DbConnection, Query, UserInfo};
+use crate::models::{Actor, Article, Namespace, Protection, ProtectionAction};
+use actix_web::{delete, error::ErrorInternalServerError, get, put, web, Error, HttpResponse};
+use actix_web_validator::Json;
+use anyhow::Result;
+use chrono::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProtectedTitlesQuery {
+    pub action: Option<ProtectionAction>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+impl Default for ProtectedTitlesQuery {
+    fn default() -> Self {
+        Self {
+            action: None,
+            offset: 0,
+            limit: 50,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtectionEntity {
+    action: ProtectionAction,
+    allowed_role_id: i32,
+    reason: String,
+    actor: ActorEntity,
+    cascading: bool,
+    /// Full title of the article whose cascading protection protects this title.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cascaded_from: Option<String>,
+    expires_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+impl ProtectionEntity {
+    pub fn from_protection(conn: &PgConnection, protection: &Protection) -> Result<Self> {
+        let cascaded_from = match protection.get_source(conn)? {
+            Some(source) => Some(source.get_full_title(conn)?),
+            None => None,
+        };
+        Ok(Self {
+            action: protection.action,
+            allowed_role_id: protection.allowed_role_id,
+            reason: protection.reason.clone(),
+            actor: ActorEntity::from_actor(conn, &protection.get_actor(conn)?)?,
+            cascading: protection.cascading,
+            cascaded_from,
+            expires_at: protection.expires_at,
+            created_at: protection.created_at,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtectedTitleEntity {
+    full_title: String,
+    #[serde(flatten)]
+    protection: ProtectionEntity,
+}
+
+pub type ProtectedTitlesGetResponse = Vec<ProtectedTitleEntity>;
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtectionRequest {
+    allowed_role_id: i32,
+    #[validate(length(min = 0, max = 1000))]
+    reason: String,
+    /// The protection is lifted at this time.
+    expires_at: Option<NaiveDateTime>,
+    /// Also protect the templates the article transcludes.
+    #[serde(default)]
+    cascade: bool,
+}
+
+#[get("/protected-titles")]
+pub async fn get_protected_titles(
+    conn: DbConnection,
+    query: Option<Query<ProtectedTitlesQuery>>,
+) -> Result<HttpResponse, Error> {
+    let query = query.unwrap_or_default();
+    let data = Protection::list_active(
+        &conn,
+        query.action,
+        query.offset.max(0),
+        query.limit.max(1).min(MAX_LIMIT),
+    )
+    .and_then(|protections| {
+        protections
+            .iter()
+            .map(|protection| {
+                Ok(ProtectedTitleEntity {
+                    full_title: protection.get_full_title(&conn)?,
+                    protection: ProtectionEntity::from_protection(&conn, protection)?,
+                })
+            })
+            .collect::<Result<ProtectedTitlesGetResponse>>()
+    })
+    .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[put("/articles/{full_title}/protections/{action}")]
+pub async fn protect_article(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((full_title, action)): web::Path<(String, ProtectionAction)>,
+    data: Json<ProtectionRequest>,
+) -> Result<HttpResponse, Error> {
+    let (namespace, title) =
+        Namespace::parse_full_title(&conn, &full_title).map_err(ApiError::from)?;
+    if !can_grant(&conn, &user_info, &namespace).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let exists = Article::find(&conn, &namespace, &title)
+        .map_err(ErrorInternalServerError)?
+        .is_some();
+    match (action, exists) {
+        (ProtectionAction::Create, true) => {
+            return Ok(HttpResponse::Conflict()
+                .body(format!("Article {} already exists", namespace.join(&title))));
+        }
+        (ProtectionAction::Edit, false) | (ProtectionAction::Rename, false) => {
+            return Ok(HttpResponse::NotFound()
+                .body(format!("No article found with full title: {}", &full_title)));
+        }
+        _ => {}
+    }
+    if data.cascade && action == ProtectionAction::Create {
+        return Ok(HttpResponse::BadRequest().body("Create protections cannot cascade"));
+    }
+    if matches!(data.expires_at, Some(expires_at) if expires_at <= Utc::now().naive_utc()) {
+        return Ok(HttpResponse::BadRequest().body("Expiry must be in the future"));
+    }
+    let allowed_role = find_role(&conn, data.allowed_role_id)?;
+    let actor = Actor::find_or_create_from_user_id(&conn, user_info.id)
+        .map_err(ErrorInternalServerError)?;
+    let protection = Protection::protect(
+        &conn,
+        &namespace,
+        &title,
+        action,
+        &allowed_role,
+        &data.reason,
+        &actor,
+        data.cascade,
+        data.expires_at,
+    )
+    .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: ProtectionEntity::from_protection(&conn, &protection)
+            .map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[delete("/articles/{full_title}/protections/{action}")]
+pub async fn unprotect_article(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((full_title, action)): web::Path<(String, ProtectionAction)>,
+) -> Result<HttpResponse, Error> {
+    let (namespace, title) =
+        Namespace::parse_full_title(&conn, &full_title).map_err(ApiError::from)?;
+    if !can_grant(&conn, &user_info, &namespace).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if !Protection::unprotect(&conn, &namespace, &title, action)
+        .map_err(ErrorInternalServerError)?
+    {
+        return Ok(HttpResponse::NotFound().body(format!(
+            "{} is not protected against {}",
+            namespace.join(&title),
+            action.as_str()
+        )));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_get_protected_titles() {
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(get_protected_titles)).await;
+        let req = test::TestRequest::get()
+            .uri("/protected-titles?action=edit")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[actix_rt::test]
+    async fn test_protect_article_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(protect_article)).await;
+        let data = ProtectionRequest {
+            allowed_role_id: 1,
+            reason: "Vandalism".to_owned(),
+            expires_at: None,
+            cascade: false,
+        };
+        let req = test::TestRequest::put()
+            .set_json(&data)
+            .uri("/articles/AA/protections/edit")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+}
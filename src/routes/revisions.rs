@@ -0,0 +1,335 @@
+use super::articles::ActorEntity;
+use super::error::ApiError;
+use super::helpers::{find_revision, has_right};
+use super::Response;
+use crate::extractors::{DbConnection, Query, UserInfo};
+use crate::models::{
+    Actor, Article, Namespace, ReadableArticles, Revision, RevisionKind, Right, Scope, User,
+    AUTOMATIC_TAGS,
+};
+use actix_web::{delete, error::ErrorInternalServerError, get, post, web, Error, HttpResponse};
+use actix_web_validator::Json;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UnpatrolledQuery {
+    /// Only revisions of articles in the namespace with this name.
+    pub namespace: Option<String>,
+    /// Only revisions with this tag.
+    pub tag: Option<String>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+impl Default for UnpatrolledQuery {
+    fn default() -> Self {
+        Self {
+            namespace: None,
+            tag: None,
+            offset: 0,
+            limit: 50,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UnpatrolledRevisionEntity {
+    id: i32,
+    full_title: String,
+    created_at: NaiveDateTime,
+    actor: ActorEntity,
+    comment: String,
+    kind: RevisionKind,
+    size_delta: i32,
+    is_bot: bool,
+    tags: Vec<String>,
+}
+
+pub type UnpatrolledRevisionsGetResponse = Vec<UnpatrolledRevisionEntity>;
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionTagCreateRequest {
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+}
+
+/// `Root` and roles with `Right::Patrol` may patrol and tag revisions.
+fn can_patrol(conn: &PgConnection, user_info: &UserInfo) -> Result<bool> {
+    has_right(conn, user_info, Scope::Edit, Right::Patrol)
+}
+
+/// Responds with 400 when `name` is one of the tags only added automatically.
+pub fn check_manual_tag(name: &str) -> Result<(), ApiError> {
+    if AUTOMATIC_TAGS.contains(&name) {
+        Err(ApiError::BadRequest(format!(
+            "{} is an automatic tag",
+            name
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Finds the revision `id`, responding with 403 unless `user` may read its article.
+fn find_readable_revision(conn: &PgConnection, user: &User, id: i32) -> Result<Revision, ApiError> {
+    let revision = find_revision(conn, id)?;
+    let article = Article::find_by_id(conn, revision.article_id)?.ok_or_else(|| {
+        ApiError::NotFound(format!("No article found with id: {}", revision.article_id))
+    })?;
+    if !user.can_read(conn, &article)? {
+        return Err(ApiError::Forbidden(format!("Cannot read revision {}", id)));
+    }
+    Ok(revision)
+}
+
+/// Revisions waiting for review, oldest first.
+#[get("/revisions/unpatrolled")]
+pub async fn get_unpatrolled_revisions(
+    user_info: UserInfo,
+    query: Option<Query<UnpatrolledQuery>>,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    if !can_patrol(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let query = query.unwrap_or_default();
+    let namespace = match &query.namespace {
+        Some(name) => {
+            match Namespace::find_by_name(&conn, name).map_err(ErrorInternalServerError)? {
+                Some(namespace) => Some(namespace),
+                None => {
+                    return Ok(HttpResponse::BadRequest()
+                        .body(format!("No namespace found with name: {}", name)));
+                }
+            }
+        }
+        None => None,
+    };
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    let readable =
+        ReadableArticles::of_user(&conn, Some(&user)).map_err(ErrorInternalServerError)?;
+    let revisions = Revision::find_unpatrolled(
+        &conn,
+        namespace.as_ref(),
+        query.tag.as_deref(),
+        &readable,
+        query.offset.max(0),
+        query.limit.max(1).min(MAX_LIMIT),
+    )
+    .map_err(ErrorInternalServerError)?;
+    let mut data: UnpatrolledRevisionsGetResponse = vec![];
+    for revision in revisions.iter() {
+        let article = match Article::find_by_id(&conn, revision.article_id)
+            .map_err(ErrorInternalServerError)?
+        {
+            Some(article) => article,
+            None => continue,
+        };
+        let actor = revision
+            .get_actor(&conn)
+            .map_err(ErrorInternalServerError)?;
+        data.push(UnpatrolledRevisionEntity {
+            id: revision.id,
+            full_title: article
+                .get_full_title(&conn)
+                .map_err(ErrorInternalServerError)?,
+            created_at: revision.created_at,
+            actor: ActorEntity::from_actor(&conn, &actor).map_err(ErrorInternalServerError)?,
+            comment: revision.comment.clone(),
+            kind: revision.kind,
+            size_delta: revision
+                .get_size_delta(&conn)
+                .map_err(ErrorInternalServerError)?,
+            is_bot: revision.is_bot,
+            tags: revision.get_tags(&conn).map_err(ErrorInternalServerError)?,
+        });
+    }
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[post("/revisions/{id}/patrol")]
+pub async fn patrol_revision(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((id,)): web::Path<(i32,)>,
+) -> Result<HttpResponse, Error> {
+    if !can_patrol(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    let mut revision = find_readable_revision(&conn, &user, id)?;
+    let actor = Actor::find_or_create_from_user_id(&conn, user_info.id)
+        .map_err(ErrorInternalServerError)?;
+    if revision.actor_id == actor.id {
+        return Ok(HttpResponse::Forbidden().body("Cannot patrol your own revision"));
+    }
+    if !revision
+        .patrol(&conn, &actor)
+        .map_err(ErrorInternalServerError)?
+    {
+        return Ok(HttpResponse::Conflict().body(format!("Revision {} is already patrolled", id)));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/revisions/{id}/tags")]
+pub async fn add_revision_tag(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((id,)): web::Path<(i32,)>,
+    data: Json<RevisionTagCreateRequest>,
+) -> Result<HttpResponse, Error> {
+    if !can_patrol(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    check_manual_tag(&data.name)?;
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    let revision = find_readable_revision(&conn, &user, id)?;
+    revision
+        .add_tags(&conn, &[data.name.clone()])
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: revision.get_tags(&conn).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[delete("/revisions/{id}/tags/{name}")]
+pub async fn delete_revision_tag(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((id, name)): web::Path<(i32, String)>,
+) -> Result<HttpResponse, Error> {
+    if !can_patrol(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    check_manual_tag(&name)?;
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    let revision = find_readable_revision(&conn, &user, id)?;
+    if !revision
+        .remove_tag(&conn, &name)
+        .map_err(ErrorInternalServerError)?
+    {
+        return Ok(HttpResponse::NotFound().body(format!("Revision {} is not tagged {}", id, name)));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::routes::test_helpers::{create_article, create_root_access_token};
+    use actix_web::{test, App};
+    use serde_json::json;
+
+    #[actix_rt::test]
+    async fn test_get_unpatrolled_revisions_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool.clone())
+                .service(get_unpatrolled_revisions),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/revisions/unpatrolled?tag=blanking")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[actix_rt::test]
+    async fn test_patrol_revision() {
+        let pool = db::create_test_connection_pool();
+        let (access_token, anonymous_revision, own_revision) = {
+            let conn = pool.get().expect("must succeed");
+            let access_token = create_root_access_token(&conn, "patroller");
+            let mut article = create_article(&conn, "192.0.2.12", "patrol test", "Unreviewed");
+            let anonymous_revision = article.get_latest_revision(&conn).expect("must succeed");
+            let user = User::find_by_username(&conn, "patroller")
+                .expect("must succeed")
+                .expect("must exist");
+            let actor = Actor::find_or_create_from_user_id(&conn, user.id).expect("must succeed");
+            let own_revision = article
+                .edit(&conn, "Reviewed", "", &actor, false)
+                .expect("must succeed");
+            (access_token, anonymous_revision.id, own_revision.id)
+        };
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(patrol_revision)).await;
+        let patrol = |id: i32| {
+            test::TestRequest::post()
+                .header("Authorization", format!("Bearer {}", access_token))
+                .uri(&format!("/revisions/{}/patrol", id))
+                .to_request()
+        };
+        let resp = test::call_service(&mut app, patrol(anonymous_revision)).await;
+        assert_eq!(resp.status().as_u16(), 204);
+        let resp = test::call_service(&mut app, patrol(anonymous_revision)).await;
+        assert_eq!(resp.status().as_u16(), 409);
+        let resp = test::call_service(&mut app, patrol(own_revision)).await;
+        assert_eq!(resp.status().as_u16(), 403);
+    }
+
+    #[actix_rt::test]
+    async fn test_autopatrol() {
+        use crate::routes::articles::create_article as create_article_route;
+
+        let pool = db::create_test_connection_pool();
+        let access_token = {
+            let conn = pool.get().expect("must succeed");
+            create_root_access_token(&conn, "autopatroller")
+        };
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(create_article_route)).await;
+        for (authorization, title) in &[
+            (Some(format!("Bearer {}", access_token)), "autopatrolled"),
+            (None, "not autopatrolled"),
+        ] {
+            let mut req = test::TestRequest::post()
+                .peer_addr("192.0.2.13:22342".parse().unwrap())
+                .set_json(&json!({
+                    "fullTitle": title,
+                    "wikitext": "Content",
+                    "comment": "",
+                }))
+                .uri("/articles");
+            if let Some(authorization) = authorization {
+                req = req.header("Authorization", authorization.as_str());
+            }
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status().as_u16(), 201);
+        }
+        let conn = pool.get().expect("must succeed");
+        let namespace = Namespace::get_default(&conn).expect("must succeed");
+        for (title, patrolled) in &[("autopatrolled", true), ("not autopatrolled", false)] {
+            let revision = Article::find(&conn, &namespace, title)
+                .expect("must succeed")
+                .expect("must exist")
+                .get_latest_revision(&conn)
+                .expect("must succeed");
+            assert_eq!(revision.is_patrolled(), *patrolled);
+        }
+    }
+
+    #[test]
+    fn test_check_manual_tag() {
+        assert!(check_manual_tag("bot").is_err());
+        assert!(check_manual_tag("needs-sources").is_ok());
+    }
+}
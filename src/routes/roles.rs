@@ -0,0 +1,259 @@
+use super::helpers::{find_role, find_user};
+use super::Response;
+use crate::extractors::{DbConnection, UserInfo};
+use crate::models::{Right, Role, Scope, User};
+use actix_web::{
+    delete, error::ErrorInternalServerError, get, post, put, web, Error, HttpResponse,
+};
+use actix_web_validator::Json;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleEntity {
+    id: i32,
+    name: String,
+    rights: Vec<Right>,
+}
+
+impl RoleEntity {
+    fn from_role(conn: &PgConnection, role: &Role) -> Result<Self> {
+        Ok(Self {
+            id: role.id,
+            name: role.name.clone(),
+            rights: role.get_rights(conn)?,
+        })
+    }
+}
+
+pub type RolesGetResponse = Vec<RoleEntity>;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleMemberEntity {
+    username: String,
+    expires_at: Option<NaiveDateTime>,
+}
+
+pub type RoleMembersGetResponse = Vec<RoleMemberEntity>;
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleCreateRequest {
+    #[validate(length(min = 1, max = 50))]
+    name: String,
+    #[serde(default)]
+    rights: Vec<Right>,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleRightsUpdateRequest {
+    rights: Vec<Right>,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRoleAssignRequest {
+    /// Temporary rights end at this time.
+    expires_at: Option<NaiveDateTime>,
+}
+
+/// `Root` and roles with `Right::ManageRoles` may manage roles. Only `Root` may hand out `Root`.
+fn can_manage(conn: &PgConnection, user_info: &UserInfo, role: Option<&Role>) -> Result<bool> {
+    if !user_info.has_scope(Scope::Admin) {
+        return Ok(false);
+    }
+    let user = User::find_by_id(conn, user_info.id)?;
+    if role == Some(&Role::root()) {
+        return user.has_any_role(conn, &[Role::root()]);
+    }
+    user.has_right(conn, Right::ManageRoles)
+}
+
+#[get("/roles")]
+pub async fn get_roles(conn: DbConnection) -> Result<HttpResponse, Error> {
+    let data = Role::list(&conn)
+        .and_then(|roles| {
+            roles
+                .iter()
+                .map(|role| RoleEntity::from_role(&conn, role))
+                .collect::<Result<RolesGetResponse>>()
+        })
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[post("/roles")]
+pub async fn create_role(
+    user_info: UserInfo,
+    conn: DbConnection,
+    data: Json<RoleCreateRequest>,
+) -> Result<HttpResponse, Error> {
+    if !can_manage(&conn, &user_info, None).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if Role::find_by_name(&conn, &data.name)
+        .map_err(ErrorInternalServerError)?
+        .is_some()
+    {
+        return Ok(HttpResponse::Conflict().body(format!("Role {} already exists", &data.name)));
+    }
+    let role = Role::create(&conn, &data.name).map_err(ErrorInternalServerError)?;
+    role.set_rights(&conn, &data.rights)
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: RoleEntity::from_role(&conn, &role).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Created().json(resp))
+}
+
+#[put("/roles/{role_id}/rights")]
+pub async fn update_role_rights(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((role_id,)): web::Path<(i32,)>,
+    data: Json<RoleRightsUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    let role = find_role(&conn, role_id)?;
+    if !can_manage(&conn, &user_info, Some(&role)).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    role.set_rights(&conn, &data.rights)
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: RoleEntity::from_role(&conn, &role).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[delete("/roles/{role_id}")]
+pub async fn delete_role(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((role_id,)): web::Path<(i32,)>,
+) -> Result<HttpResponse, Error> {
+    let role = find_role(&conn, role_id)?;
+    if !can_manage(&conn, &user_info, Some(&role)).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if role.is_builtin() {
+        return Ok(
+            HttpResponse::BadRequest().body(format!("Role {} cannot be deleted", &role.name))
+        );
+    }
+    role.delete(&conn).map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/roles/{role_id}/members")]
+pub async fn get_role_members(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((role_id,)): web::Path<(i32,)>,
+) -> Result<HttpResponse, Error> {
+    if !can_manage(&conn, &user_info, None).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let role = find_role(&conn, role_id)?;
+    let data = role
+        .get_members(&conn)
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(|(user, expires_at)| RoleMemberEntity {
+            username: user.username,
+            expires_at,
+        })
+        .collect::<RoleMembersGetResponse>();
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[put("/users/{username}/roles/{role_id}")]
+pub async fn assign_user_role(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((username, role_id)): web::Path<(String, i32)>,
+    data: Json<UserRoleAssignRequest>,
+) -> Result<HttpResponse, Error> {
+    let role = find_role(&conn, role_id)?;
+    if !can_manage(&conn, &user_info, Some(&role)).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if role == Role::anonymous() {
+        return Ok(HttpResponse::BadRequest().body("Anonymous cannot be assigned to users"));
+    }
+    let user = find_user(&conn, &username)?;
+    user.assign_role(&conn, &role, data.expires_at)
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/users/{username}/roles/{role_id}")]
+pub async fn remove_user_role(
+    user_info: UserInfo,
+    conn: DbConnection,
+    web::Path((username, role_id)): web::Path<(String, i32)>,
+) -> Result<HttpResponse, Error> {
+    let role = find_role(&conn, role_id)?;
+    if !can_manage(&conn, &user_info, Some(&role)).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let user = find_user(&conn, &username)?;
+    if !user
+        .remove_role(&conn, &role)
+        .map_err(ErrorInternalServerError)?
+    {
+        return Ok(HttpResponse::NotFound().body(format!(
+            "User {} does not have role {}",
+            &username, &role.name
+        )));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_get_roles() {
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(App::new().data(pool.clone()).service(get_roles)).await;
+        let req = test::TestRequest::get().uri("/roles").to_request();
+        let result: Response<RolesGetResponse> = test::read_response_json(&mut app, req).await;
+        assert_eq!(result.status, "OK");
+        assert!(result.data.iter().any(|role| role.name == "Root"));
+    }
+
+    #[actix_rt::test]
+    async fn test_create_role_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(App::new().data(pool.clone()).service(create_role)).await;
+        let data = RoleCreateRequest {
+            name: "Unauthorized".to_owned(),
+            rights: vec![],
+        };
+        let req = test::TestRequest::post()
+            .set_json(&data)
+            .uri("/roles")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+}
@@ -0,0 +1,30 @@
+use crate::models::{Actor, Article, Namespace, Role, Session, User};
+use diesel::PgConnection;
+use ipnetwork::IpNetwork;
+use std::str::FromStr;
+
+/// Creates an article in the main namespace as the anonymous actor at `ip`.
+pub fn create_article(conn: &PgConnection, ip: &str, title: &str, wikitext: &str) -> Article {
+    let ip_address = IpNetwork::from_str(ip).expect("must succeed");
+    let actor = Actor::find_or_create_from_ip(conn, &ip_address).expect("must succeed");
+    Article::create(
+        conn,
+        &Namespace::get_default(conn).expect("must succeed"),
+        title,
+        wikitext,
+        "Comment!",
+        &actor,
+        false,
+    )
+    .expect("must succeed")
+}
+
+/// Creates a user with the `Root` role and returns an access token of theirs.
+pub fn create_root_access_token(conn: &PgConnection, username: &str) -> String {
+    let user =
+        User::create(conn, &format!("{}@example.com", username), username).expect("must succeed");
+    user.add_role(conn, &Role::root()).expect("must succeed");
+    let ip_address = IpNetwork::from_str("127.0.0.1").expect("must succeed");
+    let (session, _) = Session::create(conn, &user, None, &ip_address).expect("must succeed");
+    crate::auth::issue_access_token(user.id, session.id).expect("must succeed")
+}
@@ -0,0 +1,486 @@
+use super::helpers::{find_user, is_root};
+use super::Response;
+use crate::client_ip::CLIENT_IP;
+use crate::extractors::{DbConnection, Query, UserInfo};
+use crate::models::{
+    Actor, Article, Namespace, ProfileChanges, ReadableArticles, Revision, RevisionKind, Scope,
+    User, UserPreferences,
+};
+use actix_web::{error::ErrorInternalServerError, get, patch, web, Error, HttpResponse};
+use actix_web_validator::Json;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use validator::{Validate, ValidationError};
+
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ContributionsQuery {
+    pub offset: i64,
+    pub limit: i64,
+    pub namespace: Option<String>,
+}
+
+impl Default for ContributionsQuery {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            limit: 50,
+            namespace: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContributionEntity {
+    id: i32,
+    full_title: String,
+    created_at: NaiveDateTime,
+    comment: String,
+    kind: RevisionKind,
+    size: i32,
+    size_delta: i32,
+    /// Made with an API token.
+    is_bot: bool,
+}
+
+pub type ContributionsGetResponse = Vec<ContributionEntity>;
+
+/// Lists contributions of `actor`, hiding articles the viewer cannot read.
+fn list_contributions(
+    conn: &PgConnection,
+    actors: &[Actor],
+    user_info: &Option<UserInfo>,
+    query: &ContributionsQuery,
+) -> Result<HttpResponse, Error> {
+    if let Some(user_info) = user_info {
+        user_info.require_scope(Scope::Read)?;
+    }
+    let namespace = match &query.namespace {
+        Some(name) => {
+            match Namespace::find_by_name(conn, name).map_err(ErrorInternalServerError)? {
+                Some(namespace) => Some(namespace),
+                None => {
+                    return Ok(HttpResponse::BadRequest()
+                        .body(format!("No namespace found with name: {}", name)));
+                }
+            }
+        }
+        None => None,
+    };
+    let viewer = match user_info {
+        Some(user_info) => {
+            Some(User::find_by_id(conn, user_info.id).map_err(ErrorInternalServerError)?)
+        }
+        None => None,
+    };
+    let readable =
+        ReadableArticles::of_user(conn, viewer.as_ref()).map_err(ErrorInternalServerError)?;
+    let revisions = Revision::find_by_actors(
+        conn,
+        actors,
+        namespace.as_ref(),
+        &readable,
+        query.offset.max(0),
+        query.limit.max(1).min(MAX_LIMIT),
+    )
+    .map_err(ErrorInternalServerError)?;
+    let sizes = Revision::get_sizes(conn, &revisions).map_err(ErrorInternalServerError)?;
+    let mut data: ContributionsGetResponse = vec![];
+    for revision in revisions.iter() {
+        let article = match Article::find_by_id(conn, revision.article_id)
+            .map_err(ErrorInternalServerError)?
+        {
+            Some(article) => article,
+            None => continue,
+        };
+        let size = sizes
+            .get(&revision.id)
+            .ok_or_else(|| ErrorInternalServerError("Cannot find revision size"))?;
+        data.push(ContributionEntity {
+            id: revision.id,
+            full_title: article
+                .get_full_title(conn)
+                .map_err(ErrorInternalServerError)?,
+            created_at: revision.created_at,
+            comment: revision.comment.clone(),
+            kind: revision.kind,
+            size: size.size,
+            size_delta: size.size_delta,
+            is_bot: revision.is_bot,
+        });
+    }
+    let resp = Response {
+        status: "OK".to_owned(),
+        data,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserProfileEntity {
+    username: String,
+    display_name: Option<String>,
+    bio: String,
+    created_at: NaiveDateTime,
+}
+
+impl From<&User> for UserProfileEntity {
+    fn from(user: &User) -> Self {
+        Self {
+            username: user.username.clone(),
+            display_name: user.display_name.clone(),
+            bio: user.bio.clone(),
+            created_at: user.created_at,
+        }
+    }
+}
+
+/// The profile together with what only its user sees.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountEntity {
+    #[serde(flatten)]
+    profile: UserProfileEntity,
+    email: String,
+    email_verified: bool,
+    language: Option<String>,
+    timezone: Option<String>,
+    preferences: UserPreferences,
+    /// Providers the user can log in with.
+    providers: Vec<String>,
+}
+
+impl AccountEntity {
+    fn load(conn: &PgConnection, user: &User) -> Result<Self> {
+        let providers = user
+            .get_authentications(conn)?
+            .into_iter()
+            .map(|auth| auth.provider)
+            .collect();
+        Ok(Self {
+            profile: user.into(),
+            email: user.email.clone(),
+            email_verified: user.is_email_verified(),
+            language: user.language.clone(),
+            timezone: user.timezone.clone(),
+            preferences: user.get_preferences(),
+            providers,
+        })
+    }
+}
+
+pub type UserGetResponse = UserProfileEntity;
+pub type MeGetResponse = AccountEntity;
+
+pub fn validate_username(username: &str) -> Result<(), ValidationError> {
+    if username.contains(|c| c == '@' || c == '/') || username.trim() != username {
+        return Err(ValidationError::new("invalid_username"));
+    }
+    Ok(())
+}
+
+/// Accepts language tags such as `en`, `pt-BR` or `zh-Hant-TW`, or an empty string.
+fn validate_language(language: &str) -> Result<(), ValidationError> {
+    if language.is_empty() {
+        return Ok(());
+    }
+    let mut subtags = language.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    let valid = (2..=8).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    if !valid {
+        return Err(ValidationError::new("invalid_language"));
+    }
+    Ok(())
+}
+
+/// Accepts IANA time zones such as `Europe/Berlin`, or an empty string.
+fn validate_timezone(timezone: &str) -> Result<(), ValidationError> {
+    if !timezone.is_empty() && timezone.parse::<chrono_tz::Tz>().is_err() {
+        return Err(ValidationError::new("invalid_timezone"));
+    }
+    Ok(())
+}
+
+/// Fields left out are kept. Empty strings clear the display name, language and timezone.
+#[derive(Serialize, Deserialize, Validate, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileUpdateRequest {
+    #[validate(length(min = 1, max = 50), custom = "validate_username")]
+    username: Option<String>,
+    #[validate(length(max = 100))]
+    display_name: Option<String>,
+    #[validate(length(max = 5000))]
+    bio: Option<String>,
+    #[validate(length(max = 35), custom = "validate_language")]
+    language: Option<String>,
+    #[validate(length(max = 64), custom = "validate_timezone")]
+    timezone: Option<String>,
+    preferences: Option<UserPreferences>,
+}
+
+fn non_empty(value: &Option<String>) -> Option<Option<String>> {
+    value
+        .as_ref()
+        .map(|v| Some(v.clone()).filter(|v| !v.is_empty()))
+}
+
+fn update_profile(
+    conn: &PgConnection,
+    mut user: User,
+    data: &ProfileUpdateRequest,
+) -> Result<HttpResponse, Error> {
+    if let Some(username) = &data.username {
+        if username != &user.username
+            && User::find_by_username(conn, username)
+                .map_err(ErrorInternalServerError)?
+                .is_some()
+        {
+            return Ok(HttpResponse::Conflict().body(format!("User {} already exists", username)));
+        }
+    }
+    let preferences = match &data.preferences {
+        Some(preferences) => {
+            Some(serde_json::to_value(preferences).map_err(ErrorInternalServerError)?)
+        }
+        None => None,
+    };
+    let changes = ProfileChanges {
+        username: data.username.clone(),
+        display_name: non_empty(&data.display_name),
+        bio: data.bio.clone(),
+        language: non_empty(&data.language),
+        timezone: non_empty(&data.timezone),
+        preferences,
+    };
+    user.update_profile(conn, &changes)
+        .map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: AccountEntity::load(conn, &user).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[get("/me")]
+pub async fn get_me(user_info: UserInfo, conn: DbConnection) -> Result<HttpResponse, Error> {
+    user_info.require_scope(Scope::Read)?;
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: MeGetResponse::load(&conn, &user).map_err(ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[patch("/me")]
+pub async fn update_me(
+    user_info: UserInfo,
+    conn: DbConnection,
+    data: Json<ProfileUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    user_info.require_session()?;
+    let user = User::find_by_id(&conn, user_info.id).map_err(ErrorInternalServerError)?;
+    update_profile(&conn, user, &data)
+}
+
+#[get("/users/{username}")]
+pub async fn get_user(
+    web::Path((username,)): web::Path<(String,)>,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    let user = find_user(&conn, &username)?;
+    let resp = Response {
+        status: "OK".to_owned(),
+        data: UserGetResponse::from(&user),
+    };
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+/// Users may update their own profile, root users any profile.
+#[patch("/users/{username}")]
+pub async fn update_user(
+    web::Path((username,)): web::Path<(String,)>,
+    user_info: UserInfo,
+    conn: DbConnection,
+    data: Json<ProfileUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    let user = find_user(&conn, &username)?;
+    if user.id == user_info.id {
+        user_info.require_session()?;
+    } else if !is_root(&conn, &user_info).map_err(ErrorInternalServerError)? {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    update_profile(&conn, user, &data)
+}
+
+#[get("/users/{username}/contributions")]
+pub async fn get_user_contributions(
+    web::Path((username,)): web::Path<(String,)>,
+    user_info: Option<UserInfo>,
+    query: Option<Query<ContributionsQuery>>,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    let query = query.unwrap_or_default();
+    let user = find_user(&conn, &username)?;
+    let actor = Actor::find_by_user_id(&conn, user.id).map_err(ErrorInternalServerError)?;
+    let actors = actor.into_iter().collect::<Vec<Actor>>();
+    list_contributions(&conn, &actors, &user_info, &query)
+}
+
+#[get("/ip/{address}/contributions")]
+pub async fn get_ip_contributions(
+    web::Path((address,)): web::Path<(String,)>,
+    user_info: Option<UserInfo>,
+    query: Option<Query<ContributionsQuery>>,
+    conn: DbConnection,
+) -> Result<HttpResponse, Error> {
+    let query = query.unwrap_or_default();
+    let ip_address = match IpNetwork::from_str(&address) {
+        Ok(ip_address) => ip_address,
+        Err(_) => {
+            return Ok(HttpResponse::BadRequest().body(format!("Invalid IP address: {}", &address)));
+        }
+    };
+    // With anonymized addresses, the whole network is shown so single addresses cannot be probed
+    let network = CLIENT_IP.lookup_network(ip_address);
+    let actors = Actor::find_in_network(&conn, &network).map_err(ErrorInternalServerError)?;
+    list_contributions(&conn, &actors, &user_info, &query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::routes::test_helpers::create_article;
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_get_user_contributions_non_existing() {
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool.clone())
+                .service(get_user_contributions),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/users/non-existing-user/contributions")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_user_non_existing() {
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(App::new().data(pool.clone()).service(get_user)).await;
+        let req = test::TestRequest::get()
+            .uri("/users/non-existing-user")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[actix_rt::test]
+    async fn test_update_me_unauthorized() {
+        let pool = db::create_connection_pool();
+        let mut app = test::init_service(App::new().data(pool.clone()).service(update_me)).await;
+        let data = ProfileUpdateRequest {
+            bio: Some("Hello".to_owned()),
+            ..Default::default()
+        };
+        let req = test::TestRequest::patch()
+            .set_json(&data)
+            .uri("/me")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[test]
+    fn test_validate_language() {
+        assert!(validate_language("en").is_ok());
+        assert!(validate_language("pt-BR").is_ok());
+        assert!(validate_language("").is_ok());
+        assert!(validate_language("e").is_err());
+        assert!(validate_language("en_US").is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_get_ip_contributions() {
+        let pool = db::create_test_connection_pool();
+        {
+            let conn = pool.get().expect("must succeed");
+            create_article(&conn, "127.0.0.2", "contributions test", "==AA==\nasdf");
+        }
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(get_ip_contributions)).await;
+        let req = test::TestRequest::get()
+            .uri("/ip/127.0.0.2/contributions?limit=10")
+            .to_request();
+        let result: Response<ContributionsGetResponse> =
+            test::read_response_json(&mut app, req).await;
+        assert_eq!(result.status, "OK");
+        assert!(!result.data.is_empty());
+        assert!(result.data.len() <= 10);
+        let created = result
+            .data
+            .iter()
+            .find(|c| c.full_title == "contributions test")
+            .expect("must exist");
+        assert_eq!(created.size, 11);
+        assert_eq!(created.size_delta, 11);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_ip_contributions_hides_unreadable() {
+        use crate::models::{ArticlePermission, ArticlePermissionValues, Role};
+        let pool = db::create_test_connection_pool();
+        {
+            let conn = pool.get().expect("must succeed");
+            create_article(&conn, "127.0.0.3", "readable", "asdf");
+            let hidden = create_article(&conn, "127.0.0.3", "hidden", "asdf");
+            let ip_address = IpNetwork::from_str("127.0.0.3").expect("must succeed");
+            let actor = Actor::find_or_create_from_ip(&conn, &ip_address).expect("must succeed");
+            let values = ArticlePermissionValues {
+                can_read: Some(false),
+                ..Default::default()
+            };
+            ArticlePermission::set(&conn, &hidden, &Role::anonymous(), &values, &actor)
+                .expect("must succeed");
+        }
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(get_ip_contributions)).await;
+        let req = test::TestRequest::get()
+            .uri("/ip/127.0.0.3/contributions?limit=1")
+            .to_request();
+        let result: Response<ContributionsGetResponse> =
+            test::read_response_json(&mut app, req).await;
+        assert_eq!(result.data.len(), 1);
+        assert_eq!(result.data[0].full_title, "readable");
+    }
+
+    #[actix_rt::test]
+    async fn test_get_ip_contributions_invalid_address() {
+        let pool = db::create_connection_pool();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(get_ip_contributions)).await;
+        let req = test::TestRequest::get()
+            .uri("/ip/not-an-ip/contributions")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+}
@@ -1,3 +1,32 @@
+table! {
+    abuse_filter_hits (id) {
+        id -> Int4,
+        filter_id -> Int4,
+        actor_id -> Int4,
+        namespace_id -> Int4,
+        title -> Varchar,
+        action -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    abuse_filters (id) {
+        id -> Int4,
+        name -> Varchar,
+        description -> Text,
+        pattern -> Text,
+        action -> Varchar,
+        tag -> Nullable<Varchar>,
+        throttle_count -> Nullable<Int4>,
+        throttle_period -> Nullable<Int4>,
+        enabled -> Bool,
+        actor_id -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 table! {
     actors (id) {
         id -> Int4,
@@ -6,6 +35,19 @@ table! {
     }
 }
 
+table! {
+    api_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        token_hash -> Varchar,
+        scopes -> Array<Varchar>,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     article_permissions (article_id, role_id) {
         article_id -> Int4,
@@ -45,13 +87,51 @@ table! {
         provider -> Varchar,
         provider_user_id -> Varchar,
         created_at -> Timestamp,
+        password_hash -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    blocks (id) {
+        id -> Int4,
+        user_id -> Nullable<Int4>,
+        ip_address -> Nullable<Cidr>,
+        reason -> Text,
+        actor_id -> Int4,
+        namespace_ids -> Nullable<Array<Int4>>,
+        prevent_account_creation -> Bool,
+        expires_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
     }
 }
 
 table! {
     contents (id) {
         id -> Int4,
-        wikitext -> Text,
+        wikitext -> Nullable<Text>,
+        sha256 -> Varchar,
+        byte_length -> Int4,
+        compression -> Varchar,
+        data -> Nullable<Bytea>,
+        base_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    login_attempts (id) {
+        id -> Int4,
+        username -> Varchar,
+        ip_address -> Cidr,
+        succeeded -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    namespace_aliases (name) {
+        name -> Varchar,
+        namespace_id -> Int4,
+        language -> Nullable<Varchar>,
     }
 }
 
@@ -72,6 +152,56 @@ table! {
     namespaces (id) {
         id -> Int4,
         name -> Varchar,
+        capitalize_first_letter -> Bool,
+        subject_namespace_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    oauth_states (state) {
+        state -> Varchar,
+        provider -> Varchar,
+        code_verifier -> Varchar,
+        created_at -> Timestamp,
+        user_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    permission_logs (id) {
+        id -> Int4,
+        actor_id -> Int4,
+        role_id -> Nullable<Int4>,
+        namespace_id -> Nullable<Int4>,
+        article_id -> Nullable<Int4>,
+        old_value -> Nullable<Jsonb>,
+        new_value -> Nullable<Jsonb>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    protections (id) {
+        id -> Int4,
+        namespace_id -> Int4,
+        title -> Varchar,
+        action -> Varchar,
+        allowed_role_id -> Int4,
+        reason -> Text,
+        actor_id -> Int4,
+        cascading -> Bool,
+        source_id -> Nullable<Int4>,
+        expires_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    rate_limit_counters (key, window_start) {
+        key -> Varchar,
+        window_start -> Timestamp,
+        hits -> Int4,
+        expires_at -> Timestamp,
     }
 }
 
@@ -85,6 +215,13 @@ table! {
     }
 }
 
+table! {
+    revision_tags (revision_id, name) {
+        revision_id -> Int4,
+        name -> Varchar,
+    }
+}
+
 table! {
     revisions (id) {
         id -> Int4,
@@ -93,6 +230,18 @@ table! {
         content_id -> Int4,
         comment -> Text,
         created_at -> Timestamp,
+        kind -> Varchar,
+        payload -> Nullable<Jsonb>,
+        is_bot -> Bool,
+        patrolled_by -> Nullable<Int4>,
+        patrolled_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    role_rights (role_id, name) {
+        role_id -> Int4,
+        name -> Varchar,
     }
 }
 
@@ -103,10 +252,45 @@ table! {
     }
 }
 
+table! {
+    sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token_id -> Varchar,
+        user_agent -> Nullable<Text>,
+        ip_address -> Cidr,
+        created_at -> Timestamp,
+        last_used_at -> Timestamp,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    template_links (article_id, namespace_id, title) {
+        article_id -> Int4,
+        namespace_id -> Int4,
+        title -> Varchar,
+    }
+}
+
 table! {
     user_roles (user_id, role_id) {
         user_id -> Int4,
         role_id -> Int4,
+        expires_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    user_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        purpose -> Varchar,
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
     }
 }
 
@@ -117,37 +301,80 @@ table! {
         email -> Varchar,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        email_verified_at -> Nullable<Timestamp>,
+        display_name -> Nullable<Varchar>,
+        bio -> Text,
+        language -> Nullable<Varchar>,
+        timezone -> Nullable<Varchar>,
+        preferences -> Jsonb,
     }
 }
 
+joinable!(abuse_filter_hits -> abuse_filters (filter_id));
+joinable!(abuse_filter_hits -> actors (actor_id));
+joinable!(abuse_filter_hits -> namespaces (namespace_id));
+joinable!(abuse_filters -> actors (actor_id));
 joinable!(actors -> users (user_id));
+joinable!(api_tokens -> users (user_id));
 joinable!(article_permissions -> articles (article_id));
 joinable!(article_permissions -> roles (role_id));
 joinable!(article_searches -> articles (article_id));
 joinable!(articles -> namespaces (namespace_id));
 joinable!(authentications -> users (user_id));
+joinable!(blocks -> actors (actor_id));
+joinable!(blocks -> users (user_id));
+joinable!(namespace_aliases -> namespaces (namespace_id));
 joinable!(namespace_permissions -> namespaces (namespace_id));
 joinable!(namespace_permissions -> roles (role_id));
+joinable!(oauth_states -> users (user_id));
+joinable!(permission_logs -> actors (actor_id));
+joinable!(permission_logs -> articles (article_id));
+joinable!(permission_logs -> namespaces (namespace_id));
+joinable!(permission_logs -> roles (role_id));
+joinable!(protections -> actors (actor_id));
+joinable!(protections -> namespaces (namespace_id));
+joinable!(protections -> roles (allowed_role_id));
 joinable!(redirections -> articles (target_id));
 joinable!(redirections -> namespaces (namespace_id));
+joinable!(revision_tags -> revisions (revision_id));
 joinable!(revisions -> actors (actor_id));
 joinable!(revisions -> articles (article_id));
 joinable!(revisions -> contents (content_id));
+joinable!(role_rights -> roles (role_id));
+joinable!(sessions -> users (user_id));
+joinable!(template_links -> articles (article_id));
+joinable!(template_links -> namespaces (namespace_id));
 joinable!(user_roles -> roles (role_id));
 joinable!(user_roles -> users (user_id));
+joinable!(user_tokens -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
+    abuse_filter_hits,
+    abuse_filters,
     actors,
+    api_tokens,
     article_permissions,
     article_searches,
     articles,
     authentications,
+    blocks,
     contents,
+    login_attempts,
+    namespace_aliases,
     namespace_permissions,
     namespaces,
+    oauth_states,
+    permission_logs,
+    protections,
+    rate_limit_counters,
     redirections,
+    revision_tags,
     revisions,
+    role_rights,
     roles,
+    sessions,
+    template_links,
     user_roles,
+    user_tokens,
     users,
 );
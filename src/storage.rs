@@ -0,0 +1,155 @@
+//! Encoding of stored wikitext
+//!
+//! A `Content` row keeps its wikitext either as plain text, compressed, or as a delta against the
+//! wikitext of a keyframe row, which may itself be compressed.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Zstd,
+    Deflate,
+}
+
+impl Compression {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+            Compression::Deflate => "deflate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "none" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            "deflate" => Ok(Compression::Deflate),
+            _ => Err(anyhow!("Unknown compression: {}", name)),
+        }
+    }
+
+    /// Reads `CONTENT_COMPRESSION`, defaulting to zstd.
+    pub fn from_env() -> Result<Self> {
+        match env::var("CONTENT_COMPRESSION") {
+            Ok(name) => Self::from_name(&name),
+            Err(_) => Ok(Compression::Zstd),
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Zstd => Ok(zstd::encode_all(data, 0)?),
+            Compression::Deflate => {
+                use flate2::write::DeflateEncoder;
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Zstd => Ok(zstd::decode_all(data)?),
+            Compression::Deflate => {
+                use flate2::write::DeflateDecoder;
+                let mut decoder = DeflateDecoder::new(Vec::new());
+                decoder.write_all(data)?;
+                Ok(decoder.finish()?)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum DeltaOp {
+    /// Copy `len` lines of the base starting at line `start`.
+    #[serde(rename = "c")]
+    Copy { start: usize, len: usize },
+    #[serde(rename = "i")]
+    Insert(String),
+}
+
+/// Encodes `target` as line based operations on `base`.
+pub fn encode_delta(base: &str, target: &str) -> Result<Vec<u8>> {
+    let base_lines = base.split_inclusive('\n').collect::<Vec<&str>>();
+    let target_lines = target.split_inclusive('\n').collect::<Vec<&str>>();
+    let mut ops: Vec<DeltaOp> = vec![];
+    let mut base_index = 0;
+    for line in diff::slice(&base_lines, &target_lines) {
+        match line {
+            diff::Result::Left(_) => base_index += 1,
+            diff::Result::Both(_, _) => {
+                match ops.last_mut() {
+                    Some(DeltaOp::Copy { start, len }) if *start + *len == base_index => *len += 1,
+                    _ => ops.push(DeltaOp::Copy {
+                        start: base_index,
+                        len: 1,
+                    }),
+                }
+                base_index += 1;
+            }
+            diff::Result::Right(added) => match ops.last_mut() {
+                Some(DeltaOp::Insert(text)) => text.push_str(added),
+                _ => ops.push(DeltaOp::Insert((*added).to_owned())),
+            },
+        }
+    }
+    Ok(serde_json::to_vec(&ops)?)
+}
+
+/// Reconstructs the target text of `encode_delta`.
+pub fn apply_delta(base: &str, delta: &[u8]) -> Result<String> {
+    let base_lines = base.split_inclusive('\n').collect::<Vec<&str>>();
+    let ops = serde_json::from_slice::<Vec<DeltaOp>>(delta)?;
+    let mut target = String::new();
+    for op in ops.iter() {
+        match op {
+            DeltaOp::Copy { start, len } => {
+                let lines = base_lines
+                    .get(*start..*start + *len)
+                    .ok_or_else(|| anyhow!("Delta does not match its base"))?;
+                for line in lines {
+                    target.push_str(line);
+                }
+            }
+            DeltaOp::Insert(text) => target.push_str(text),
+        }
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_round_trip() {
+        let text = "==Heading==\nSome text, some text, some text.\n".repeat(100);
+        for compression in [Compression::None, Compression::Zstd, Compression::Deflate].iter() {
+            let compressed = compression.compress(text.as_bytes()).expect("must succeed");
+            let decompressed = compression.decompress(&compressed).expect("must succeed");
+            assert_eq!(decompressed, text.as_bytes());
+        }
+        let compressed = Compression::Zstd
+            .compress(text.as_bytes())
+            .expect("must succeed");
+        assert!(compressed.len() < text.len());
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let base = "a\nb\nc\nd";
+        for target in ["a\nb\nc\nd", "a\nc\nd\ne", "x\na\nb\nc\nd\n", "", "d"].iter() {
+            let delta = encode_delta(base, target).expect("must succeed");
+            assert_eq!(apply_delta(base, &delta).expect("must succeed"), *target);
+        }
+    }
+}
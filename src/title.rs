@@ -0,0 +1,131 @@
+use std::fmt;
+use std::ops::Deref;
+use unicode_normalization::UnicodeNormalization;
+
+/// Maximum length of a full title in characters.
+pub const MAX_LENGTH: usize = 300;
+
+/// Characters which cannot appear in a title since wikitext or URLs give them a meaning.
+const FORBIDDEN_CHARACTERS: &[char] = &['#', '<', '>', '[', ']', '|', '{', '}'];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TitleError {
+    Empty,
+    TooLong,
+    LeadingColon,
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for TitleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Title is empty"),
+            Self::TooLong => write!(f, "Title is longer than {} characters", MAX_LENGTH),
+            Self::LeadingColon => write!(f, "Title cannot start with a colon"),
+            Self::InvalidCharacter(c) => {
+                write!(f, "Title cannot contain the character {:?}", c)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TitleError {}
+
+/// A normalized and validated full title.
+///
+/// Underscores are read as spaces, whitespace is collapsed into a single space and the text is
+/// put in Unicode NFC, so that every spelling of a title refers to the same article.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Title(String);
+
+impl Title {
+    pub fn new(title: &str) -> Result<Self, TitleError> {
+        let normalized = title
+            .nfc()
+            .map(|c| if c == '_' { ' ' } else { c })
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        if normalized.is_empty() {
+            return Err(TitleError::Empty);
+        }
+        if normalized.chars().count() > MAX_LENGTH {
+            return Err(TitleError::TooLong);
+        }
+        if normalized.starts_with(':') {
+            return Err(TitleError::LeadingColon);
+        }
+        if let Some(c) = normalized
+            .chars()
+            .find(|c| c.is_control() || FORBIDDEN_CHARACTERS.contains(c))
+        {
+            return Err(TitleError::InvalidCharacter(c));
+        }
+        Ok(Self(normalized))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl Deref for Title {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Title {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// For `#[validate(custom = "...")]` on request fields holding a full title.
+pub fn validate_title(title: &str) -> Result<(), validator::ValidationError> {
+    Title::new(title)
+        .map(|_| ())
+        .map_err(|_| validator::ValidationError::new("invalid_title"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let title = Title::new("  Foo__bar \t baz ").expect("must succeed");
+        assert_eq!(title.as_str(), "Foo bar baz");
+        let decomposed = Title::new("Cafe\u{301}").expect("must succeed");
+        assert_eq!(decomposed, Title::new("Caf\u{e9}").expect("must succeed"));
+        let namespaced = Title::new("Talk:_Foo").expect("must succeed");
+        assert_eq!(namespaced.as_str(), "Talk: Foo");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Title::new(" _ "), Err(TitleError::Empty));
+        assert_eq!(Title::new(":Foo"), Err(TitleError::LeadingColon));
+        assert_eq!(
+            Title::new("Foo#bar"),
+            Err(TitleError::InvalidCharacter('#'))
+        );
+        assert_eq!(
+            Title::new("Foo{{bar}}"),
+            Err(TitleError::InvalidCharacter('{'))
+        );
+        assert_eq!(
+            Title::new("Foo\u{7}"),
+            Err(TitleError::InvalidCharacter('\u{7}'))
+        );
+        assert_eq!(Title::new(&"a".repeat(301)), Err(TitleError::TooLong));
+        assert!(Title::new(&"a".repeat(300)).is_ok());
+    }
+}